@@ -26,7 +26,10 @@ pub struct CommonSection {
 
 #[derive(Debug, Deserialize)]
 pub struct AuthSection {
+    pub allowed_builds: Vec<u16>,
     pub network: NetworkSection,
+    pub lockout: LockoutSection,
+    pub realms: RealmsSection,
 }
 
 #[derive(Debug, Deserialize)]
@@ -39,3 +42,15 @@ pub struct NetworkSection {
     pub host: String,
     pub port: String,
 }
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct LockoutSection {
+    pub max_failed_attempts_per_account: u32,
+    pub max_failed_attempts_per_ip: u32,
+    pub lockout_duration_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RealmsSection {
+    pub heartbeat_timeout_secs: u64,
+}
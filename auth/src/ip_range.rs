@@ -0,0 +1,142 @@
+use std::{fmt::Display, net::IpAddr, str::FromStr};
+
+// An IP address range in CIDR notation (e.g. 192.168.0.0/16), a plain IP address being a range
+// with the maximum prefix length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+#[derive(Debug)]
+pub struct InvalidIpRange;
+
+impl Display for InvalidIpRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid IP address or CIDR range")
+    }
+}
+
+impl std::error::Error for InvalidIpRange {}
+
+impl IpRange {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        let ip = ip.to_canonical();
+
+        ip.is_ipv4() == self.network.is_ipv4() && Self::masked(&ip, self.prefix_len) == self.network
+    }
+
+    fn masked(ip: &IpAddr, prefix_len: u8) -> IpAddr {
+        match ip {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+                IpAddr::V4((u32::from(*ip) & mask).into())
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+                IpAddr::V6((u128::from(*ip) & mask).into())
+            }
+        }
+    }
+
+    fn max_prefix_len(ip: &IpAddr) -> u8 {
+        match ip {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = InvalidIpRange;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (network, prefix_len) = match s.split_once('/') {
+            Some((network, prefix_len)) => (
+                network.parse::<IpAddr>().map_err(|_| InvalidIpRange)?,
+                Some(prefix_len.parse::<u8>().map_err(|_| InvalidIpRange)?),
+            ),
+            None => (s.parse::<IpAddr>().map_err(|_| InvalidIpRange)?, None),
+        };
+        let network = network.to_canonical();
+
+        let max_prefix_len = Self::max_prefix_len(&network);
+        let prefix_len = prefix_len.unwrap_or(max_prefix_len);
+        if prefix_len > max_prefix_len {
+            return Err(InvalidIpRange);
+        }
+
+        Ok(Self {
+            network: Self::masked(&network, prefix_len),
+            prefix_len,
+        })
+    }
+}
+
+impl Display for IpRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.prefix_len == Self::max_prefix_len(&self.network) {
+            write!(f, "{}", self.network)
+        } else {
+            write!(f, "{}/{}", self.network, self.prefix_len)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_single_address() {
+        let range: IpRange = "192.168.1.10".parse().unwrap();
+
+        assert_eq!(range.to_string(), "192.168.1.10");
+        assert!(range.contains(&ip("192.168.1.10")));
+        assert!(!range.contains(&ip("192.168.1.11")));
+    }
+
+    #[test]
+    fn test_parse_cidr_masks_the_network() {
+        let range: IpRange = "10.1.2.3/8".parse().unwrap();
+
+        assert_eq!(range.to_string(), "10.0.0.0/8");
+        assert!(range.contains(&ip("10.255.0.1")));
+        assert!(!range.contains(&ip("11.0.0.1")));
+    }
+
+    #[test]
+    fn test_zero_prefix_matches_every_address_of_the_family() {
+        let range: IpRange = "0.0.0.0/0".parse().unwrap();
+
+        assert!(range.contains(&ip("1.2.3.4")));
+        assert!(!range.contains(&ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn test_ipv6_range() {
+        let range: IpRange = "2001:db8::/32".parse().unwrap();
+
+        assert!(range.contains(&ip("2001:db8:1234::1")));
+        assert!(!range.contains(&ip("2001:db9::1")));
+    }
+
+    #[test]
+    fn test_ipv4_mapped_addresses_match_ipv4_ranges() {
+        let range: IpRange = "192.168.0.0/16".parse().unwrap();
+
+        assert!(range.contains(&ip("::ffff:192.168.3.4")));
+    }
+
+    #[test]
+    fn test_invalid_ranges() {
+        assert!("192.168.0.0/33".parse::<IpRange>().is_err());
+        assert!("2001:db8::/129".parse::<IpRange>().is_err());
+        assert!("192.168.0.0/".parse::<IpRange>().is_err());
+        assert!("not an ip".parse::<IpRange>().is_err());
+    }
+}
@@ -1,38 +1,92 @@
 use std::sync::Arc;
 
+use crate::config::AuthConfig;
+pub use crate::packets::AuthResult;
 use crate::packets::{
     CmdAuthLogonChallengeClient, CmdAuthLogonChallengeServer, CmdAuthLogonProofClient,
-    CmdAuthLogonProofServer, CmdRealmListClient, CmdRealmListServer,
+    CmdAuthLogonProofServer, CmdAuthLogonProofServerFailure, CmdAuthReconnectChallengeServer,
+    CmdAuthReconnectProofClient, CmdAuthReconnectProofServer, CmdRealmListClient,
+    CmdRealmListServer, Opcode, PinChallenge,
 };
-pub use crate::packets::{Realm, RealmType};
 use binrw::io::Cursor;
-use binrw::{BinReaderExt, BinWriterExt};
+use binrw::{BinRead, BinReaderExt, BinWriterExt};
 
 use hex::ToHex;
-use log::{error, trace};
+use log::{error, info, trace, warn};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
+use repositories::{
+    account::{AccountError, AccountRepository},
+    failed_login::FailedLoginRepository,
+    realm::RealmRepository,
+};
 use rusqlite::Connection;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
-use wow_srp::server::SrpProof;
+use wow_srp::server::{SrpProof, SrpVerifier};
 
 pub mod config;
+pub mod ip_range;
+pub mod logon_checks;
 mod packets;
+pub mod totp;
+pub mod repositories {
+    pub mod account;
+    pub mod ban;
+    pub mod failed_login;
+    pub mod realm;
+
+    pub(crate) fn now_timestamp() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+}
+
+// No client packet comes close to this size
+const MAX_PACKET_SIZE: usize = 1024;
 
 // TypeState pattern (https://yoric.github.io/post/rust-typestate/)
 struct SocketOpened;
 struct ServerSentLogonChallenge {
     proof: SrpProof,
     account_name: String,
+    build: u16,
+    two_factor: Option<TwoFactorChallenge>,
+}
+struct TwoFactorChallenge {
+    pin_challenge: PinChallenge,
+    totp_secret: Vec<u8>,
+}
+struct ServerSentReconnectChallenge {
+    challenge_data: [u8; 16],
+    session_key: [u8; 40],
+    account_name: String,
+    build: u16,
+}
+struct ClientAuthenticated {
+    account_id: u32,
 }
-struct ClientAuthenticated;
 
 struct AuthState<S> {
     socket: TcpStream,
+    read_buffer: Vec<u8>, // Received bytes not consumed by a packet yet
     state: S,
 }
 
+// A client either logs in with its password or reconnects with the session key of its
+// previous logon
+enum ChallengeSent {
+    Logon(AuthState<ServerSentLogonChallenge>),
+    Reconnect(AuthState<ServerSentReconnectChallenge>),
+}
+
+enum Credentials {
+    Verifier(SrpVerifier),
+    SessionKey([u8; 40]),
+}
+
 // Error types to be moved to another file
 #[derive(Debug)]
 pub enum AuthError {
@@ -40,6 +94,9 @@ pub enum AuthError {
     SocketError(std::io::Error),
     BinRwError(binrw::Error),
     DbError(r2d2::Error),
+    QueryError(rusqlite::Error),
+    LogonFailed(AuthResult),
+    PacketTooLarge,
 }
 
 impl From<std::io::Error> for AuthError {
@@ -60,33 +117,139 @@ impl From<r2d2::Error> for AuthError {
     }
 }
 
+impl From<rusqlite::Error> for AuthError {
+    fn from(value: rusqlite::Error) -> Self {
+        Self::QueryError(value)
+    }
+}
+
 impl<S> AuthState<S> {
-    async fn read_socket(&mut self) -> Result<[u8; 1024], AuthError> {
-        let mut buf = [0_u8; 1024];
-        match self.socket.read(&mut buf).await {
-            Ok(0) => {
-                trace!("Client disconnected");
-                Err(AuthError::ClientDisconnected)
+    // Reads from the socket until a whole packet is buffered, any following bytes are kept for
+    // the next call
+    async fn read_packet<T>(&mut self) -> Result<T, AuthError>
+    where
+        T: for<'a> BinRead<Args<'a> = ()>,
+    {
+        loop {
+            if !self.read_buffer.is_empty() {
+                let mut reader = Cursor::new(self.read_buffer.as_slice());
+                match reader.read_le::<T>() {
+                    Ok(packet) => {
+                        let consumed = reader.position() as usize;
+                        self.read_buffer.drain(..consumed);
+                        return Ok(packet);
+                    }
+                    Err(e) if e.is_eof() => (), // Incomplete packet
+                    Err(e) => {
+                        warn!("Received a malformed packet, closing");
+                        return Err(AuthError::BinRwError(e));
+                    }
+                }
             }
-            Ok(_) => Ok(buf),
-            Err(e) => {
-                error!("Socket error, closing");
-                Err(AuthError::SocketError(e))
+
+            if self.read_buffer.len() >= MAX_PACKET_SIZE {
+                warn!("Received an oversized packet, closing");
+                return Err(AuthError::PacketTooLarge);
+            }
+
+            let mut buf = [0_u8; MAX_PACKET_SIZE];
+            let max_read = MAX_PACKET_SIZE - self.read_buffer.len();
+            match self.socket.read(&mut buf[..max_read]).await {
+                Ok(0) => {
+                    trace!("Client disconnected");
+                    return Err(AuthError::ClientDisconnected);
+                }
+                Ok(n) => self.read_buffer.extend_from_slice(&buf[..n]),
+                Err(e) => {
+                    error!("Socket error, closing");
+                    return Err(AuthError::SocketError(e));
+                }
             }
         }
     }
 }
 
 impl AuthState<SocketOpened> {
-    async fn handle_challenge(mut self) -> Result<AuthState<ServerSentLogonChallenge>, AuthError> {
-        let buf = self.read_socket().await?;
-
-        let mut reader = Cursor::new(buf);
-        let cmd_auth_logon_challenge_client: CmdAuthLogonChallengeClient = reader.read_le()?;
+    async fn handle_challenge(
+        mut self,
+        conn: &Connection,
+        config: &AuthConfig,
+    ) -> Result<ChallengeSent, AuthError> {
+        let cmd_auth_logon_challenge_client: CmdAuthLogonChallengeClient =
+            self.read_packet().await?;
         trace!("Received {:?}", cmd_auth_logon_challenge_client);
 
-        let (cmd_auth_logon_challenge_server, proof) =
-            CmdAuthLogonChallengeServer::new(&cmd_auth_logon_challenge_client.account_name);
+        let opcode = cmd_auth_logon_challenge_client.opcode;
+        let account_name = cmd_auth_logon_challenge_client.account_name.to_string();
+        let build = cmd_auth_logon_challenge_client.build;
+        let client_ip = self.socket.peer_addr()?.ip();
+
+        if !config.auth.allowed_builds.contains(&build) {
+            info!(
+                "rejected logon from {} with unsupported client build {}",
+                client_ip, build
+            );
+            return self
+                .reject_challenge(opcode, AuthResult::FailVersionInvalid)
+                .await;
+        }
+
+        let lockout = &config.auth.lockout;
+        if let Some(result) = logon_checks::ip_rejection(conn, lockout, &client_ip)? {
+            return self.reject_challenge(opcode, result).await;
+        }
+
+        let credentials = match opcode {
+            Opcode::CmdAuthReconnectChallenge => {
+                AccountRepository::fetch_session_key(conn, &account_name)?
+                    .map(Credentials::SessionKey)
+            }
+            _ => AccountRepository::fetch_verifier(conn, &account_name)?.map(Credentials::Verifier),
+        };
+        let Some(credentials) = credentials else {
+            // Guessing account names counts towards the IP lockout
+            if opcode == Opcode::CmdAuthLogonChallenge {
+                FailedLoginRepository::record(conn, &account_name, &client_ip)?;
+            }
+            return self
+                .reject_challenge(opcode, AuthResult::FailUnknownAccount)
+                .await;
+        };
+
+        if let Some(result) = logon_checks::account_rejection(conn, lockout, &account_name)? {
+            return self.reject_challenge(opcode, result).await;
+        }
+
+        match credentials {
+            Credentials::Verifier(verifier) => {
+                let totp_secret = AccountRepository::fetch_totp_secret(conn, &account_name)?;
+                self.send_logon_challenge(verifier, totp_secret, account_name, build)
+                    .await
+                    .map(ChallengeSent::Logon)
+            }
+            Credentials::SessionKey(session_key) => self
+                .send_reconnect_challenge(session_key, account_name, build)
+                .await
+                .map(ChallengeSent::Reconnect),
+        }
+    }
+
+    // Accounts with a TOTP secret must also enter their current code with the PIN keypad
+    async fn send_logon_challenge(
+        mut self,
+        verifier: SrpVerifier,
+        totp_secret: Option<Vec<u8>>,
+        account_name: String,
+        build: u16,
+    ) -> Result<AuthState<ServerSentLogonChallenge>, AuthError> {
+        let two_factor = totp_secret.map(|totp_secret| TwoFactorChallenge {
+            pin_challenge: PinChallenge::generate(),
+            totp_secret,
+        });
+        let (cmd_auth_logon_challenge_server, proof) = CmdAuthLogonChallengeServer::new(
+            verifier,
+            two_factor.as_ref().map(|t| t.pin_challenge),
+        );
 
         let mut writer = Cursor::new(Vec::new());
         writer.write_le(&cmd_auth_logon_challenge_server)?;
@@ -95,36 +258,123 @@ impl AuthState<SocketOpened> {
 
         let new_state = AuthState {
             socket: self.socket,
+            read_buffer: self.read_buffer,
             state: ServerSentLogonChallenge {
                 proof,
-                account_name: cmd_auth_logon_challenge_client.account_name.to_string(),
+                account_name,
+                build,
+                two_factor,
             },
         };
         Ok(new_state)
     }
+
+    async fn send_reconnect_challenge(
+        mut self,
+        session_key: [u8; 40],
+        account_name: String,
+        build: u16,
+    ) -> Result<AuthState<ServerSentReconnectChallenge>, AuthError> {
+        let challenge_data: [u8; 16] = rand::random();
+
+        let mut writer = Cursor::new(Vec::new());
+        writer.write_le(&CmdAuthReconnectChallengeServer::new(challenge_data))?;
+        self.socket.write_all(writer.get_ref()).await?;
+        trace!("Sent auth reconnect challenge (server)");
+
+        let new_state = AuthState {
+            socket: self.socket,
+            read_buffer: self.read_buffer,
+            state: ServerSentReconnectChallenge {
+                challenge_data,
+                session_key,
+                account_name,
+                build,
+            },
+        };
+        Ok(new_state)
+    }
+
+    async fn reject_challenge<T>(
+        mut self,
+        opcode: Opcode,
+        result: AuthResult,
+    ) -> Result<T, AuthError> {
+        let mut writer = Cursor::new(Vec::new());
+        match opcode {
+            Opcode::CmdAuthReconnectChallenge => {
+                writer.write_le(&CmdAuthReconnectChallengeServer::failure(result))?
+            }
+            _ => writer.write_le(&CmdAuthLogonChallengeServer::failure(result))?,
+        }
+        self.socket.write_all(writer.get_ref()).await?;
+        trace!("Sent auth logon challenge failure (server): {:?}", result);
+
+        Err(AuthError::LogonFailed(result))
+    }
 }
 
 impl AuthState<ServerSentLogonChallenge> {
     async fn handle_proof(
         mut self,
-        conn: &mut Connection,
+        conn: &Connection,
     ) -> Result<AuthState<ClientAuthenticated>, AuthError> {
-        let buf = self.read_socket().await?;
-
-        let mut reader = Cursor::new(buf);
-        let cmd_auth_logon_proof_client: CmdAuthLogonProofClient = reader.read_le()?;
+        let cmd_auth_logon_proof_client: CmdAuthLogonProofClient = self.read_packet().await?;
         trace!("Received {:?}", cmd_auth_logon_proof_client);
 
-        let (cmd_auth_logon_proof_server, server_proof) =
-            CmdAuthLogonProofServer::new(cmd_auth_logon_proof_client, self.state.proof);
+        let pin_valid = match &self.state.two_factor {
+            None => true,
+            Some(two_factor) => {
+                cmd_auth_logon_proof_client
+                    .pin_proof
+                    .as_ref()
+                    .is_some_and(|pin_proof| {
+                        totp::valid_codes(&two_factor.totp_secret, repositories::now_timestamp())
+                            .iter()
+                            .any(|code| two_factor.pin_challenge.is_valid(pin_proof, code))
+                    })
+            }
+        };
+
+        let Some((cmd_auth_logon_proof_server, server_proof)) =
+            CmdAuthLogonProofServer::new(cmd_auth_logon_proof_client, self.state.proof)
+                .filter(|_| pin_valid)
+        else {
+            let client_ip = self.socket.peer_addr()?.ip();
+            warn!(
+                "failed logon (wrong password or PIN) for account {} from {}",
+                self.state.account_name, client_ip
+            );
+            FailedLoginRepository::record(conn, &self.state.account_name, &client_ip)?;
+
+            // TBC clients display "incorrect password" for this result in a proof response
+            let result = AuthResult::FailUnknownAccount;
+            let mut writer = Cursor::new(Vec::new());
+            writer.write_le(&CmdAuthLogonProofServerFailure::new(result))?;
+            self.socket.write_all(writer.get_ref()).await?;
+            trace!("Sent auth logon proof failure (server)");
+
+            return Err(AuthError::LogonFailed(result));
+        };
+
+        // The account could have been deleted since the challenge
+        let Some(account_id) = AccountRepository::fetch_id(conn, &self.state.account_name)? else {
+            return Err(AuthError::LogonFailed(AuthResult::FailUnknownAccount));
+        };
+
+        FailedLoginRepository::clear_for_account(conn, &self.state.account_name)?;
 
         // Save the session key to the database
-        save_session_key(
+        AccountRepository::save_session_key(
             conn,
-            self.state.account_name,
+            &self.state.account_name,
             server_proof.session_key().encode_hex::<String>(),
         )
-        .unwrap();
+        .map_err(|e| match e {
+            AccountError::DbError(e) => AuthError::QueryError(e),
+            _ => AuthError::LogonFailed(AuthResult::FailUnknownAccount),
+        })?;
+        AccountRepository::save_last_build(conn, &self.state.account_name, self.state.build)?;
 
         let mut writer = Cursor::new(Vec::new());
         writer.write_le(&cmd_auth_logon_proof_server)?;
@@ -133,26 +383,85 @@ impl AuthState<ServerSentLogonChallenge> {
 
         let new_state = AuthState {
             socket: self.socket,
-            state: ClientAuthenticated,
+            read_buffer: self.read_buffer,
+            state: ClientAuthenticated { account_id },
         };
         Ok(new_state)
     }
 }
 
-impl AuthState<ClientAuthenticated> {
-    async fn handle_realm_list(&mut self, realms: &Arc<Vec<Realm>>) -> Result<(), AuthError> {
-        let buf = self.read_socket().await?;
+impl AuthState<ServerSentReconnectChallenge> {
+    async fn handle_reconnect_proof(
+        mut self,
+        conn: &Connection,
+    ) -> Result<AuthState<ClientAuthenticated>, AuthError> {
+        let cmd_auth_reconnect_proof_client: CmdAuthReconnectProofClient =
+            self.read_packet().await?;
+        trace!("Received {:?}", cmd_auth_reconnect_proof_client);
+
+        let is_valid = cmd_auth_reconnect_proof_client.is_valid(
+            &self.state.account_name,
+            &self.state.challenge_data,
+            &self.state.session_key,
+        );
+        let account_id = AccountRepository::fetch_id(conn, &self.state.account_name)?;
+
+        let result = match account_id {
+            Some(_) if is_valid => AuthResult::Success,
+            _ => {
+                let client_ip = self.socket.peer_addr()?.ip();
+                warn!(
+                    "failed reconnection for account {} from {}",
+                    self.state.account_name, client_ip
+                );
+                FailedLoginRepository::record(conn, &self.state.account_name, &client_ip)?;
 
-        let mut reader = Cursor::new(buf);
-        let cmd_realm_list_client: CmdRealmListClient = reader.read_le()?;
+                AuthResult::FailUnknownAccount
+            }
+        };
+
+        let mut writer = Cursor::new(Vec::new());
+        writer.write_le(&CmdAuthReconnectProofServer::new(result))?;
+        self.socket.write_all(writer.get_ref()).await?;
+        trace!("Sent auth reconnect proof (server): {:?}", result);
+
+        let Some(account_id) = account_id.filter(|_| is_valid) else {
+            return Err(AuthError::LogonFailed(result));
+        };
+
+        FailedLoginRepository::clear_for_account(conn, &self.state.account_name)?;
+        AccountRepository::save_last_build(conn, &self.state.account_name, self.state.build)?;
+
+        let new_state = AuthState {
+            socket: self.socket,
+            read_buffer: self.read_buffer,
+            state: ClientAuthenticated { account_id },
+        };
+        Ok(new_state)
+    }
+}
+
+impl AuthState<ClientAuthenticated> {
+    async fn handle_realm_list(
+        &mut self,
+        conn: &Connection,
+        config: &AuthConfig,
+    ) -> Result<(), AuthError> {
+        let cmd_realm_list_client: CmdRealmListClient = self.read_packet().await?;
         trace!("Received {:?}", cmd_realm_list_client);
 
+        let realms = RealmRepository::fetch_realms(
+            conn,
+            self.state.account_id,
+            config.auth.realms.heartbeat_timeout_secs,
+        )?;
+
         let cmd_realm_list_server = CmdRealmListServer {
             opcode: packets::Opcode::CmdRealmList,
             size: 8 + realms.iter().fold(0, |acc, r| acc + r.size()),
             padding: 0,
             num_realms: realms.len().try_into().unwrap(),
-            realms,
+            realms: &realms,
             padding_footer: 0,
         };
 
@@ -168,32 +477,26 @@ impl AuthState<ClientAuthenticated> {
 // TODO: Improve with https://docs.rs/wow_srp/latest/wow_srp/server/index.html
 pub async fn process(
     socket: TcpStream,
-    realms: Arc<Vec<Realm>>,
     db_pool: Arc<Pool<SqliteConnectionManager>>,
+    config: Arc<AuthConfig>,
 ) -> Result<(), AuthError> {
-    let mut conn = db_pool.get()?;
-    let mut authenticated_state = AuthState {
+    let conn = db_pool.get()?;
+    let challenge_sent = AuthState {
         socket,
+        read_buffer: Vec::new(),
         state: SocketOpened,
     }
-    .handle_challenge()
-    .await?
-    .handle_proof(&mut conn)
+    .handle_challenge(&conn, &config)
     .await?;
 
+    let mut authenticated_state = match challenge_sent {
+        ChallengeSent::Logon(state) => state.handle_proof(&conn).await?,
+        ChallengeSent::Reconnect(state) => state.handle_reconnect_proof(&conn).await?,
+    };
+
     loop {
-        authenticated_state.handle_realm_list(&realms).await?;
+        authenticated_state
+            .handle_realm_list(&conn, &config)
+            .await?;
     }
 }
-
-fn save_session_key(
-    conn: &mut Connection,
-    account_name: String,
-    session_key: String,
-) -> Result<(), rusqlite::Error> {
-    let mut stmt =
-        conn.prepare_cached("UPDATE accounts SET session_key = ? WHERE UPPER(username) = ?")?;
-    stmt.execute([session_key, account_name])?;
-
-    Ok(())
-}
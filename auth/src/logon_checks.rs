@@ -0,0 +1,81 @@
+use std::net::IpAddr;
+
+use log::info;
+use rusqlite::Connection;
+
+use crate::{
+    config::LockoutSection,
+    packets::AuthResult,
+    repositories::{
+        ban::{BanRecord, BanRepository},
+        failed_login::FailedLoginRepository,
+    },
+};
+
+// Ban and lockout checks run before any credential is verified, also used by the admin console of
+// the world server
+
+// Returns the result to reject the logon with if the IP is banned or locked
+pub fn ip_rejection(
+    conn: &Connection,
+    lockout: &LockoutSection,
+    client_ip: &IpAddr,
+) -> Result<Option<AuthResult>, rusqlite::Error> {
+    if let Some(ban) = BanRepository::fetch_active_ip_ban(conn, client_ip)? {
+        info!(
+            "rejected logon from banned IP {} ({}): {}",
+            client_ip, ban.target, ban.reason
+        );
+        return Ok(Some(ban_result(&ban)));
+    }
+
+    let ip_failures =
+        FailedLoginRepository::count_recent_for_ip(conn, client_ip, lockout.lockout_duration_secs)?;
+    if ip_failures >= lockout.max_failed_attempts_per_ip {
+        info!(
+            "rejected logon from locked IP {} ({} failed attempts)",
+            client_ip, ip_failures
+        );
+        return Ok(Some(AuthResult::FailSuspended));
+    }
+
+    Ok(None)
+}
+
+// Returns the result to reject the logon with if the account is banned or locked
+pub fn account_rejection(
+    conn: &Connection,
+    lockout: &LockoutSection,
+    account_name: &str,
+) -> Result<Option<AuthResult>, rusqlite::Error> {
+    if let Some(ban) = BanRepository::fetch_active_account_ban(conn, account_name)? {
+        info!(
+            "rejected logon from banned account {}: {}",
+            account_name, ban.reason
+        );
+        return Ok(Some(ban_result(&ban)));
+    }
+
+    let account_failures = FailedLoginRepository::count_recent_for_account(
+        conn,
+        account_name,
+        lockout.lockout_duration_secs,
+    )?;
+    if account_failures >= lockout.max_failed_attempts_per_account {
+        info!(
+            "rejected logon from locked account {} ({} failed attempts)",
+            account_name, account_failures
+        );
+        return Ok(Some(AuthResult::FailSuspended));
+    }
+
+    Ok(None)
+}
+
+fn ban_result(ban: &BanRecord) -> AuthResult {
+    if ban.is_permanent() {
+        AuthResult::FailBanned
+    } else {
+        AuthResult::FailSuspended
+    }
+}
@@ -1,9 +1,22 @@
+use clap::{Parser, Subcommand};
 use env_logger::Env;
-use log::trace;
+use log::{trace, warn};
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::Connection;
-use rustbolt_auth::{config::AuthConfig, AuthError, Realm, RealmType};
-use std::sync::Arc;
+use rustbolt_auth::{
+    config::AuthConfig,
+    ip_range::IpRange,
+    repositories::{
+        account::AccountRepository,
+        ban::{BanRecord, BanRepository},
+        failed_login::FailedLoginRepository,
+    },
+    totp, AuthError,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tokio::net::TcpListener;
 
 mod embedded {
@@ -13,8 +26,10 @@ mod embedded {
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+
     // Load config
-    let config = AuthConfig::load().expect("Error in config file");
+    let config = Arc::new(AuthConfig::load().expect("Error in config file"));
 
     // Setup logging
     env_logger::Builder::from_env(Env::default().default_filter_or("debug")).init();
@@ -26,15 +41,29 @@ async fn main() {
     ))
     .with_init(|c| {
         embedded::migrations::runner().run(c).unwrap();
-        Ok(())
+        // SQLite ignores the ON DELETE CASCADE clauses unless this is set on each connection
+        c.execute_batch("PRAGMA foreign_keys = ON;")
     });
     let db_pool = r2d2::Pool::new(sqlite_connection_manager)
         .expect("Failed to create r2d2 SQlite connection pool");
     let db_pool = Arc::new(db_pool);
 
-    // Load realms from database
-    let mut conn = db_pool.get().unwrap();
-    let realms = load_realms_from_db(&mut conn);
+    if let Some(command) = cli.command {
+        let conn = db_pool.get().unwrap();
+        match command {
+            Commands::Account(command) => run_account_command(&conn, command),
+            Commands::Ban(command) => run_ban_command(&conn, command),
+            Commands::Unban(command) => run_unban_command(&conn, command),
+        }
+        return;
+    }
+
+    // Forget failed logons that no longer count towards a lockout
+    {
+        let conn = db_pool.get().unwrap();
+        FailedLoginRepository::purge_older_than(&conn, config.auth.lockout.lockout_duration_secs)
+            .unwrap();
+    }
 
     // Bind the listener to the address
     let listener = TcpListener::bind(format!(
@@ -49,42 +78,224 @@ async fn main() {
         let (socket, _) = listener.accept().await.unwrap();
 
         // Spawn a new task for each inbound socket
-        let realms_copy = Arc::clone(&realms);
         let db_pool_copy = Arc::clone(&db_pool);
+        let config_copy = Arc::clone(&config);
         tokio::spawn(async move {
-            match rustbolt_auth::process(socket, realms_copy, db_pool_copy).await {
+            match rustbolt_auth::process(socket, db_pool_copy, config_copy).await {
                 Ok(_) => (),
                 Err(AuthError::ClientDisconnected) => trace!("Client disconnected"),
-                Err(e) => panic!("Parse error during auth sequence: {:?}", e),
+                Err(AuthError::LogonFailed(result)) => trace!("Logon failed: {:?}", result),
+                Err(e) => warn!("Auth sequence aborted: {:?}", e),
             }
         });
     }
 }
 
-fn load_realms_from_db(conn: &mut Connection) -> Arc<Vec<Realm>> {
-    let mut stmt = conn.prepare("SELECT id, realm_type, is_locked, flags, name, address, population, category FROM realms").unwrap();
-    let realm_iter = stmt
-        .query_map([], |row| {
-            let realm_type: i32 = row.get("realm_type")?;
-            let realm_type = RealmType::try_from(realm_type).unwrap();
-            let locked: i32 = row.get("is_locked")?;
-            let locked = locked > 0;
-            let realm_name: String = row.get("name")?;
-            let address: String = row.get("address")?;
-
-            Ok(Realm {
-                realm_type,
-                locked,
-                realm_flags: row.get("flags")?,
-                realm_name: realm_name.into(),
-                address_port: address.into(),
-                population: row.get("population")?,
-                num_chars: 1,
-                realm_category: row.get("category")?,
-                realm_id: row.get("id")?,
+fn run_account_command(conn: &Connection, command: AccountCommands) {
+    let result = match command {
+        AccountCommands::Create { username, password } => {
+            AccountRepository::create(conn, &username, &password)
+                .map(|id| println!("Account {username} created with id {id}"))
+        }
+        AccountCommands::Delete { username } => AccountRepository::delete(conn, &username)
+            .map(|_| println!("Account {username} deleted")),
+        AccountCommands::List => AccountRepository::list(conn).map(|accounts| {
+            for account in accounts {
+                println!("{}\t{}", account.id, account.username);
+            }
+        }),
+        AccountCommands::SetPassword { username, password } => {
+            AccountRepository::set_password(conn, &username, &password)
+                .map(|_| println!("Password updated for account {username}"))
+        }
+        AccountCommands::SetSecurityLevel { username, level } => {
+            AccountRepository::set_security_level(conn, &username, level)
+                .map(|_| println!("Security level of account {username} set to {level}"))
+        }
+        AccountCommands::Enable2fa { username } => {
+            let secret = totp::generate_secret();
+            AccountRepository::set_totp_secret(conn, &username, Some(&secret)).map(|_| {
+                println!("Two-factor authentication enabled for account {username}");
+                println!("Add this URI to an authenticator app and enter the generated codes with the PIN keypad:");
+                println!("{}", totp::provisioning_uri(&secret, &username));
+            })
+        }
+        AccountCommands::Disable2fa { username } => {
+            AccountRepository::set_totp_secret(conn, &username, None)
+                .map(|_| println!("Two-factor authentication disabled for account {username}"))
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run_ban_command(conn: &Connection, command: BanCommands) {
+    let result = match command {
+        BanCommands::Account {
+            username,
+            duration,
+            reason,
+        } => BanRepository::ban_account(conn, &username, duration, &reason)
+            .map(|_| println!("Account {username} banned"))
+            .map_err(|e| e.to_string()),
+        BanCommands::Ip {
+            ip_range,
+            duration,
+            reason,
+        } => BanRepository::ban_ip(conn, &ip_range, duration, &reason)
+            .map(|_| println!("IP range {ip_range} banned"))
+            .map_err(|e| e.to_string()),
+        BanCommands::List => BanRepository::list_active_account_bans(conn)
+            .and_then(|account_bans| {
+                BanRepository::list_active_ip_bans(conn).map(|ip_bans| (account_bans, ip_bans))
             })
-        })
-        .unwrap();
+            .map(|(account_bans, ip_bans)| {
+                println!("Accounts:");
+                account_bans.iter().for_each(print_ban);
+                println!("IP ranges:");
+                ip_bans.iter().for_each(print_ban);
+            })
+            .map_err(|e| e.to_string()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run_unban_command(conn: &Connection, command: UnbanCommands) {
+    let result = match command {
+        UnbanCommands::Account { username } => BanRepository::unban_account(conn, &username)
+            .map(|_| println!("Account {username} unbanned"))
+            .map_err(|e| e.to_string()),
+        UnbanCommands::Ip { ip_range } => BanRepository::unban_ip(conn, &ip_range)
+            .map(|count| println!("Removed {count} ban(s) on IP range {ip_range}"))
+            .map_err(|e| e.to_string()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn print_ban(ban: &BanRecord) {
+    let expiration = match ban.expires_at {
+        None => "permanent".to_owned(),
+        Some(expires_at) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let remaining = expires_at.saturating_sub(now);
+            format!(
+                "expires in {}d {}h {}m",
+                remaining / 86400,
+                (remaining % 86400) / 3600,
+                (remaining % 3600) / 60
+            )
+        }
+    };
+
+    println!("\t{}\t{}\t{}", ban.target, expiration, ban.reason);
+}
+
+// Parses durations such as "90s", "30m", "12h" or "7d" (seconds if no unit is given)
+fn parse_duration(input: &str) -> Result<Duration, String> {
+    let (value, multiplier) = match input.chars().last() {
+        Some('s') => (&input[..input.len() - 1], 1),
+        Some('m') => (&input[..input.len() - 1], 60),
+        Some('h') => (&input[..input.len() - 1], 3600),
+        Some('d') => (&input[..input.len() - 1], 86400),
+        _ => (input, 1),
+    };
+
+    value
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(multiplier))
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid duration: {input}"))
+}
+
+#[derive(Parser)]
+#[command(name = "rustbolt-auth")]
+#[command(about = "Rustbolt authentication server", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Manage accounts instead of starting the server
+    #[command(subcommand)]
+    Account(AccountCommands),
+    /// Ban accounts or IP ranges instead of starting the server
+    #[command(subcommand)]
+    Ban(BanCommands),
+    /// Lift account or IP range bans instead of starting the server
+    #[command(subcommand)]
+    Unban(UnbanCommands),
+}
+
+#[derive(Subcommand)]
+enum AccountCommands {
+    /// Create a new account
+    Create { username: String, password: String },
+    /// Delete an existing account
+    Delete { username: String },
+    /// List all accounts
+    List,
+    /// Reset the password of an existing account
+    SetPassword { username: String, password: String },
+    /// Set the level required to use chat commands (0 = player, 1 = moderator,
+    /// 2 = game master, 3 = administrator)
+    SetSecurityLevel {
+        username: String,
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=3))]
+        level: u8,
+    },
+    /// Require a TOTP code entered with the PIN keypad in addition to the password
+    #[command(name = "enable-2fa")]
+    Enable2fa { username: String },
+    /// Stop requiring a TOTP code
+    #[command(name = "disable-2fa")]
+    Disable2fa { username: String },
+}
+
+#[derive(Subcommand)]
+enum BanCommands {
+    /// Ban an account
+    Account {
+        username: String,
+        /// Ban duration (e.g. 30m, 12h, 7d), permanent if omitted
+        #[arg(short, long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+        #[arg(short, long, default_value = "")]
+        reason: String,
+    },
+    /// Ban an IP address or a CIDR range (e.g. 10.0.0.0/8)
+    Ip {
+        ip_range: IpRange,
+        /// Ban duration (e.g. 30m, 12h, 7d), permanent if omitted
+        #[arg(short, long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+        #[arg(short, long, default_value = "")]
+        reason: String,
+    },
+    /// List active bans
+    List,
+}
 
-    Arc::new(realm_iter.map(|r| r.unwrap()).collect())
+#[derive(Subcommand)]
+enum UnbanCommands {
+    /// Lift all bans on an account
+    Account { username: String },
+    /// Lift all bans on an IP address or CIDR range
+    Ip { ip_range: IpRange },
 }
@@ -1,29 +1,52 @@
 use binrw::{binread, binrw, binwrite, NullString};
-use wow_srp::{
-    normalized_string::NormalizedString,
-    server::{SrpProof, SrpServer, SrpVerifier},
-};
+use sha1::{Digest, Sha1};
+use wow_srp::server::{SrpProof, SrpServer, SrpVerifier};
 
 #[binrw]
 #[brw(repr(u8))]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(clippy::enum_variant_names)]
 pub enum Opcode {
     CmdAuthLogonChallenge = 0x00,
     CmdAuthLogonProof = 0x01,
-    // CMD_AUTH_RECONNECT_CHALLENGE = 0x02,
+    CmdAuthReconnectChallenge = 0x02,
+    CmdAuthReconnectProof = 0x03,
     CmdRealmList = 0x10,
 }
 
+// https://wowdev.wiki/CMD_AUTH_LOGON_CHALLENGE_Server#AuthResult
+#[binrw]
+#[brw(repr(u8))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum AuthResult {
+    Success = 0x00,
+    FailBanned = 0x03,
+    FailUnknownAccount = 0x04,
+    FailIncorrectPassword = 0x05,
+    FailAlreadyOnline = 0x06,
+    FailNoTime = 0x07,
+    FailDbBusy = 0x08,
+    FailVersionInvalid = 0x09,
+    FailVersionUpdate = 0x0A,
+    FailInvalidServer = 0x0B,
+    FailSuspended = 0x0C,
+    FailNoAccess = 0x0D,
+    SuccessSurvey = 0x0E,
+    FailParentControl = 0x0F,
+    FailLockedEnforced = 0x10,
+}
+
+// Also used for CMD_AUTH_RECONNECT_CHALLENGE which has the same layout
 #[binread]
 #[derive(Debug)]
 pub struct CmdAuthLogonChallengeClient {
-    _opcode: Opcode,
+    pub opcode: Opcode,
     _protocol_version: u8,
     _size: u16,
     _game_name: NullString,
     _version: [u8; 3],
-    _build: u16,
+    pub build: u16,
     _platform: NullString,
     _os: NullString,
     #[br(count = 4)]
@@ -32,6 +55,8 @@ pub struct CmdAuthLogonChallengeClient {
     _worldregion_bias: u32,
     _ip: [u8; 4], // u32 on wowdev.wiki
     _account_name_length: u8,
+    // Not null-terminated
+    #[br(count = _account_name_length, map = |bytes: Vec<u8>| NullString(bytes))]
     pub account_name: NullString,
 }
 
@@ -40,7 +65,13 @@ pub struct CmdAuthLogonChallengeClient {
 pub struct CmdAuthLogonChallengeServer {
     _opcode: Opcode,
     _protocol_version: u8,
-    _result: u8,
+    _result: AuthResult,
+    _challenge: Option<LogonChallenge>, // Only sent if result is Success
+}
+
+#[binwrite]
+#[derive(Debug)]
+struct LogonChallenge {
     _server_public_key: [u8; wow_srp::PUBLIC_KEY_LENGTH as usize],
     _generator_len: u8, // Always 1
     _generator: u8,
@@ -49,35 +80,110 @@ pub struct CmdAuthLogonChallengeServer {
     _salt: [u8; 32],
     _crc_salt: [u8; 16],
     _security_flags: u8,
+    _pin_challenge: Option<PinChallenge>, // Only sent if security_flags & SECURITY_FLAG_PIN
 }
 
-impl CmdAuthLogonChallengeServer {
-    fn get_proof(username: &str) -> SrpProof {
-        let username = NormalizedString::new(username.to_string()).unwrap();
-        let password = NormalizedString::new(username.to_string()).unwrap();
-        SrpVerifier::from_username_and_password(username, password).into_proof()
+pub const SECURITY_FLAG_PIN: u8 = 0x01;
+
+// The client shows a keypad whose layout is shuffled with grid_seed and sends back a hash of
+// the positions of the PIN digits on it
+#[binwrite]
+#[derive(Debug, Clone, Copy)]
+pub struct PinChallenge {
+    pub grid_seed: u32,
+    pub salt: [u8; 16],
+}
+
+impl PinChallenge {
+    pub fn generate() -> PinChallenge {
+        PinChallenge {
+            grid_seed: rand::random(),
+            salt: rand::random(),
+        }
     }
 
-    pub fn new(username: &NullString) -> (CmdAuthLogonChallengeServer, SrpProof) {
-        let p = Self::get_proof(&username.to_string());
+    // client_hash = SHA1(client_salt | SHA1(server_salt | digit positions on the keypad))
+    pub fn is_valid(&self, pin_proof: &PinProof, pin: &str) -> bool {
+        let grid = self.remapped_grid();
+        let Some(positions) = pin
+            .chars()
+            .map(|c| {
+                let digit = c.to_digit(10)? as u8;
+                let position = grid.iter().position(|&d| d == digit)? as u8;
+                Some(b'0' + position)
+            })
+            .collect::<Option<Vec<u8>>>()
+        else {
+            return false;
+        };
+
+        let pin_hash = Sha1::new()
+            .chain_update(self.salt)
+            .chain_update(positions)
+            .finalize();
+        let expected_hash = Sha1::new()
+            .chain_update(pin_proof.salt)
+            .chain_update(pin_hash)
+            .finalize();
+
+        constant_time_eq(expected_hash.as_slice(), &pin_proof.hash)
+    }
+
+    fn remapped_grid(&self) -> [u8; 10] {
+        let mut digits: Vec<u8> = (0..10).collect();
+        let mut remapped_grid = [0_u8; 10];
+        let mut seed = self.grid_seed;
+
+        for (i, remapped_digit) in remapped_grid.iter_mut().enumerate() {
+            let remaining = (10 - i) as u32;
+            *remapped_digit = digits.remove((seed % remaining) as usize);
+            seed /= remaining;
+        }
+
+        remapped_grid
+    }
+}
+
+impl CmdAuthLogonChallengeServer {
+    pub fn new(
+        verifier: SrpVerifier,
+        pin_challenge: Option<PinChallenge>,
+    ) -> (CmdAuthLogonChallengeServer, SrpProof) {
+        let p = verifier.into_proof();
 
         (
             CmdAuthLogonChallengeServer {
                 _opcode: Opcode::CmdAuthLogonChallenge,
                 _protocol_version: 0,
-                _result: 0,
-                _server_public_key: *p.server_public_key(),
-                _generator_len: 1,
-                _generator: wow_srp::GENERATOR,
-                _large_safe_prime_len: wow_srp::LARGE_SAFE_PRIME_LENGTH,
-                _large_safe_prime: wow_srp::LARGE_SAFE_PRIME_LITTLE_ENDIAN,
-                _salt: *p.salt(),
-                _crc_salt: [0; 16],
-                _security_flags: 0,
+                _result: AuthResult::Success,
+                _challenge: Some(LogonChallenge {
+                    _server_public_key: *p.server_public_key(),
+                    _generator_len: 1,
+                    _generator: wow_srp::GENERATOR,
+                    _large_safe_prime_len: wow_srp::LARGE_SAFE_PRIME_LENGTH,
+                    _large_safe_prime: wow_srp::LARGE_SAFE_PRIME_LITTLE_ENDIAN,
+                    _salt: *p.salt(),
+                    _crc_salt: [0; 16],
+                    _security_flags: if pin_challenge.is_some() {
+                        SECURITY_FLAG_PIN
+                    } else {
+                        0
+                    },
+                    _pin_challenge: pin_challenge,
+                }),
             },
             p,
         )
     }
+
+    pub fn failure(result: AuthResult) -> CmdAuthLogonChallengeServer {
+        CmdAuthLogonChallengeServer {
+            _opcode: Opcode::CmdAuthLogonChallenge,
+            _protocol_version: 0,
+            _result: result,
+            _challenge: None,
+        }
+    }
 }
 
 #[binread]
@@ -89,6 +195,15 @@ pub struct CmdAuthLogonProofClient {
     _crc_hash: [u8; 20],
     _num_keys: u8,
     _security_flags: u8,
+    #[br(if(_security_flags & SECURITY_FLAG_PIN != 0))]
+    pub pin_proof: Option<PinProof>,
+}
+
+#[binread]
+#[derive(Debug)]
+pub struct PinProof {
+    salt: [u8; 16],
+    hash: [u8; 20],
 }
 
 #[binwrite]
@@ -103,18 +218,18 @@ pub struct CmdAuthLogonProofServer {
 }
 
 impl CmdAuthLogonProofServer {
+    // Returns None if the client proof doesn't match (i.e. wrong password)
     pub fn new(
         logon_proof_client: CmdAuthLogonProofClient,
         p: SrpProof,
-    ) -> (CmdAuthLogonProofServer, SrpServer) {
+    ) -> Option<(CmdAuthLogonProofServer, SrpServer)> {
+        let client_public_key =
+            wow_srp::PublicKey::from_le_bytes(&logon_proof_client._client_public_key).ok()?;
         let (srp_server, server_proof) = p
-            .into_server(
-                wow_srp::PublicKey::from_le_bytes(&logon_proof_client._client_public_key).unwrap(),
-                logon_proof_client._client_proof,
-            )
-            .unwrap();
+            .into_server(client_public_key, logon_proof_client._client_proof)
+            .ok()?;
 
-        (
+        Some((
             CmdAuthLogonProofServer {
                 _opcode: Opcode::CmdAuthLogonProof,
                 _result: 0,
@@ -124,7 +239,109 @@ impl CmdAuthLogonProofServer {
                 _unknown_flags: 0,
             },
             srp_server,
-        )
+        ))
+    }
+}
+
+#[binwrite]
+#[derive(Debug)]
+pub struct CmdAuthLogonProofServerFailure {
+    _opcode: Opcode,
+    _result: AuthResult,
+    _padding: [u8; 2], // Expected by TBC clients
+}
+
+impl CmdAuthLogonProofServerFailure {
+    pub fn new(result: AuthResult) -> CmdAuthLogonProofServerFailure {
+        CmdAuthLogonProofServerFailure {
+            _opcode: Opcode::CmdAuthLogonProof,
+            _result: result,
+            _padding: [3, 0],
+        }
+    }
+}
+
+#[binwrite]
+#[derive(Debug)]
+pub struct CmdAuthReconnectChallengeServer {
+    _opcode: Opcode,
+    _result: AuthResult,
+    _challenge: Option<ReconnectChallenge>, // Only sent if result is Success
+}
+
+#[binwrite]
+#[derive(Debug)]
+struct ReconnectChallenge {
+    _challenge_data: [u8; 16],
+    _checksum_salt: [u8; 16],
+}
+
+impl CmdAuthReconnectChallengeServer {
+    pub fn new(challenge_data: [u8; 16]) -> CmdAuthReconnectChallengeServer {
+        CmdAuthReconnectChallengeServer {
+            _opcode: Opcode::CmdAuthReconnectChallenge,
+            _result: AuthResult::Success,
+            _challenge: Some(ReconnectChallenge {
+                _challenge_data: challenge_data,
+                _checksum_salt: [0; 16],
+            }),
+        }
+    }
+
+    pub fn failure(result: AuthResult) -> CmdAuthReconnectChallengeServer {
+        CmdAuthReconnectChallengeServer {
+            _opcode: Opcode::CmdAuthReconnectChallenge,
+            _result: result,
+            _challenge: None,
+        }
+    }
+}
+
+#[binread]
+#[derive(Debug)]
+pub struct CmdAuthReconnectProofClient {
+    _opcode: Opcode,
+    _proof_data: [u8; 16],
+    _client_proof: [u8; 20],
+    _client_checksum: [u8; 20],
+    _num_keys: u8,
+}
+
+impl CmdAuthReconnectProofClient {
+    // The client proves that it knows the session key of its previous logon:
+    // client_proof = SHA1(account_name | proof_data | challenge_data | session_key)
+    pub fn is_valid(
+        &self,
+        account_name: &str,
+        challenge_data: &[u8; 16],
+        session_key: &[u8; 40],
+    ) -> bool {
+        let expected_proof = Sha1::new()
+            .chain_update(account_name.as_bytes())
+            .chain_update(self._proof_data)
+            .chain_update(challenge_data)
+            .chain_update(session_key)
+            .finalize();
+
+        constant_time_eq(expected_proof.as_slice(), &self._client_proof)
+    }
+}
+
+#[binwrite]
+#[derive(Debug)]
+pub struct CmdAuthReconnectProofServer {
+    _opcode: Opcode,
+    _result: AuthResult,
+    _padding: u16,
+}
+
+impl CmdAuthReconnectProofServer {
+    pub fn new(result: AuthResult) -> CmdAuthReconnectProofServer {
+        CmdAuthReconnectProofServer {
+            _opcode: Opcode::CmdAuthReconnectProof,
+            _result: result,
+            _padding: 0,
+        }
     }
 }
 
@@ -163,6 +380,7 @@ impl TryFrom<i32> for RealmType {
 #[brw(repr(u8))]
 #[repr(u8)]
 #[derive(Copy, Clone, Debug)]
+#[allow(dead_code)]
 pub enum RealmFlag {
     None = 0x00,
     Invalid = 0x01,
@@ -206,3 +424,14 @@ pub struct CmdRealmListServer<'a> {
     pub realms: &'a Vec<Realm>,
     pub padding_footer: u16,
 }
+
+// Compares the proofs without returning early on the first different byte, so that the response
+// time does not tell how much of a forged proof is right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let diff = a.iter().zip(b).fold(0_u8, |acc, (x, y)| acc | (x ^ y));
+    std::hint::black_box(diff) == 0
+}
@@ -0,0 +1,304 @@
+use std::fmt::Display;
+
+use hex::{FromHex, ToHex};
+use log::error;
+use rusqlite::{named_params, Connection, OptionalExtension};
+use wow_srp::{normalized_string::NormalizedString, server::SrpVerifier};
+
+// Limits enforced by the client login screen
+pub const MAX_USERNAME_LENGTH: usize = 16;
+pub const MAX_PASSWORD_LENGTH: usize = 16;
+
+#[derive(Debug)]
+pub enum AccountError {
+    InvalidUsername,
+    InvalidPassword,
+    UsernameTaken,
+    AccountNotFound,
+    DbError(rusqlite::Error),
+}
+
+impl From<rusqlite::Error> for AccountError {
+    fn from(value: rusqlite::Error) -> Self {
+        Self::DbError(value)
+    }
+}
+
+impl Display for AccountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidUsername => write!(
+                f,
+                "invalid username (1 to {MAX_USERNAME_LENGTH} characters, letters and digits only)"
+            ),
+            Self::InvalidPassword => write!(
+                f,
+                "invalid password (1 to {MAX_PASSWORD_LENGTH} characters, letters, digits and punctuation only)"
+            ),
+            Self::UsernameTaken => write!(f, "an account with this username already exists"),
+            Self::AccountNotFound => write!(f, "account not found"),
+            Self::DbError(e) => write!(f, "database error: {e}"),
+        }
+    }
+}
+
+pub struct AccountRecord {
+    pub id: u32,
+    pub username: String,
+}
+
+pub struct AccountRepository;
+
+impl AccountRepository {
+    pub fn create(conn: &Connection, username: &str, password: &str) -> Result<u32, AccountError> {
+        let verifier = Self::build_verifier(username, password)?;
+
+        if Self::fetch_id(conn, verifier.username())?.is_some() {
+            return Err(AccountError::UsernameTaken);
+        }
+
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO accounts(id, username, verifier, salt, session_key) VALUES (NULL, :username, :verifier, :salt, '')",
+        )?;
+        stmt.execute(named_params! {
+            ":username": verifier.username(),
+            ":verifier": verifier.password_verifier().encode_hex::<String>(),
+            ":salt": verifier.salt().encode_hex::<String>(),
+        })?;
+
+        Ok(conn.last_insert_rowid() as u32)
+    }
+
+    pub fn delete(conn: &Connection, username: &str) -> Result<(), AccountError> {
+        let mut stmt =
+            conn.prepare_cached("DELETE FROM accounts WHERE UPPER(username) = UPPER(:username)")?;
+        let deleted = stmt.execute(named_params! { ":username": username })?;
+
+        if deleted == 0 {
+            return Err(AccountError::AccountNotFound);
+        }
+
+        Ok(())
+    }
+
+    pub fn list(conn: &Connection) -> Result<Vec<AccountRecord>, AccountError> {
+        let mut stmt = conn.prepare_cached("SELECT id, username FROM accounts ORDER BY id")?;
+        let accounts = stmt
+            .query_map([], |row| {
+                Ok(AccountRecord {
+                    id: row.get("id")?,
+                    username: row.get("username")?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(accounts)
+    }
+
+    pub fn set_password(
+        conn: &Connection,
+        username: &str,
+        password: &str,
+    ) -> Result<(), AccountError> {
+        let verifier = Self::build_verifier(username, password)?;
+
+        // Also clear the session key so that the old password cannot be used to reconnect
+        let mut stmt = conn.prepare_cached(
+            "UPDATE accounts SET verifier = :verifier, salt = :salt, session_key = '' WHERE UPPER(username) = UPPER(:username)",
+        )?;
+        let updated = stmt.execute(named_params! {
+            ":username": verifier.username(),
+            ":verifier": verifier.password_verifier().encode_hex::<String>(),
+            ":salt": verifier.salt().encode_hex::<String>(),
+        })?;
+
+        if updated == 0 {
+            return Err(AccountError::AccountNotFound);
+        }
+
+        Ok(())
+    }
+
+    pub fn fetch_id(conn: &Connection, username: &str) -> Result<Option<u32>, rusqlite::Error> {
+        let mut stmt = conn
+            .prepare_cached("SELECT id FROM accounts WHERE UPPER(username) = UPPER(:username)")?;
+        stmt.query_row(named_params! { ":username": username }, |row| row.get("id"))
+            .optional()
+    }
+
+    // Returns None if the account doesn't exist or has no usable credentials
+    pub fn fetch_verifier(
+        conn: &Connection,
+        username: &str,
+    ) -> Result<Option<SrpVerifier>, rusqlite::Error> {
+        let Ok(normalized_username) = NormalizedString::new(username.to_string()) else {
+            return Ok(None);
+        };
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT verifier, salt FROM accounts WHERE UPPER(username) = UPPER(:username)",
+        )?;
+        let values: Option<(String, String)> = stmt
+            .query_row(
+                named_params! { ":username": normalized_username.to_string() },
+                |row| Ok((row.get("verifier")?, row.get("salt")?)),
+            )
+            .optional()?;
+
+        Ok(values.and_then(|(verifier, salt)| {
+            let verifier: Option<[u8; 32]> = <Vec<u8>>::from_hex(verifier)
+                .ok()
+                .and_then(|v| v.try_into().ok());
+            let salt: Option<[u8; 32]> = <Vec<u8>>::from_hex(salt)
+                .ok()
+                .and_then(|s| s.try_into().ok());
+
+            match (verifier, salt) {
+                (Some(verifier), Some(salt)) => Some(SrpVerifier::from_database_values(
+                    normalized_username,
+                    verifier,
+                    salt,
+                )),
+                _ => {
+                    error!("account {username} has an invalid verifier or salt in database");
+                    None
+                }
+            }
+        }))
+    }
+
+    // Returns None if the account doesn't exist or never logged in with its current password
+    pub fn fetch_session_key(
+        conn: &Connection,
+        username: &str,
+    ) -> Result<Option<[u8; 40]>, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT session_key FROM accounts WHERE UPPER(username) = UPPER(:username)",
+        )?;
+        let session_key: Option<String> = stmt
+            .query_row(named_params! { ":username": username }, |row| {
+                row.get("session_key")
+            })
+            .optional()?;
+
+        Ok(session_key.and_then(|session_key| {
+            <Vec<u8>>::from_hex(session_key)
+                .ok()
+                .and_then(|k| k.try_into().ok())
+        }))
+    }
+
+    pub fn fetch_totp_secret(
+        conn: &Connection,
+        username: &str,
+    ) -> Result<Option<Vec<u8>>, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT totp_secret FROM accounts WHERE UPPER(username) = UPPER(:username)",
+        )?;
+        let totp_secret: Option<Option<String>> = stmt
+            .query_row(named_params! { ":username": username }, |row| {
+                row.get("totp_secret")
+            })
+            .optional()?;
+
+        Ok(totp_secret.flatten().and_then(|totp_secret| {
+            <Vec<u8>>::from_hex(totp_secret)
+                .map_err(|_| error!("account {username} has an invalid TOTP secret in database"))
+                .ok()
+        }))
+    }
+
+    // Pass None to disable two-factor authentication
+    pub fn set_totp_secret(
+        conn: &Connection,
+        username: &str,
+        totp_secret: Option<&[u8]>,
+    ) -> Result<(), AccountError> {
+        let mut stmt = conn.prepare_cached(
+            "UPDATE accounts SET totp_secret = :totp_secret WHERE UPPER(username) = UPPER(:username)",
+        )?;
+        let updated = stmt.execute(named_params! {
+            ":username": username,
+            ":totp_secret": totp_secret.map(|s| s.encode_hex::<String>()),
+        })?;
+
+        if updated == 0 {
+            return Err(AccountError::AccountNotFound);
+        }
+
+        Ok(())
+    }
+
+    pub fn set_security_level(
+        conn: &Connection,
+        username: &str,
+        security_level: u8,
+    ) -> Result<(), AccountError> {
+        let mut stmt = conn.prepare_cached(
+            "UPDATE accounts SET security_level = :security_level WHERE UPPER(username) = UPPER(:username)",
+        )?;
+        let updated = stmt.execute(named_params! {
+            ":username": username,
+            ":security_level": security_level,
+        })?;
+
+        if updated == 0 {
+            return Err(AccountError::AccountNotFound);
+        }
+
+        Ok(())
+    }
+
+    pub fn save_session_key(
+        conn: &Connection,
+        username: &str,
+        session_key: String,
+    ) -> Result<(), AccountError> {
+        let mut stmt = conn.prepare_cached(
+            "UPDATE accounts SET session_key = :session_key WHERE UPPER(username) = UPPER(:username)",
+        )?;
+        let updated = stmt.execute(named_params! {
+            ":session_key": session_key,
+            ":username": username,
+        })?;
+
+        if updated == 0 {
+            return Err(AccountError::AccountNotFound);
+        }
+
+        Ok(())
+    }
+
+    pub fn save_last_build(
+        conn: &Connection,
+        username: &str,
+        build: u16,
+    ) -> Result<(), rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "UPDATE accounts SET last_build = :build WHERE UPPER(username) = UPPER(:username)",
+        )?;
+        stmt.execute(named_params! {
+            ":build": build,
+            ":username": username,
+        })?;
+
+        Ok(())
+    }
+
+    fn build_verifier(username: &str, password: &str) -> Result<SrpVerifier, AccountError> {
+        if username.is_empty() || username.len() > MAX_USERNAME_LENGTH {
+            return Err(AccountError::InvalidUsername);
+        }
+
+        if password.is_empty() || password.len() > MAX_PASSWORD_LENGTH {
+            return Err(AccountError::InvalidPassword);
+        }
+
+        let username = NormalizedString::new(username.to_string())
+            .map_err(|_| AccountError::InvalidUsername)?;
+        let password = NormalizedString::new(password.to_string())
+            .map_err(|_| AccountError::InvalidPassword)?;
+
+        Ok(SrpVerifier::from_username_and_password(username, password))
+    }
+}
@@ -0,0 +1,187 @@
+use std::{net::IpAddr, time::Duration};
+
+use log::warn;
+use rusqlite::{named_params, Connection, OptionalExtension};
+
+use crate::ip_range::IpRange;
+
+use super::{
+    account::{AccountError, AccountRepository},
+    now_timestamp,
+};
+
+pub struct BanRecord {
+    pub target: String, // Username or IP range
+    pub banned_at: u64,
+    pub expires_at: Option<u64>, // None for permanent bans
+    pub reason: String,
+}
+
+impl BanRecord {
+    pub fn is_permanent(&self) -> bool {
+        self.expires_at.is_none()
+    }
+}
+
+// Durations ending past what the database can store are permanent bans
+fn expiry_timestamp(now: u64, duration: Option<Duration>) -> Option<u64> {
+    duration.and_then(|d| {
+        now.checked_add(d.as_secs())
+            .filter(|expires_at| *expires_at <= i64::MAX as u64)
+    })
+}
+
+pub struct BanRepository;
+
+impl BanRepository {
+    pub fn ban_account(
+        conn: &Connection,
+        username: &str,
+        duration: Option<Duration>,
+        reason: &str,
+    ) -> Result<(), AccountError> {
+        let account_id =
+            AccountRepository::fetch_id(conn, username)?.ok_or(AccountError::AccountNotFound)?;
+
+        let now = now_timestamp();
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO account_bans(account_id, banned_at, expires_at, reason) VALUES (:account_id, :banned_at, :expires_at, :reason)",
+        )?;
+        stmt.execute(named_params! {
+            ":account_id": account_id,
+            ":banned_at": now,
+            ":expires_at": expiry_timestamp(now, duration),
+            ":reason": reason,
+        })?;
+
+        Ok(())
+    }
+
+    pub fn unban_account(conn: &Connection, username: &str) -> Result<(), AccountError> {
+        let account_id =
+            AccountRepository::fetch_id(conn, username)?.ok_or(AccountError::AccountNotFound)?;
+
+        let mut stmt =
+            conn.prepare_cached("DELETE FROM account_bans WHERE account_id = :account_id")?;
+        stmt.execute(named_params! { ":account_id": account_id })?;
+
+        Ok(())
+    }
+
+    pub fn ban_ip(
+        conn: &Connection,
+        ip_range: &IpRange,
+        duration: Option<Duration>,
+        reason: &str,
+    ) -> Result<(), rusqlite::Error> {
+        let now = now_timestamp();
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO ip_bans(ip_range, banned_at, expires_at, reason) VALUES (:ip_range, :banned_at, :expires_at, :reason)",
+        )?;
+        stmt.execute(named_params! {
+            ":ip_range": ip_range.to_string(),
+            ":banned_at": now,
+            ":expires_at": expiry_timestamp(now, duration),
+            ":reason": reason,
+        })?;
+
+        Ok(())
+    }
+
+    // Returns the number of removed bans
+    pub fn unban_ip(conn: &Connection, ip_range: &IpRange) -> Result<usize, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached("DELETE FROM ip_bans WHERE ip_range = :ip_range")?;
+        stmt.execute(named_params! { ":ip_range": ip_range.to_string() })
+    }
+
+    // Returns the active ban with the longest remaining duration, if any
+    pub fn fetch_active_account_ban(
+        conn: &Connection,
+        username: &str,
+    ) -> Result<Option<BanRecord>, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT accounts.username, account_bans.banned_at, account_bans.expires_at, account_bans.reason
+            FROM account_bans
+            JOIN accounts ON accounts.id = account_bans.account_id
+            WHERE UPPER(accounts.username) = UPPER(:username) AND (account_bans.expires_at IS NULL OR account_bans.expires_at > :now)
+            ORDER BY account_bans.expires_at IS NULL DESC, account_bans.expires_at DESC
+            LIMIT 1",
+        )?;
+
+        stmt.query_row(
+            named_params! { ":username": username, ":now": now_timestamp() },
+            |row| {
+                Ok(BanRecord {
+                    target: row.get("username")?,
+                    banned_at: row.get("banned_at")?,
+                    expires_at: row.get("expires_at")?,
+                    reason: row.get("reason")?,
+                })
+            },
+        )
+        .optional()
+    }
+
+    // Returns the active ban with the longest remaining duration among the ranges containing ip
+    pub fn fetch_active_ip_ban(
+        conn: &Connection,
+        ip: &IpAddr,
+    ) -> Result<Option<BanRecord>, rusqlite::Error> {
+        let bans = Self::list_active_ip_bans(conn)?;
+
+        Ok(bans
+            .into_iter()
+            .filter(|ban| match ban.target.parse::<IpRange>() {
+                Ok(ip_range) => ip_range.contains(ip),
+                Err(_) => {
+                    warn!("ignoring invalid IP range in ip_bans: {}", ban.target);
+                    false
+                }
+            })
+            .max_by_key(|ban| ban.expires_at.unwrap_or(u64::MAX)))
+    }
+
+    pub fn list_active_account_bans(conn: &Connection) -> Result<Vec<BanRecord>, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT accounts.username, account_bans.banned_at, account_bans.expires_at, account_bans.reason
+            FROM account_bans
+            JOIN accounts ON accounts.id = account_bans.account_id
+            WHERE account_bans.expires_at IS NULL OR account_bans.expires_at > :now
+            ORDER BY account_bans.banned_at",
+        )?;
+
+        let bans = stmt
+            .query_map(named_params! { ":now": now_timestamp() }, |row| {
+                Ok(BanRecord {
+                    target: row.get("username")?,
+                    banned_at: row.get("banned_at")?,
+                    expires_at: row.get("expires_at")?,
+                    reason: row.get("reason")?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(bans)
+    }
+
+    pub fn list_active_ip_bans(conn: &Connection) -> Result<Vec<BanRecord>, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT ip_range, banned_at, expires_at, reason FROM ip_bans
+            WHERE expires_at IS NULL OR expires_at > :now
+            ORDER BY banned_at",
+        )?;
+
+        let bans = stmt
+            .query_map(named_params! { ":now": now_timestamp() }, |row| {
+                Ok(BanRecord {
+                    target: row.get("ip_range")?,
+                    banned_at: row.get("banned_at")?,
+                    expires_at: row.get("expires_at")?,
+                    reason: row.get("reason")?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(bans)
+    }
+}
@@ -0,0 +1,81 @@
+use std::net::IpAddr;
+
+use rusqlite::{named_params, Connection};
+
+use super::{account::AccountRepository, now_timestamp};
+
+pub struct FailedLoginRepository;
+
+impl FailedLoginRepository {
+    pub fn record(conn: &Connection, username: &str, ip: &IpAddr) -> Result<(), rusqlite::Error> {
+        // Unknown account names only count against the IP
+        let account_id = AccountRepository::fetch_id(conn, username)?;
+
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO failed_logins(account_id, ip_address, attempted_at) VALUES (:account_id, :ip_address, :attempted_at)",
+        )?;
+        stmt.execute(named_params! {
+            ":account_id": account_id,
+            ":ip_address": ip.to_canonical().to_string(),
+            ":attempted_at": now_timestamp(),
+        })?;
+
+        Ok(())
+    }
+
+    // Called after a successful logon, failures from the IP are kept to still catch
+    // attempts spread over several accounts
+    pub fn clear_for_account(conn: &Connection, username: &str) -> Result<(), rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "DELETE FROM failed_logins WHERE account_id = (SELECT id FROM accounts WHERE UPPER(username) = UPPER(:username))",
+        )?;
+        stmt.execute(named_params! { ":username": username })?;
+
+        Ok(())
+    }
+
+    pub fn count_recent_for_account(
+        conn: &Connection,
+        username: &str,
+        window_secs: u64,
+    ) -> Result<u32, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT COUNT(*) FROM failed_logins
+            JOIN accounts ON accounts.id = failed_logins.account_id
+            WHERE UPPER(accounts.username) = UPPER(:username) AND failed_logins.attempted_at > :since",
+        )?;
+        stmt.query_row(
+            named_params! {
+                ":username": username,
+                ":since": now_timestamp().saturating_sub(window_secs),
+            },
+            |row| row.get(0),
+        )
+    }
+
+    pub fn count_recent_for_ip(
+        conn: &Connection,
+        ip: &IpAddr,
+        window_secs: u64,
+    ) -> Result<u32, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT COUNT(*) FROM failed_logins WHERE ip_address = :ip_address AND attempted_at > :since",
+        )?;
+        stmt.query_row(
+            named_params! {
+                ":ip_address": ip.to_canonical().to_string(),
+                ":since": now_timestamp().saturating_sub(window_secs),
+            },
+            |row| row.get(0),
+        )
+    }
+
+    // Old failures are irrelevant once they fall out of the lockout window
+    pub fn purge_older_than(conn: &Connection, window_secs: u64) -> Result<(), rusqlite::Error> {
+        let mut stmt =
+            conn.prepare_cached("DELETE FROM failed_logins WHERE attempted_at <= :since")?;
+        stmt.execute(named_params! { ":since": now_timestamp().saturating_sub(window_secs) })?;
+
+        Ok(())
+    }
+}
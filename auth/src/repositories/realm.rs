@@ -0,0 +1,80 @@
+use log::error;
+use rusqlite::{named_params, Connection};
+
+use crate::packets::{Realm, RealmFlag, RealmType};
+
+use super::now_timestamp;
+
+pub struct RealmRepository;
+
+impl RealmRepository {
+    // Realms whose last heartbeat is older than heartbeat_timeout_secs are reported as offline
+    pub fn fetch_realms(
+        conn: &Connection,
+        account_id: u32,
+        heartbeat_timeout_secs: u64,
+    ) -> Result<Vec<Realm>, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT realms.id, realms.realm_type, realms.is_locked, realms.flags, realms.name, realms.address,
+                realms.category, realms.is_online, realms.online_players, realms.max_players, realms.last_heartbeat,
+                COALESCE(realm_characters.num_chars, 0) AS num_chars
+            FROM realms
+            LEFT JOIN realm_characters ON realm_characters.realm_id = realms.id AND realm_characters.account_id = :account_id
+            ORDER BY realms.id",
+        )?;
+
+        let heartbeat_threshold = now_timestamp().saturating_sub(heartbeat_timeout_secs);
+        let realms = stmt
+            .query_map(named_params! { ":account_id": account_id }, |row| {
+                let realm_type: i32 = row.get("realm_type")?;
+                let realm_type = RealmType::try_from(realm_type).unwrap_or_else(|e| {
+                    error!("{e}, falling back to Normal");
+                    RealmType::Normal
+                });
+                let locked: i32 = row.get("is_locked")?;
+                let realm_name: String = row.get("name")?;
+                let address: String = row.get("address")?;
+
+                let is_online: bool = row.get("is_online")?;
+                let last_heartbeat: Option<u64> = row.get("last_heartbeat")?;
+                let is_alive = is_online
+                    && last_heartbeat.is_some_and(|heartbeat| heartbeat > heartbeat_threshold);
+                let online_players: u32 = row.get("online_players")?;
+                let max_players: u32 = row.get("max_players")?;
+                let (status_flag, population) =
+                    Self::realm_status(is_alive, online_players, max_players);
+                let flags: u8 = row.get("flags")?;
+
+                Ok(Realm {
+                    realm_type,
+                    locked: locked > 0,
+                    realm_flags: flags | status_flag as u8,
+                    realm_name: realm_name.into(),
+                    address_port: address.into(),
+                    population,
+                    num_chars: row.get("num_chars")?,
+                    realm_category: row.get("category")?,
+                    realm_id: row.get("id")?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(realms)
+    }
+
+    // Population goes from 0.0 (empty) to 2.0 (full), as expected by the client
+    fn realm_status(is_alive: bool, online_players: u32, max_players: u32) -> (RealmFlag, f32) {
+        if !is_alive {
+            return (RealmFlag::Offline, 0.0);
+        }
+
+        if online_players >= max_players {
+            return (RealmFlag::ForceFull, 2.0);
+        }
+
+        (
+            RealmFlag::ForceRecommended,
+            2.0 * online_players as f32 / max_players as f32,
+        )
+    }
+}
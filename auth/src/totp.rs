@@ -0,0 +1,58 @@
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+// Time-based one-time passwords (RFC 6238) with the defaults of authenticator apps
+pub const SECRET_LENGTH: usize = 20;
+const TIME_STEP_SECS: u64 = 30;
+const DIGITS: u32 = 6;
+
+pub fn generate_secret() -> [u8; SECRET_LENGTH] {
+    rand::random()
+}
+
+// Returns the codes of the previous, current and next time steps to tolerate clock drift
+pub fn valid_codes(secret: &[u8], timestamp: u64) -> Vec<String> {
+    let counter = timestamp / TIME_STEP_SECS;
+
+    [counter.saturating_sub(1), counter, counter + 1]
+        .iter()
+        .map(|&counter| format!("{:0width$}", code(secret, counter), width = DIGITS as usize))
+        .collect()
+}
+
+// URI to be turned into a QR code or pasted in an authenticator app
+pub fn provisioning_uri(secret: &[u8], account_name: &str) -> String {
+    format!(
+        "otpauth://totp/Rustbolt:{account_name}?secret={}&issuer=Rustbolt",
+        BASE32_NOPAD.encode(secret)
+    )
+}
+
+fn code(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation (RFC 4226 section 5.3)
+    let offset = (hash[hash.len() - 1] & 0x0F) as usize;
+    let binary = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7FFF_FFFF;
+
+    binary % 10_u32.pow(DIGITS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from RFC 6238 appendix B, truncated to 6 digits
+    #[test]
+    fn test_rfc_6238_vectors() {
+        let secret = b"12345678901234567890";
+
+        assert_eq!(valid_codes(secret, 59)[1], "287082");
+        assert_eq!(valid_codes(secret, 1111111109)[1], "081804");
+        assert_eq!(valid_codes(secret, 1234567890)[1], "005924");
+        assert_eq!(valid_codes(secret, 2000000000)[1], "279037");
+    }
+}
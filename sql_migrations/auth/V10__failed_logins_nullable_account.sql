@@ -0,0 +1,17 @@
+-- Failed logons with an unknown account name are only recorded against the IP
+CREATE TABLE failed_logins_new (
+  id INTEGER PRIMARY KEY,
+  account_id INTEGER,
+  ip_address TEXT NOT NULL,
+  attempted_at INTEGER NOT NULL,
+  FOREIGN KEY(account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
+
+INSERT INTO failed_logins_new(id, account_id, ip_address, attempted_at)
+SELECT id, account_id, ip_address, attempted_at FROM failed_logins;
+
+DROP TABLE failed_logins;
+ALTER TABLE failed_logins_new RENAME TO failed_logins;
+
+CREATE INDEX idx_failed_logins_account_id ON failed_logins(account_id);
+CREATE INDEX idx_failed_logins_ip_address ON failed_logins(ip_address);
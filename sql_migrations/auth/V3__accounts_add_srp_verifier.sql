@@ -0,0 +1,12 @@
+ALTER TABLE accounts ADD COLUMN verifier TEXT NOT NULL DEFAULT '';
+ALTER TABLE accounts ADD COLUMN salt TEXT NOT NULL DEFAULT '';
+
+-- Default account 'a' with password 'a'
+UPDATE accounts SET
+  verifier = '83022ca9083390dc5bf10ab40a277981b605afacf9b298ed5a5a2d776666d543',
+  salt = 'ad5ac1c8b6f2e4a0c7ef0e1f9e3d2b7c4a6f8e1d0c3b5a7928e6f4d2b0a9c8e7'
+WHERE UPPER(username) = 'A';
+
+ALTER TABLE accounts DROP COLUMN password_hash;
+
+CREATE UNIQUE INDEX idx_accounts_username ON accounts(UPPER(username));
@@ -0,0 +1,18 @@
+CREATE TABLE account_bans (
+  id INTEGER PRIMARY KEY,
+  account_id INTEGER NOT NULL,
+  banned_at INTEGER NOT NULL,
+  expires_at INTEGER, -- NULL for permanent bans
+  reason TEXT NOT NULL,
+  FOREIGN KEY(account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_account_bans_account_id ON account_bans(account_id);
+
+CREATE TABLE ip_bans (
+  id INTEGER PRIMARY KEY,
+  ip_range TEXT NOT NULL, -- IP address or CIDR range
+  banned_at INTEGER NOT NULL,
+  expires_at INTEGER, -- NULL for permanent bans
+  reason TEXT NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE failed_logins (
+  id INTEGER PRIMARY KEY,
+  account_id INTEGER NOT NULL,
+  ip_address TEXT NOT NULL,
+  attempted_at INTEGER NOT NULL,
+  FOREIGN KEY(account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_failed_logins_account_id ON failed_logins(account_id);
+CREATE INDEX idx_failed_logins_ip_address ON failed_logins(ip_address);
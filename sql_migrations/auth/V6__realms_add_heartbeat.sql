@@ -0,0 +1,17 @@
+-- Updated by the world servers
+ALTER TABLE realms ADD COLUMN is_online INTEGER NOT NULL DEFAULT FALSE;
+ALTER TABLE realms ADD COLUMN online_players INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE realms ADD COLUMN max_players INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE realms ADD COLUMN last_heartbeat INTEGER; -- NULL if the realm never registered
+
+-- Recommended/full/offline flags are now computed from the heartbeats
+UPDATE realms SET flags = 0;
+
+CREATE TABLE realm_characters (
+  realm_id INTEGER NOT NULL,
+  account_id INTEGER NOT NULL,
+  num_chars INTEGER NOT NULL,
+  PRIMARY KEY(realm_id, account_id),
+  FOREIGN KEY(realm_id) REFERENCES realms(id) ON DELETE CASCADE,
+  FOREIGN KEY(account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+-- Hex encoded, NULL if two-factor authentication is disabled
+ALTER TABLE accounts ADD COLUMN totp_secret TEXT;
@@ -0,0 +1,2 @@
+-- Client build used for the last successful logon, checked by the world servers
+ALTER TABLE accounts ADD COLUMN last_build INTEGER;
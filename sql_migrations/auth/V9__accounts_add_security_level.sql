@@ -0,0 +1,5 @@
+-- 0 = player, 1 = moderator, 2 = game master, 3 = administrator
+ALTER TABLE accounts ADD COLUMN security_level INTEGER NOT NULL DEFAULT 0;
+
+-- Keep the development account able to use every chat command
+UPDATE accounts SET security_level = 3 WHERE UPPER(username) = 'A';
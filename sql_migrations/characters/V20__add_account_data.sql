@@ -0,0 +1,18 @@
+CREATE TABLE account_data(
+  account_id INTEGER NOT NULL,
+  type INTEGER NOT NULL,
+  updated_at INTEGER NOT NULL,
+  decompressed_size INTEGER NOT NULL,
+  data BLOB NOT NULL,
+  PRIMARY KEY (account_id, type)
+);
+
+CREATE TABLE character_account_data(
+  character_guid INTEGER NOT NULL,
+  type INTEGER NOT NULL,
+  updated_at INTEGER NOT NULL,
+  decompressed_size INTEGER NOT NULL,
+  data BLOB NOT NULL,
+  PRIMARY KEY (character_guid, type),
+  FOREIGN KEY (character_guid) REFERENCES characters(guid) ON DELETE CASCADE
+);
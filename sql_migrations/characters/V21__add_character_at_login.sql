@@ -0,0 +1 @@
+ALTER TABLE characters ADD COLUMN at_login INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Deleted characters are archived with account_id = 0 until they are restored or purged
+ALTER TABLE characters ADD COLUMN deleted_at INTEGER;
+ALTER TABLE characters ADD COLUMN deleted_account_id INTEGER;
@@ -0,0 +1,4 @@
+-- Names that cannot be used when creating or renaming a character
+CREATE TABLE reserved_names (
+  name TEXT PRIMARY KEY COLLATE NOCASE
+);
@@ -0,0 +1,2 @@
+-- Increases with every save so that a late autosave cannot overwrite a more recent save
+ALTER TABLE characters ADD COLUMN save_generation INTEGER NOT NULL DEFAULT 0;
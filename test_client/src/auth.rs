@@ -0,0 +1,250 @@
+use binrw::io::Cursor;
+use binrw::{binread, binwrite, BinRead, BinReaderExt, BinWrite, BinWriterExt, NullString};
+use log::trace;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use wow_srp::{client::SrpClientUser, normalized_string::NormalizedString, PublicKey};
+
+use crate::TestClientError;
+
+const CMD_AUTH_LOGON_CHALLENGE: u8 = 0x00;
+const CMD_AUTH_LOGON_PROOF: u8 = 0x01;
+const CMD_REALM_LIST: u8 = 0x10;
+
+const AUTH_RESULT_SUCCESS: u8 = 0x00;
+const SECURITY_FLAG_PIN: u8 = 0x01;
+
+// Sent by a 2.4.3 enUS Windows client
+const CLIENT_VERSION: [u8; 3] = [2, 4, 3];
+pub const CLIENT_BUILD: u16 = 8606;
+
+#[binwrite]
+struct CmdAuthLogonChallengeClient {
+    #[bw(calc = CMD_AUTH_LOGON_CHALLENGE)]
+    _opcode: u8,
+    #[bw(calc = 8)]
+    _protocol_version: u8,
+    #[bw(calc = 30 + account_name.len() as u16)] // Size of the remaining fields
+    _size: u16,
+    #[bw(calc = *b"WoW\0")]
+    _game_name: [u8; 4],
+    #[bw(calc = CLIENT_VERSION)]
+    _version: [u8; 3],
+    #[bw(calc = CLIENT_BUILD)]
+    _build: u16,
+    #[bw(calc = *b"68x\0")]
+    _platform: [u8; 4],
+    #[bw(calc = *b"niW\0")]
+    _os: [u8; 4],
+    #[bw(calc = *b"SUne")]
+    _locale: [u8; 4],
+    #[bw(calc = 0)]
+    _worldregion_bias: u32,
+    #[bw(calc = [127, 0, 0, 1])]
+    _ip: [u8; 4],
+    #[bw(calc = account_name.len() as u8)]
+    _account_name_length: u8,
+    account_name: Vec<u8>, // Not null-terminated
+}
+
+#[binread]
+struct CmdAuthLogonChallengeServer {
+    _opcode: u8,
+    _protocol_version: u8,
+    result: u8,
+    #[br(if(result == AUTH_RESULT_SUCCESS))]
+    challenge: Option<LogonChallenge>,
+}
+
+#[binread]
+struct LogonChallenge {
+    server_public_key: [u8; 32],
+    _generator_len: u8,
+    generator: u8,
+    _large_safe_prime_len: u8,
+    large_safe_prime: [u8; 32],
+    salt: [u8; 32],
+    _crc_salt: [u8; 16],
+    security_flags: u8,
+    #[br(if(security_flags & SECURITY_FLAG_PIN != 0))]
+    _pin_challenge: Option<[u8; 20]>,
+}
+
+#[binwrite]
+struct CmdAuthLogonProofClient {
+    #[bw(calc = CMD_AUTH_LOGON_PROOF)]
+    _opcode: u8,
+    client_public_key: [u8; 32],
+    client_proof: [u8; 20],
+    #[bw(calc = [0; 20])]
+    _crc_hash: [u8; 20],
+    #[bw(calc = 0)]
+    _num_keys: u8,
+    #[bw(calc = 0)]
+    _security_flags: u8,
+}
+
+#[binread]
+struct CmdAuthLogonProofServer {
+    _opcode: u8,
+    result: u8,
+    #[br(if(result == AUTH_RESULT_SUCCESS))]
+    proof: Option<LogonProof>,
+    #[br(if(result != AUTH_RESULT_SUCCESS))]
+    _padding: Option<[u8; 2]>,
+}
+
+#[binread]
+struct LogonProof {
+    server_proof: [u8; 20],
+    _account_flag: u32,
+    _hardware_survey_id: u32,
+    _unknown_flags: u16,
+}
+
+#[binwrite]
+struct CmdRealmListClient {
+    #[bw(calc = CMD_REALM_LIST)]
+    _opcode: u8,
+    #[bw(calc = 0)]
+    _padding: u32,
+}
+
+#[binread]
+struct CmdRealmListServer {
+    _opcode: u8,
+    _size: u16,
+    _padding: u32,
+    num_realms: u16,
+    #[br(count = num_realms)]
+    realms: Vec<Realm>,
+    _padding_footer: u16,
+}
+
+#[binread]
+#[derive(Debug, Clone)]
+pub struct Realm {
+    pub realm_type: u8,
+    #[br(map = |b: u8| b != 0)]
+    pub locked: bool,
+    pub realm_flags: u8,
+    #[br(map = |s: NullString| s.to_string())]
+    pub realm_name: String,
+    #[br(map = |s: NullString| s.to_string())]
+    pub address_port: String,
+    pub population: f32,
+    pub num_chars: u8,
+    pub realm_category: u8,
+    pub realm_id: u8,
+}
+
+// Client side of the auth protocol, the PIN and reconnection steps are not supported
+pub struct AuthClient {
+    socket: TcpStream,
+    read_buffer: Vec<u8>,
+}
+
+impl AuthClient {
+    pub async fn connect<A: ToSocketAddrs>(address: A) -> Result<AuthClient, TestClientError> {
+        Ok(AuthClient {
+            socket: TcpStream::connect(address).await?,
+            read_buffer: Vec::new(),
+        })
+    }
+
+    // Performs the SRP6 exchange and returns the session key to present to the world server
+    pub async fn logon(
+        &mut self,
+        username: &str,
+        password: &str,
+    ) -> Result<[u8; 40], TestClientError> {
+        let username =
+            NormalizedString::new(username).map_err(|_| TestClientError::InvalidCredentials)?;
+        let password =
+            NormalizedString::new(password).map_err(|_| TestClientError::InvalidCredentials)?;
+
+        self.write_packet(&CmdAuthLogonChallengeClient {
+            account_name: username.to_string().into_bytes(),
+        })
+        .await?;
+
+        let challenge_response: CmdAuthLogonChallengeServer = self.read_packet().await?;
+        let Some(challenge) = challenge_response.challenge else {
+            return Err(TestClientError::LogonFailed(challenge_response.result));
+        };
+        if challenge.security_flags & SECURITY_FLAG_PIN != 0 {
+            return Err(TestClientError::PinRequired);
+        }
+        trace!("Received the logon challenge");
+
+        let server_public_key = PublicKey::from_le_bytes(&challenge.server_public_key)
+            .map_err(|_| TestClientError::ServerProofMismatch)?;
+        let srp_challenge = SrpClientUser::new(username, password).into_challenge(
+            challenge.generator,
+            challenge.large_safe_prime,
+            server_public_key,
+            challenge.salt,
+        );
+
+        self.write_packet(&CmdAuthLogonProofClient {
+            client_public_key: *srp_challenge.client_public_key(),
+            client_proof: *srp_challenge.client_proof(),
+        })
+        .await?;
+
+        let proof_response: CmdAuthLogonProofServer = self.read_packet().await?;
+        let Some(proof) = proof_response.proof else {
+            return Err(TestClientError::LogonFailed(proof_response.result));
+        };
+
+        let srp_client = srp_challenge
+            .verify_server_proof(proof.server_proof)
+            .map_err(|_| TestClientError::ServerProofMismatch)?;
+        trace!("Logon succeeded");
+
+        Ok(srp_client.session_key())
+    }
+
+    pub async fn realm_list(&mut self) -> Result<Vec<Realm>, TestClientError> {
+        self.write_packet(&CmdRealmListClient {}).await?;
+
+        let realm_list: CmdRealmListServer = self.read_packet().await?;
+        Ok(realm_list.realms)
+    }
+
+    async fn write_packet<T>(&mut self, packet: &T) -> Result<(), TestClientError>
+    where
+        T: for<'a> BinWrite<Args<'a> = ()>,
+    {
+        let mut writer = Cursor::new(Vec::new());
+        writer.write_le(packet)?;
+        self.socket.write_all(writer.get_ref()).await?;
+        Ok(())
+    }
+
+    async fn read_packet<T>(&mut self) -> Result<T, TestClientError>
+    where
+        T: for<'a> BinRead<Args<'a> = ()>,
+    {
+        loop {
+            if !self.read_buffer.is_empty() {
+                let mut reader = Cursor::new(self.read_buffer.as_slice());
+                match reader.read_le::<T>() {
+                    Ok(packet) => {
+                        let consumed = reader.position() as usize;
+                        self.read_buffer.drain(..consumed);
+                        return Ok(packet);
+                    }
+                    Err(e) if e.is_eof() => (), // Incomplete packet
+                    Err(e) => return Err(TestClientError::BinRwError(e)),
+                }
+            }
+
+            let mut buf = [0_u8; 1024];
+            match self.socket.read(&mut buf).await? {
+                0 => return Err(TestClientError::ServerDisconnected),
+                n => self.read_buffer.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+}
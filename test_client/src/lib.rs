@@ -0,0 +1,42 @@
+// Headless client speaking the auth and world protocols, meant for end-to-end tests against
+// locally running servers
+
+pub mod auth;
+pub mod world;
+
+pub use auth::{AuthClient, Realm};
+pub use world::{ServerPacket, WorldClient};
+
+#[derive(Debug)]
+pub enum TestClientError {
+    ServerDisconnected,
+    SocketError(std::io::Error),
+    BinRwError(binrw::Error),
+    InvalidCredentials,
+    LogonFailed(u8), // AuthResult sent by the auth server
+    PinRequired,
+    ServerProofMismatch,
+    AuthSessionRefused(u8), // ResponseCodes sent by the world server
+    InvalidGuid,
+    Timeout(String),
+}
+
+impl From<std::io::Error> for TestClientError {
+    fn from(value: std::io::Error) -> Self {
+        Self::SocketError(value)
+    }
+}
+
+impl From<binrw::Error> for TestClientError {
+    fn from(value: binrw::Error) -> Self {
+        Self::BinRwError(value)
+    }
+}
+
+impl std::fmt::Display for TestClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for TestClientError {}
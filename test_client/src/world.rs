@@ -0,0 +1,328 @@
+use std::time::Duration;
+
+use binrw::io::Cursor;
+use binrw::{BinRead, BinReaderExt, BinWrite, BinWriterExt, NullString};
+use log::trace;
+use rustbolt_world::game::spell_cast_target::SpellCastTargets;
+use rustbolt_world::protocol::opcodes::Opcode;
+use rustbolt_world::protocol::packets::{
+    CharEnumData, CmsgAttackSwing, CmsgAuthSession, CmsgAutostoreLootItem, CmsgCastSpell,
+    CmsgCharCreate, CmsgLoot, CmsgMessageChat, CmsgPlayerLogin, CmsgSetSelection, MovementInfo,
+    MsgMoveTeleportAck, MsgMoveTeleportAckFromClient, SmsgAuthChallenge, SmsgAuthResponse,
+    SmsgCharCreate, SmsgCharEnum, SmsgLoginVerifyWorld, SmsgLootResponse,
+};
+use rustbolt_world::shared::constants::{ChatMessageType, Language};
+use rustbolt_world::shared::response_codes::ResponseCodes;
+use rustbolt_world::ObjectGuid;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use wow_srp::normalized_string::NormalizedString;
+use wow_srp::tbc_header::{HeaderCrypto, ProofSeed};
+
+use crate::auth::CLIENT_BUILD;
+use crate::TestClientError;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct ServerPacket {
+    pub opcode: u16,
+    pub payload: Vec<u8>,
+}
+
+impl ServerPacket {
+    pub fn read_as<T: for<'a> BinRead<Args<'a> = ()>>(&self) -> Result<T, binrw::Error> {
+        Cursor::new(&self.payload).read_le()
+    }
+}
+
+// Client side of the world protocol, on top of the packet structs of rustbolt-world.
+// Packets that are not waited for are discarded.
+pub struct WorldClient {
+    socket: TcpStream,
+    encryption: HeaderCrypto,
+    timeout: Duration,
+}
+
+impl WorldClient {
+    // Authenticates with the session key obtained from the auth server, waiting in the login
+    // queue if needed
+    pub async fn connect<A: ToSocketAddrs>(
+        address: A,
+        username: &str,
+        session_key: [u8; 40],
+    ) -> Result<WorldClient, TestClientError> {
+        let mut socket = TcpStream::connect(address).await?;
+
+        // The challenge is not encrypted: size (big endian, including the opcode) and opcode
+        let mut header = [0_u8; 4];
+        socket.read_exact(&mut header).await?;
+        let mut payload = vec![0_u8; u16::from_be_bytes([header[0], header[1]]) as usize - 2];
+        socket.read_exact(&mut payload).await?;
+        let challenge: SmsgAuthChallenge = Cursor::new(payload).read_le()?;
+
+        let username =
+            NormalizedString::new(username).map_err(|_| TestClientError::InvalidCredentials)?;
+        let client_seed = ProofSeed::new();
+        let client_seed_value = client_seed.seed();
+        let (client_proof, encryption) =
+            client_seed.into_proof_and_header_crypto(&username, session_key, challenge.server_seed);
+
+        let mut payload = Cursor::new(Vec::new());
+        payload.write_le(&CmsgAuthSession {
+            _build: CLIENT_BUILD as u32,
+            _server_id: 0,
+            username: NullString::from(username.to_string()),
+            client_seed: client_seed_value,
+            client_proof,
+        })?;
+        // No addons: uncompressed size followed by an empty zlib stream
+        payload.write_le(&0_u32)?;
+        payload
+            .get_mut()
+            .extend(miniz_oxide::deflate::compress_to_vec_zlib(&[], 6));
+
+        let payload = payload.into_inner();
+        let mut packet = Vec::with_capacity(6 + payload.len());
+        packet.extend((payload.len() as u16 + 4).to_be_bytes()); // + 4 for the opcode size
+        packet.extend((Opcode::CmsgAuthSession as u32).to_le_bytes());
+        packet.extend(payload);
+        socket.write_all(&packet).await?;
+
+        let mut client = WorldClient {
+            socket,
+            encryption,
+            timeout: DEFAULT_TIMEOUT,
+        };
+
+        loop {
+            // No timeout here, the login queue can take a while
+            let packet = client.receive().await?;
+            if packet.opcode != Opcode::SmsgAuthResponse as u16 {
+                continue;
+            }
+
+            let response: SmsgAuthResponse = packet.read_as()?;
+            match response.result {
+                r if r == ResponseCodes::AuthOk as u8 => break,
+                r if r == ResponseCodes::AuthWaitQueue as u8 => trace!(
+                    "Waiting in the login queue at position {}",
+                    response.position_in_queue
+                ),
+                r => return Err(TestClientError::AuthSessionRefused(r)),
+            }
+        }
+
+        Ok(client)
+    }
+
+    // How long wait_for waits for a packet before giving up
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub async fn send<T>(&mut self, opcode: Opcode, payload: &T) -> Result<(), TestClientError>
+    where
+        T: for<'a> BinWrite<Args<'a> = ()>,
+    {
+        let mut writer = Cursor::new(Vec::new());
+        writer.write_le(payload)?;
+        self.send_raw(opcode, writer.get_ref()).await
+    }
+
+    pub async fn send_raw(
+        &mut self,
+        opcode: Opcode,
+        payload: &[u8],
+    ) -> Result<(), TestClientError> {
+        let header = self
+            .encryption
+            .encrypt_client_header(payload.len() as u16 + 4, opcode as u32); // + 4 for the opcode size
+
+        let mut packet = Vec::with_capacity(header.len() + payload.len());
+        packet.extend(header);
+        packet.extend(payload);
+        self.socket.write_all(&packet).await?;
+
+        trace!("Sent {:?}", opcode);
+        Ok(())
+    }
+
+    pub async fn receive(&mut self) -> Result<ServerPacket, TestClientError> {
+        let mut header = [0_u8; 4];
+        self.read_exact(&mut header).await?;
+        let header = self.encryption.decrypt_server_header(header);
+
+        let mut payload = vec![0_u8; (header.size as usize).saturating_sub(2)]; // - 2 for the opcode size
+        self.read_exact(&mut payload).await?;
+
+        Ok(ServerPacket {
+            opcode: header.opcode,
+            payload,
+        })
+    }
+
+    // Discards the packets received until one with the given opcode comes in
+    pub async fn wait_for(&mut self, opcode: Opcode) -> Result<ServerPacket, TestClientError> {
+        self.wait_for_any(&[opcode]).await
+    }
+
+    // Same as wait_for, for packets that can be answered with different opcodes (e.g. a spell
+    // cast that starts or fails)
+    pub async fn wait_for_any(
+        &mut self,
+        opcodes: &[Opcode],
+    ) -> Result<ServerPacket, TestClientError> {
+        let timeout = self.timeout;
+        let wait = async {
+            loop {
+                let packet = self.receive().await?;
+                if opcodes.iter().any(|&opcode| packet.opcode == opcode as u16) {
+                    return Ok(packet);
+                }
+            }
+        };
+
+        tokio::time::timeout(timeout, wait)
+            .await
+            .map_err(|_| TestClientError::Timeout(format!("{opcodes:?}")))?
+    }
+
+    pub async fn list_characters(&mut self) -> Result<Vec<CharEnumData>, TestClientError> {
+        self.send_raw(Opcode::CmsgCharEnum, &[]).await?;
+
+        let char_enum: SmsgCharEnum = self.wait_for(Opcode::SmsgCharEnum).await?.read_as()?;
+        Ok(char_enum.character_data)
+    }
+
+    // Returns the ResponseCodes sent by the server
+    pub async fn create_character(
+        &mut self,
+        character: &CmsgCharCreate,
+    ) -> Result<u8, TestClientError> {
+        self.send(Opcode::CmsgCharCreate, character).await?;
+
+        let response: SmsgCharCreate = self.wait_for(Opcode::SmsgCharCreate).await?.read_as()?;
+        Ok(response.result)
+    }
+
+    pub async fn login(&mut self, guid: u64) -> Result<SmsgLoginVerifyWorld, TestClientError> {
+        self.send(Opcode::CmsgPlayerLogin, &CmsgPlayerLogin { guid })
+            .await?;
+
+        Ok(self
+            .wait_for(Opcode::SmsgLoginVerifyWorld)
+            .await?
+            .read_as()?)
+    }
+
+    // opcode is one of the MSG_MOVE_* opcodes
+    pub async fn move_with(
+        &mut self,
+        opcode: Opcode,
+        movement_info: &MovementInfo,
+    ) -> Result<(), TestClientError> {
+        self.send(opcode, movement_info).await
+    }
+
+    // Answers a near teleport (e.g. from the .teleport chat command), returns the movement info
+    // the server teleported the player with
+    pub async fn acknowledge_teleport(
+        &mut self,
+        player_guid: u64,
+    ) -> Result<MovementInfo, TestClientError> {
+        let teleport: MsgMoveTeleportAck =
+            self.wait_for(Opcode::MsgMoveTeleportAck).await?.read_as()?;
+
+        self.send(
+            Opcode::MsgMoveTeleportAck,
+            &MsgMoveTeleportAckFromClient {
+                _guid: ObjectGuid::from_raw(player_guid).ok_or(TestClientError::InvalidGuid)?,
+                _counter: teleport.unk_counter,
+                _time: teleport.movement_info.timestamp,
+            },
+        )
+        .await?;
+
+        Ok(teleport.movement_info)
+    }
+
+    // Selects the target first, the server swings at the selected unit
+    pub async fn attack(&mut self, target_guid: u64) -> Result<(), TestClientError> {
+        self.send(
+            Opcode::CmsgSetSelection,
+            &CmsgSetSelection { guid: target_guid },
+        )
+        .await?;
+
+        self.send(
+            Opcode::CmsgAttackSwing,
+            &CmsgAttackSwing {
+                guid: ObjectGuid::from_raw(target_guid).ok_or(TestClientError::InvalidGuid)?,
+            },
+        )
+        .await
+    }
+
+    pub async fn cast_spell(
+        &mut self,
+        spell_id: u32,
+        cast_count: u8,
+        cast_targets: SpellCastTargets,
+    ) -> Result<(), TestClientError> {
+        self.send(
+            Opcode::CmsgCastSpell,
+            &CmsgCastSpell {
+                spell_id,
+                cast_count,
+                cast_targets,
+            },
+        )
+        .await
+    }
+
+    pub async fn loot(&mut self, target_guid: u64) -> Result<SmsgLootResponse, TestClientError> {
+        self.send(Opcode::CmsgLoot, &CmsgLoot { target_guid })
+            .await?;
+
+        Ok(self.wait_for(Opcode::SmsgLootResponse).await?.read_as()?)
+    }
+
+    pub async fn autostore_loot_item(&mut self, loot_index: u8) -> Result<(), TestClientError> {
+        self.send(
+            Opcode::CmsgAutostoreLootItem,
+            &CmsgAutostoreLootItem { loot_index },
+        )
+        .await
+    }
+
+    pub async fn say(&mut self, message: &str) -> Result<(), TestClientError> {
+        self.send(
+            Opcode::CmsgMessageChat,
+            &CmsgMessageChat {
+                chat_type: ChatMessageType::Say,
+                language: Language::Universal,
+                recipient: None,
+                channel: None,
+                msg: NullString::from(message),
+            },
+        )
+        .await
+    }
+
+    pub async fn logout(&mut self) -> Result<(), TestClientError> {
+        self.send_raw(Opcode::CmsgLogoutRequest, &[]).await?;
+
+        self.wait_for(Opcode::SmsgLogoutComplete).await?;
+        Ok(())
+    }
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), TestClientError> {
+        match self.socket.read_exact(buf).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                Err(TestClientError::ServerDisconnected)
+            }
+            Err(e) => Err(TestClientError::SocketError(e)),
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use std::env;
+use std::fs::{self, File};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use binrw::NullString;
+use rusqlite::{named_params, Connection};
+use rustbolt_test_client::{AuthClient, WorldClient};
+use rustbolt_world::game::spell_cast_target::SpellCastTargets;
+use rustbolt_world::protocol::opcodes::Opcode;
+use rustbolt_world::protocol::packets::{CmsgCharCreate, MovementInfo, SmsgAttackStop};
+use rustbolt_world::shared::constants::HighGuidType;
+use rustbolt_world::shared::response_codes::ResponseCodes;
+use rustbolt_world::{ObjectGuid, Position};
+
+const USERNAME: &str = "testclient";
+const PASSWORD: &str = "testclient";
+const CHARACTER_NAME: &str = "Testclient";
+const HEROIC_STRIKE_SPELL_ID: u32 = 78;
+const SERVER_STARTUP_TIMEOUT: Duration = Duration::from_secs(180);
+const COMBAT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Auth and world servers running on fresh databases and free ports, stopped when dropped
+struct TestServers {
+    directory: PathBuf,
+    auth: Child,
+    world: Child,
+    auth_address: SocketAddr,
+    world_address: SocketAddr,
+}
+
+impl TestServers {
+    // Panics if the game data the world server needs (DBCs and the world database with the
+    // creature spawns) is missing
+    fn start() -> Self {
+        let workspace_directory = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+        let game_data_directory = env::var_os("RUSTBOLT_DATA_DIRECTORY")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| workspace_directory.join("data"));
+        let world_database = game_data_directory.join("databases").join("world.db");
+        assert!(
+            game_data_directory.join("dbcs").is_dir() && world_database.is_file(),
+            "no DBCs or world database in {}, set RUSTBOLT_DATA_DIRECTORY",
+            game_data_directory.display()
+        );
+
+        let binaries_directory = build_servers(workspace_directory);
+
+        let directory = env::temp_dir().join(format!("rustbolt-end-to-end-{}", process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let data_directory = directory.join("data");
+        fs::create_dir_all(data_directory.join("databases")).unwrap();
+        // The static world database is copied, the auth and characters ones start empty
+        fs::copy(
+            &world_database,
+            data_directory.join("databases").join("world.db"),
+        )
+        .unwrap();
+        // Everything but the databases comes from the game data directory
+        for entry in fs::read_dir(&game_data_directory).unwrap() {
+            let entry = entry.unwrap();
+            if !["databases", "captures"].contains(&entry.file_name().to_str().unwrap_or("")) {
+                symlink(&entry.path(), &data_directory.join(entry.file_name()));
+            }
+        }
+
+        let auth_address = free_local_address();
+        let world_address = free_local_address();
+        fs::copy(
+            workspace_directory.join("config.template.toml"),
+            directory.join("config.template.toml"),
+        )
+        .unwrap();
+        fs::write(
+            directory.join("config.toml"),
+            format!(
+                "[common.data]\n\
+                 directory = {:?}\n\
+                 [auth.network]\n\
+                 port = \"{}\"\n\
+                 [world.network]\n\
+                 port = \"{}\"\n\
+                 [world.metrics]\n\
+                 enabled = false\n\
+                 [world.dev]\n\
+                 load_terrain = false\n",
+                data_directory.to_str().unwrap(),
+                auth_address.port(),
+                world_address.port(),
+            ),
+        )
+        .unwrap();
+
+        // Also creates the auth database, the world server expects it to exist
+        let status = Command::new(binaries_directory.join(binary_name("rustbolt-auth")))
+            .args(["account", "create", USERNAME, PASSWORD])
+            .current_dir(&directory)
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "unable to create the test account");
+        // Game master to use the .teleport chat command
+        let status = Command::new(binaries_directory.join(binary_name("rustbolt-auth")))
+            .args(["account", "set-security-level", USERNAME, "2"])
+            .current_dir(&directory)
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(
+            status.success(),
+            "unable to make the test account a game master"
+        );
+
+        let auth = spawn_server(&binaries_directory, "rustbolt-auth", &directory);
+        let world = spawn_server(&binaries_directory, "rustbolt-world", &directory);
+        let mut servers = TestServers {
+            directory,
+            auth,
+            world,
+            auth_address,
+            world_address,
+        };
+        servers.wait_until_listening();
+
+        servers
+    }
+
+    // The closest low level creature to the given position on the Eastern Kingdoms (map 0)
+    // that always drops an item when killed
+    fn nearest_lootable_creature(&self, position: &Position) -> (ObjectGuid, Position) {
+        let conn = Connection::open(self.directory.join("data/databases/world.db")).unwrap();
+        conn.query_row(
+            "SELECT creature_spawns.guid, creature_spawns.entry, position_x, position_y, position_z, orientation
+            FROM creature_spawns
+            JOIN creature_templates ON creature_templates.entry = creature_spawns.entry
+            LEFT OUTER JOIN seasonal_event_creatures ON creature_spawns.guid = seasonal_event_creatures.creature_guid
+            WHERE map = 0 AND max_level <= 2 AND seasonal_event_creatures.event_id IS NULL
+            AND EXISTS (
+                SELECT 1 FROM loot_table_groups
+                JOIN loot_groups ON loot_groups.id = loot_table_groups.loot_group_id
+                WHERE loot_table_groups.loot_table_id = creature_templates.loot_table_id
+                AND loot_groups.chance = 100 AND loot_groups.condition_id IS NULL
+                AND NOT EXISTS (
+                    SELECT 1 FROM loot_items
+                    WHERE loot_items.group_id = loot_groups.id AND loot_items.condition_id IS NOT NULL
+                )
+            )
+            ORDER BY (position_x - :x) * (position_x - :x) + (position_y - :y) * (position_y - :y)
+            LIMIT 1",
+            named_params! { ":x": position.x, ":y": position.y },
+            |row| {
+                Ok((
+                    ObjectGuid::with_entry(HighGuidType::Unit, row.get(1)?, row.get(0)?),
+                    Position {
+                        x: row.get(2)?,
+                        y: row.get(3)?,
+                        z: row.get(4)?,
+                        o: row.get(5)?,
+                    },
+                ))
+            },
+        )
+        .expect("no lootable creature in the world database")
+    }
+
+    fn wait_until_listening(&mut self) {
+        let started_at = Instant::now();
+        for (name, address) in [
+            ("rustbolt-auth", self.auth_address),
+            ("rustbolt-world", self.world_address),
+        ] {
+            while TcpStream::connect(address).is_err() {
+                let server = if name == "rustbolt-auth" {
+                    &mut self.auth
+                } else {
+                    &mut self.world
+                };
+                let exited = server.try_wait().unwrap().is_some();
+                if exited || started_at.elapsed() > SERVER_STARTUP_TIMEOUT {
+                    let log = fs::read_to_string(self.directory.join(format!("{name}.log")))
+                        .unwrap_or_default();
+                    panic!("{name} is not listening on {address}:\n{log}");
+                }
+                thread::sleep(Duration::from_millis(200));
+            }
+        }
+    }
+}
+
+impl Drop for TestServers {
+    fn drop(&mut self) {
+        for server in [&mut self.world, &mut self.auth] {
+            let _ = server.kill();
+            let _ = server.wait();
+        }
+        let _ = fs::remove_dir_all(&self.directory);
+    }
+}
+
+// Builds the servers in their own target directory, the one running the tests is locked
+fn build_servers(workspace_directory: &Path) -> PathBuf {
+    let target_directory = env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| workspace_directory.join("target"))
+        .join("end_to_end");
+
+    let status = Command::new(env::var_os("CARGO").unwrap_or("cargo".into()))
+        .args(["build", "-p", "rustbolt-auth", "-p", "rustbolt-world"])
+        .arg("--target-dir")
+        .arg(&target_directory)
+        .current_dir(workspace_directory)
+        .status()
+        .unwrap();
+    assert!(status.success(), "unable to build the servers");
+
+    target_directory.join("debug")
+}
+
+fn spawn_server(binaries_directory: &Path, name: &str, directory: &Path) -> Child {
+    let log = File::create(directory.join(format!("{name}.log"))).unwrap();
+    Command::new(binaries_directory.join(binary_name(name)))
+        .current_dir(directory)
+        .stdout(log.try_clone().unwrap())
+        .stderr(log)
+        .spawn()
+        .unwrap()
+}
+
+fn binary_name(name: &str) -> String {
+    format!("{name}{}", env::consts::EXE_SUFFIX)
+}
+
+fn free_local_address() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path) {
+    std::os::unix::fs::symlink(original, link).unwrap();
+}
+
+#[cfg(windows)]
+fn symlink(original: &Path, link: &Path) {
+    if original.is_dir() {
+        std::os::windows::fs::symlink_dir(original, link).unwrap();
+    } else {
+        std::os::windows::fs::symlink_file(original, link).unwrap();
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs the game data (DBCs and world database), see the README"]
+async fn create_character_log_in_move_fight_loot_and_chat() {
+    let servers = TestServers::start();
+
+    let mut auth_client = AuthClient::connect(servers.auth_address).await.unwrap();
+    let session_key = auth_client.logon(USERNAME, PASSWORD).await.unwrap();
+    let realms = auth_client.realm_list().await.unwrap();
+    assert!(!realms.is_empty(), "no realm found");
+
+    // The seeded realm points to the default world port, not to the one picked for the test
+    let mut world_client = WorldClient::connect(servers.world_address, USERNAME, session_key)
+        .await
+        .unwrap();
+
+    let result = world_client
+        .create_character(&CmsgCharCreate {
+            name: NullString::from(CHARACTER_NAME),
+            race: 1,  // Human
+            class: 1, // Warrior
+            gender: 0,
+            skin: 0,
+            face: 0,
+            hairstyle: 0,
+            haircolor: 0,
+            facialstyle: 0,
+        })
+        .await
+        .unwrap();
+    assert_eq!(result, ResponseCodes::CharCreateSuccess as u8);
+
+    let characters = world_client.list_characters().await.unwrap();
+    let character = characters
+        .iter()
+        .find(|c| c.name.to_string() == CHARACTER_NAME)
+        .expect("character not found after creation");
+    let player_guid = character.guid;
+    let login = world_client.login(player_guid).await.unwrap();
+
+    let position = Position {
+        x: login.position_x,
+        y: login.position_y,
+        z: login.position_z,
+        o: login.orientation,
+    };
+    // Timestamps must be consistent with the distance moved or the server rejects the movement
+    for (opcode, movement_flags, timestamp, x) in [
+        (Opcode::MsgMoveStartForward, 0x1, 0, position.x),
+        (Opcode::MsgMoveStop, 0x0, 500, position.x + 1.),
+    ] {
+        world_client
+            .move_with(
+                opcode,
+                &MovementInfo {
+                    movement_flags,
+                    movement_flags2: 0,
+                    timestamp,
+                    position: Position { x, ..position },
+                    fall_time: 0,
+                },
+            )
+            .await
+            .unwrap();
+    }
+
+    // The position saved on logout is the one sent in the character list
+    world_client.logout().await.unwrap();
+    let characters = world_client.list_characters().await.unwrap();
+    let character = characters.iter().find(|c| c.guid == player_guid).unwrap();
+    assert!(
+        (character.position_x - (position.x + 1.)).abs() < 0.01
+            && (character.position_y - position.y).abs() < 0.01,
+        "the server did not register the movement: ({}, {}) after moving to ({}, {})",
+        character.position_x,
+        character.position_y,
+        position.x + 1.,
+        position.y,
+    );
+    world_client.login(player_guid).await.unwrap();
+
+    let (creature_guid, creature_position) = servers.nearest_lootable_creature(&position);
+    world_client
+        .say(&format!(
+            ".teleport --xyz {} {} {}",
+            creature_position.x, creature_position.y, creature_position.z
+        ))
+        .await
+        .unwrap();
+    let teleport = world_client
+        .acknowledge_teleport(player_guid)
+        .await
+        .unwrap();
+    assert!(
+        (teleport.position.x - creature_position.x).abs() < 0.01
+            && (teleport.position.y - creature_position.y).abs() < 0.01,
+        "teleported to ({}, {}) instead of ({}, {})",
+        teleport.position.x,
+        teleport.position.y,
+        creature_position.x,
+        creature_position.y,
+    );
+
+    world_client.attack(creature_guid.raw()).await.unwrap();
+    world_client
+        .cast_spell(
+            HEROIC_STRIKE_SPELL_ID,
+            1,
+            SpellCastTargets::new(Some(creature_guid), None, None, None, None, None),
+        )
+        .await
+        .unwrap();
+    let cast_result = world_client
+        .wait_for_any(&[Opcode::SmsgSpellStart, Opcode::SmsgCastFailed])
+        .await
+        .unwrap();
+    assert_eq!(
+        cast_result.opcode,
+        Opcode::SmsgSpellStart as u16,
+        "Heroic Strike failed: {:?}",
+        cast_result.payload
+    );
+
+    // The player stops attacking when the creature dies, the creature stopping is not enough
+    world_client.set_timeout(COMBAT_TIMEOUT);
+    loop {
+        let attack_stop: SmsgAttackStop = world_client
+            .wait_for(Opcode::SmsgAttackStop)
+            .await
+            .unwrap()
+            .read_as()
+            .unwrap();
+        if ObjectGuid::from_packed(attack_stop.attacker_guid).map(|guid| guid.raw())
+            == Some(player_guid)
+        {
+            break;
+        }
+    }
+
+    let loot = world_client.loot(creature_guid.raw()).await.unwrap();
+    assert_eq!(loot.target_guid, creature_guid.raw());
+    let item = loot.items.first().expect("the creature dropped no item");
+    world_client.autostore_loot_item(item.index).await.unwrap();
+    world_client
+        .wait_for(Opcode::SmsgLootRemoved)
+        .await
+        .unwrap();
+
+    world_client
+        .say("Hello from the test client")
+        .await
+        .unwrap();
+    world_client
+        .wait_for(Opcode::SmsgMessageChat)
+        .await
+        .unwrap();
+
+    world_client.logout().await.unwrap();
+}
@@ -0,0 +1,319 @@
+use std::{
+    error::Error,
+    io,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hex::FromHex;
+use log::{error, info, warn};
+use rustbolt_auth::{
+    logon_checks,
+    repositories::{
+        account::AccountRepository as AuthAccountRepository, failed_login::FailedLoginRepository,
+    },
+    totp,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf},
+    net::{TcpListener, TcpStream},
+};
+use wow_srp::{
+    client::SrpClientUser, normalized_string::NormalizedString, server::SrpVerifier, PublicKey,
+};
+
+use crate::{
+    game::world_context::WorldContext, repositories::account::AccountRepository,
+    shared::constants::SecurityLevel,
+};
+
+// Slows down password guessing, the lockouts of the auth server apply too
+const FAILED_LOGIN_DELAY: Duration = Duration::from_secs(2);
+// Longest line accepted, commands are far shorter
+const MAX_LINE_LENGTH: u64 = 1024;
+// Connections without any input for this long are closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+type BoxedError = Box<dyn Error + Send + Sync>;
+
+// Line-based console (e.g. telnet or nc) running the chat commands from outside of the game
+pub async fn serve_admin_console(world_context: Arc<WorldContext>) {
+    let config = world_context.config.get();
+    let config = &config.world.admin_console;
+    let address = format!("{}:{}", config.host, config.port);
+    let listener = match TcpListener::bind(&address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("unable to start the admin console on {address}: {e}");
+            return;
+        }
+    };
+    info!("Admin console listening on {address}");
+
+    loop {
+        let (socket, peer_address) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("admin console failed to accept a connection: {e}");
+                continue;
+            }
+        };
+
+        let world_context = world_context.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(socket, peer_address.ip(), world_context).await {
+                warn!("admin console connection from {peer_address} closed: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    socket: TcpStream,
+    client_ip: IpAddr,
+    world_context: Arc<WorldContext>,
+) -> io::Result<()> {
+    let (read_half, mut write_half) = tokio::io::split(socket);
+    let mut reader = BufReader::new(read_half);
+
+    write_half.write_all(b"Username: ").await?;
+    let Some(username) = next_line(&mut reader).await? else {
+        return Ok(());
+    };
+    let Ok(username) = NormalizedString::new(&username).map(|name| name.to_string()) else {
+        write_half.write_all(b"Authentication failed\r\n").await?;
+        return Ok(());
+    };
+    write_half.write_all(b"Password: ").await?;
+    let Some(password) = next_line(&mut reader).await? else {
+        return Ok(());
+    };
+
+    // Accounts with two-factor authentication also need their current authenticator code
+    let totp_secret = {
+        let world_context = world_context.clone();
+        let username = username.clone();
+        run_blocking(move || {
+            let conn = world_context.database.auth.get()?;
+            Ok(AuthAccountRepository::fetch_totp_secret(&conn, &username)?)
+        })
+        .await
+    };
+    let totp_secret = match totp_secret {
+        Ok(totp_secret) => totp_secret,
+        Err(e) => {
+            error!("admin console: unable to fetch the account {username}: {e}");
+            write_half.write_all(b"Authentication failed\r\n").await?;
+            return Ok(());
+        }
+    };
+    let totp_code = match totp_secret {
+        Some(_) => {
+            write_half.write_all(b"Authenticator code: ").await?;
+            let Some(totp_code) = next_line(&mut reader).await? else {
+                return Ok(());
+            };
+            Some(totp_code)
+        }
+        None => None,
+    };
+
+    let authentication = {
+        let world_context = world_context.clone();
+        let username = username.clone();
+        run_blocking(move || {
+            authenticate(
+                &username,
+                &password,
+                totp_secret.zip(totp_code),
+                client_ip,
+                &world_context,
+            )
+        })
+        .await
+    };
+    let security_level = match authentication {
+        Ok(Some(security_level)) => security_level,
+        Ok(None) => {
+            warn!("admin console: failed login for account {username} from {client_ip}");
+            tokio::time::sleep(FAILED_LOGIN_DELAY).await;
+            write_half.write_all(b"Authentication failed\r\n").await?;
+            return Ok(());
+        }
+        Err(e) => {
+            error!("admin console: unable to authenticate account {username}: {e}");
+            write_half.write_all(b"Authentication failed\r\n").await?;
+            return Ok(());
+        }
+    };
+
+    let min_security_level = world_context
+        .config
+        .get()
+        .world
+        .admin_console
+        .min_security_level;
+    if (security_level as u8) < min_security_level {
+        warn!("admin console: account {username} does not have the required security level");
+        write_half
+            .write_all(b"Insufficient security level\r\n")
+            .await?;
+        return Ok(());
+    }
+
+    info!("admin console: account {username} logged in");
+    write_line(
+        &mut write_half,
+        &format!("Logged in as {username}, type quit to leave"),
+    )
+    .await?;
+
+    loop {
+        write_half.write_all(b"> ").await?;
+        let Some(input) = next_line(&mut reader).await? else {
+            break;
+        };
+
+        // Accept the commands as they are typed in game
+        let input = input.strip_prefix('.').unwrap_or(&input);
+        match input {
+            "" => continue,
+            "quit" | "exit" => break,
+            _ => (),
+        }
+
+        info!("admin console: {username} runs '{input}'");
+        let command_world_context = world_context.clone();
+        let input = input.to_owned();
+        // Commands lock the map worlds, keep them away from the network threads
+        let output = tokio::task::spawn_blocking(move || {
+            command_world_context.chat_commands.execute_from_console(
+                &input,
+                security_level,
+                command_world_context.clone(),
+            )
+        })
+        .await
+        .unwrap_or_else(|_| vec!["Error: the command panicked".to_owned()]);
+
+        for line in output {
+            write_line(&mut write_half, &line).await?;
+        }
+    }
+
+    info!("admin console: account {username} logged out");
+    write_half.shutdown().await
+}
+
+// Applies the ban and lockout checks of the auth server before checking the password and the
+// authenticator code, failures count towards the lockouts. Returns None if the login is refused.
+fn authenticate(
+    username: &str,
+    password: &str,
+    two_factor: Option<(Vec<u8>, String)>,
+    client_ip: IpAddr,
+    world_context: &WorldContext,
+) -> Result<Option<SecurityLevel>, BoxedError> {
+    let mut conn = world_context.database.auth.get()?;
+    let config = world_context.config.get();
+    let lockout = &config.auth.lockout;
+
+    if logon_checks::ip_rejection(&conn, lockout, &client_ip)?.is_some()
+        || logon_checks::account_rejection(&conn, lockout, username)?.is_some()
+    {
+        return Ok(None);
+    }
+
+    let Some((account_id, verifier, salt)) =
+        AccountRepository::fetch_id_and_verifier(&mut conn, username)
+    else {
+        // Guessing account names counts towards the IP lockout
+        FailedLoginRepository::record(&conn, username, &client_ip)?;
+        return Ok(None);
+    };
+
+    let password_valid = verify_password(username, password, verifier, salt).is_some();
+    let code_valid = match two_factor {
+        None => true,
+        Some((totp_secret, totp_code)) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            totp::valid_codes(&totp_secret, now).contains(&totp_code)
+        }
+    };
+    if !password_valid || !code_valid {
+        FailedLoginRepository::record(&conn, username, &client_ip)?;
+        return Ok(None);
+    }
+
+    FailedLoginRepository::clear_for_account(&conn, username)?;
+    Ok(Some(AccountRepository::fetch_security_level(
+        &mut conn, account_id,
+    )))
+}
+
+// Checks the password against the SRP verifier of the account by running both sides of the
+// exchange locally
+fn verify_password(username: &str, password: &str, verifier: String, salt: String) -> Option<()> {
+    let verifier: [u8; 32] = <Vec<u8>>::from_hex(verifier).ok()?.try_into().ok()?;
+    let salt: [u8; 32] = <Vec<u8>>::from_hex(salt).ok()?.try_into().ok()?;
+    let normalized_username = NormalizedString::new(username).ok()?;
+    let proof =
+        SrpVerifier::from_database_values(normalized_username.clone(), verifier, salt).into_proof();
+
+    let challenge = SrpClientUser::new(normalized_username, NormalizedString::new(password).ok()?)
+        .into_challenge(
+            wow_srp::GENERATOR,
+            wow_srp::LARGE_SAFE_PRIME_LITTLE_ENDIAN,
+            PublicKey::from_le_bytes(proof.server_public_key()).ok()?,
+            *proof.salt(),
+        );
+    proof
+        .into_server(
+            PublicKey::from_le_bytes(challenge.client_public_key()).ok()?,
+            *challenge.client_proof(),
+        )
+        .ok()?;
+
+    Some(())
+}
+
+// Database and SRP work, kept away from the network threads
+async fn run_blocking<T, F>(f: F) -> Result<T, BoxedError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, BoxedError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await?
+}
+
+// Returns None once the client disconnected, fails on lines longer than MAX_LINE_LENGTH and after
+// IDLE_TIMEOUT without a complete line
+async fn next_line(reader: &mut BufReader<ReadHalf<TcpStream>>) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    let read = tokio::time::timeout(
+        IDLE_TIMEOUT,
+        (&mut *reader)
+            .take(MAX_LINE_LENGTH)
+            .read_until(b'\n', &mut line),
+    )
+    .await
+    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "idle for too long"))??;
+
+    if read == 0 {
+        return Ok(None);
+    } else if read as u64 == MAX_LINE_LENGTH && !line.ends_with(b"\n") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+    }
+
+    // Telnet clients end the lines with \r\n
+    Ok(Some(String::from_utf8_lossy(&line).trim().to_owned()))
+}
+
+async fn write_line(write_half: &mut WriteHalf<TcpStream>, line: &str) -> io::Result<()> {
+    write_half.write_all(line.as_bytes()).await?;
+    write_half.write_all(b"\r\n").await
+}
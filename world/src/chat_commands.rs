@@ -1,6 +1,7 @@
 use clap::{ArgMatches, Command};
 use lazy_static::lazy_static;
 use log::{error, warn};
+use parking_lot::Mutex;
 use shipyard::{EntityId, View};
 use std::{collections::HashMap, sync::Arc};
 
@@ -8,12 +9,17 @@ use regex::Regex;
 
 use crate::{
     ecs::components::unit::Unit,
+    entities::{object_guid::ObjectGuid, position::WorldPosition},
     game::{map::Map, world_context::WorldContext},
-    session::world_session::WorldSession,
+    repositories::character::CharacterRepository,
+    session::{character_claims::CharacterClaim, world_session::WorldSession},
+    shared::constants::SecurityLevel,
 };
 
+mod character;
 mod debug;
 mod movement;
+mod server;
 
 pub struct ChatCommands {
     commands: CommandMap,
@@ -26,8 +32,10 @@ lazy_static! {
 impl ChatCommands {
     pub fn build() -> Self {
         let mut commands = HashMap::new();
+        commands.extend(character::commands());
         commands.extend(debug::commands());
         commands.extend(movement::commands());
+        commands.extend(server::commands());
 
         Self { commands }
     }
@@ -43,7 +51,7 @@ impl ChatCommands {
             return false;
         }
 
-        if let Some((command, handler)) = self.commands.get(input[0].as_str()) {
+        if let Some((command, handler)) = self.find(&input[0], session.security_level) {
             let Some(map) = session.current_map() else {
                 error!("chat command: player has not current map");
                 return false;
@@ -54,40 +62,101 @@ impl ChatCommands {
                 return false;
             };
 
-            match command.clone().try_get_matches_from(input.clone()) {
-                Ok(matches) => {
-                    let command_context = CommandContext {
-                        session: session.clone(),
-                        world_context,
-                        map: map.clone(),
-                        my_entity_id,
-                        target_entity_id: Self::extract_target_entity_id(map.clone(), my_entity_id),
-                    };
-
-                    match handler(command_context, matches) {
-                        Ok(_) => {}
-                        Err(ChatCommandError::RequiresTarget) => {
-                            session.send_error_system_message("You must select a target");
-                        }
-                        Err(ChatCommandError::InvalidArguments) => {
-                            session.send_error_system_message("Invalid arguments");
-                        }
-                        Err(ChatCommandError::GenericError) => {
-                            session.send_error_system_message("An error occurred");
-                        }
+            let command_context = CommandContext {
+                world_context,
+                source: CommandSource::Player(PlayerSource {
+                    session: session.clone(),
+                    map: map.clone(),
+                    my_entity_id,
+                    target_entity_id: Self::extract_target_entity_id(map.clone(), my_entity_id),
+                }),
+            };
+            Self::run(command, handler, input, command_context);
+
+            return true;
+        }
+
+        false
+    }
+
+    // Runs a command outside of the game (no player entity), returns the lines to display
+    pub fn execute_from_console(
+        &self,
+        input: &str,
+        security_level: SecurityLevel,
+        world_context: Arc<WorldContext>,
+    ) -> Vec<String> {
+        let Ok(input) = shell_words::split(input) else {
+            return vec!["Invalid quoting".to_owned()];
+        };
+        if input.is_empty() {
+            return Vec::new();
+        }
+
+        let Some((command, handler)) = self.find(&input[0], security_level) else {
+            return vec![format!("Unknown command: {}", input[0])];
+        };
+
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let command_context = CommandContext {
+            world_context,
+            source: CommandSource::Console(output.clone()),
+        };
+        Self::run(command, handler, input, command_context);
+
+        // The context is dropped once the command has run
+        Arc::try_unwrap(output)
+            .map(Mutex::into_inner)
+            .unwrap_or_default()
+    }
+
+    // Commands above the security level of the account are treated as unknown commands
+    fn find(
+        &self,
+        name: &str,
+        security_level: SecurityLevel,
+    ) -> Option<(&Command, CommandHandler)> {
+        self.commands
+            .get(name)
+            .filter(|(_, _, required_level)| security_level >= *required_level)
+            .map(|(command, handler, _)| (command, *handler))
+    }
+
+    fn run(
+        command: &Command,
+        handler: CommandHandler,
+        input: Vec<String>,
+        command_context: CommandContext,
+    ) {
+        match command.clone().try_get_matches_from(input) {
+            Ok(matches) => {
+                // The handler takes ownership of the context
+                let reply_context = command_context.clone();
+                match handler(command_context, matches) {
+                    Ok(_) => {}
+                    Err(ChatCommandError::RequiresTarget) => {
+                        reply_context.reply_error("You must select a target");
+                    }
+                    Err(ChatCommandError::RequiresPlayer) => {
+                        reply_context.reply_error("This command can only be used in game");
+                    }
+                    Err(ChatCommandError::InvalidArguments) => {
+                        reply_context.reply_error("Invalid arguments");
+                    }
+                    Err(ChatCommandError::GenericError) => {
+                        reply_context.reply_error("An error occurred");
                     }
                 }
-                Err(err) => {
+            }
+            Err(err) => match &command_context.source {
+                CommandSource::Player(_) => {
                     let error_message = err.render().ansi().to_string();
                     let error_message = ChatCommands::replace_ansi_escape_sequences(error_message);
-                    session.send_system_message(error_message.as_str());
+                    command_context.reply(error_message.as_str());
                 }
-            }
-
-            return true;
+                CommandSource::Console(_) => command_context.reply(&err.render().to_string()),
+            },
         }
-
-        false
     }
 
     fn replace_ansi_escape_sequences(input: String) -> String {
@@ -126,32 +195,139 @@ type ChatCommandResult = Result<(), ChatCommandError>;
 
 enum ChatCommandError {
     RequiresTarget,
+    RequiresPlayer,
     InvalidArguments,
     GenericError,
 }
 
+#[derive(Clone)]
 struct CommandContext {
-    pub session: Arc<WorldSession>,
     pub world_context: Arc<WorldContext>,
+    source: CommandSource,
+}
+
+#[derive(Clone)]
+enum CommandSource {
+    Player(PlayerSource),
+    Console(Arc<Mutex<Vec<String>>>), // Output sent back to the console client
+}
+
+#[derive(Clone)]
+struct PlayerSource {
+    pub session: Arc<WorldSession>,
     pub map: Arc<Map>,
     pub my_entity_id: EntityId,
     target_entity_id: Option<EntityId>,
 }
 
+impl PlayerSource {
+    pub fn as_character(&self) -> CharacterLookup {
+        CharacterLookup::Online {
+            map: self.map.clone(),
+            entity_id: self.my_entity_id,
+        }
+    }
+}
+
+// A character designated by its name in a command
+enum CharacterLookup {
+    Online {
+        map: Arc<Map>,
+        entity_id: EntityId,
+    },
+    Offline {
+        guid: ObjectGuid,
+        position: WorldPosition,
+    },
+}
+
 impl CommandContext {
     pub fn reply(&self, message: &str) {
-        self.session.send_system_message(message);
+        match &self.source {
+            CommandSource::Player(player) => player.session.send_system_message(message),
+            CommandSource::Console(output) => output.lock().push(message.to_owned()),
+        }
     }
 
     pub fn reply_error(&self, message: &str) {
-        self.session.send_error_system_message(message);
+        match &self.source {
+            CommandSource::Player(player) => player.session.send_error_system_message(message),
+            CommandSource::Console(output) => output.lock().push(format!("Error: {message}")),
+        }
+    }
+
+    // Commands acting on the player who typed them can't be run from the console
+    pub fn require_player(&self) -> Result<&PlayerSource, ChatCommandError> {
+        match &self.source {
+            CommandSource::Player(player) => Ok(player),
+            CommandSource::Console(_) => Err(ChatCommandError::RequiresPlayer),
+        }
     }
 
     pub fn require_target(&self) -> Result<EntityId, ChatCommandError> {
-        self.target_entity_id
+        self.require_player()?
+            .target_entity_id
             .ok_or(ChatCommandError::RequiresTarget)
     }
+
+    pub fn find_character(&self, name: &str) -> Result<CharacterLookup, ChatCommandError> {
+        let conn = self.world_context.database.characters.get().unwrap();
+        let Some((guid, position)) =
+            CharacterRepository::fetch_guid_and_position_by_name(&conn, name)
+        else {
+            self.reply_error("Player not found");
+            return Err(ChatCommandError::GenericError);
+        };
+
+        let online = self
+            .world_context
+            .session_holder
+            .get_matching_sessions(|_| true)
+            .into_iter()
+            .filter(|session| session.player_guid() == Some(guid))
+            .find_map(|session| Some((session.current_map()?, session.player_entity_id()?)));
+
+        Ok(match online {
+            Some((map, entity_id)) => CharacterLookup::Online { map, entity_id },
+            None => CharacterLookup::Offline { guid, position },
+        })
+    }
+
+    // Keeps an offline character from logging in while a command edits it in the database. Fails
+    // if the character logged in since it was looked up.
+    pub fn claim_offline_character(
+        &self,
+        guid: ObjectGuid,
+    ) -> Result<CharacterClaim<'_>, ChatCommandError> {
+        let claim = self.world_context.character_claims.claim(guid.raw());
+        let is_online = self
+            .world_context
+            .session_holder
+            .get_matching_sessions(|_| true)
+            .into_iter()
+            .any(|session| session.player_guid() == Some(guid));
+
+        match claim {
+            Some(claim) if !is_online => Ok(claim),
+            _ => {
+                self.reply_error("The character is logging in, try again");
+                Err(ChatCommandError::GenericError)
+            }
+        }
+    }
+
+    // The character named with the given argument, or the player who typed the command
+    pub fn character_from_args(
+        &self,
+        matches: &ArgMatches,
+        arg: &str,
+    ) -> Result<CharacterLookup, ChatCommandError> {
+        match matches.get_one::<String>(arg) {
+            Some(name) => self.find_character(name),
+            None => Ok(self.require_player()?.as_character()),
+        }
+    }
 }
 
 type CommandHandler = fn(CommandContext, ArgMatches) -> ChatCommandResult;
-type CommandMap = HashMap<&'static str, (Command, CommandHandler)>;
+type CommandMap = HashMap<&'static str, (Command, CommandHandler, SecurityLevel)>;
@@ -0,0 +1,110 @@
+use std::{collections::HashMap, time::SystemTime};
+
+use clap::{Arg, ArgMatches, Command};
+
+use crate::{
+    chat_commands::ChatCommandError,
+    repositories::character::CharacterRepository,
+    session::opcode_handler::OpcodeHandler,
+    shared::constants::{AtLoginFlag, SecurityLevel},
+};
+
+use super::{ChatCommandResult, CommandContext, CommandHandler, CommandMap};
+
+pub(super) fn commands() -> CommandMap {
+    HashMap::from([setup_rename_command(), setup_deleted_command()])
+}
+
+fn setup_rename_command() -> (&'static str, (Command, CommandHandler, SecurityLevel)) {
+    let command_name = "rename";
+    // The player picks the new name on the characters list
+    let command = Command::new(command_name).arg(
+        Arg::new("name")
+            .required(true)
+            .help("Character to rename at their next login"),
+    );
+
+    fn handler(ctx: CommandContext, matches: ArgMatches) -> ChatCommandResult {
+        let name = matches.get_one::<String>("name").unwrap();
+
+        let conn = ctx.world_context.database.characters.get().unwrap();
+        let Some((guid, _)) = CharacterRepository::fetch_guid_and_position_by_name(&conn, name)
+        else {
+            ctx.reply_error("Player not found");
+            return Err(ChatCommandError::GenericError);
+        };
+
+        CharacterRepository::add_at_login_flag(&conn, guid.counter(), AtLoginFlag::Rename);
+        ctx.reply(&format!(
+            "{name} will have to choose a new name at their next login"
+        ));
+
+        Ok(())
+    }
+
+    (command_name, (command, handler, SecurityLevel::GameMaster))
+}
+
+fn setup_deleted_command() -> (&'static str, (Command, CommandHandler, SecurityLevel)) {
+    let command_name = "deleted";
+    let command = Command::new(command_name)
+        .subcommand_required(true)
+        .subcommand(Command::new("list").about("List the archived characters"))
+        .subcommand(
+            Command::new("restore")
+                .about("Give an archived character back to its account")
+                .arg(
+                    Arg::new("guid")
+                        .required(true)
+                        .value_parser(clap::value_parser!(u32)),
+                ),
+        );
+
+    fn handler(ctx: CommandContext, matches: ArgMatches) -> ChatCommandResult {
+        let conn = ctx.world_context.database.characters.get().unwrap();
+
+        if matches.subcommand_matches("list").is_some() {
+            let deleted_characters = CharacterRepository::fetch_deleted_characters(&conn);
+            if deleted_characters.is_empty() {
+                ctx.reply("No deleted characters");
+                return Ok(());
+            }
+
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            for character in deleted_characters {
+                let days_ago = now.saturating_sub(character.deleted_at) / 86400;
+                ctx.reply(&format!(
+                    "{} (guid {}, level {}) from account {}, deleted {days_ago} day(s) ago",
+                    character.name, character.guid, character.level, character.account_id
+                ));
+            }
+
+            return Ok(());
+        }
+
+        if let Some(subcommand_restore) = matches.subcommand_matches("restore") {
+            let guid: &u32 = subcommand_restore.get_one("guid").unwrap();
+
+            let Some(account_id) = CharacterRepository::restore_character(&conn, *guid) else {
+                ctx.reply_error(&format!("No deleted character with guid {guid}"));
+                return Err(ChatCommandError::GenericError);
+            };
+
+            OpcodeHandler::refresh_realm_character_count(&ctx.world_context, account_id);
+            ctx.reply(&format!(
+                "Character {guid} restored to account {account_id}"
+            ));
+            return Ok(());
+        }
+
+        Err(ChatCommandError::InvalidArguments)
+    }
+
+    (
+        command_name,
+        (command, handler, SecurityLevel::Administrator),
+    )
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use clap::{Arg, ArgAction, ArgMatches, Command};
 use log::info;
@@ -7,13 +7,18 @@ use shipyard::{Get, UniqueView, View, ViewMut};
 use crate::{
     ecs::components::{guid::Guid, movement::Movement, threat_list::ThreatList},
     entities::{
-        attributes::Attributes, creature::Creature, player::Player,
+        attributes::Attributes, creature::Creature, object_guid::ObjectGuid, player::Player,
         position::WorldPosition,
     },
     game::packet_broadcaster::WrappedPacketBroadcaster,
+    repositories::{character::CharacterRepository, item::ItemRepository},
+    shared::constants::{InventorySlot, SecurityLevel},
 };
 
-use super::{ChatCommandError, ChatCommandResult, CommandContext, CommandHandler, CommandMap};
+use super::{
+    CharacterLookup, ChatCommandError, ChatCommandResult, CommandContext, CommandHandler,
+    CommandMap,
+};
 
 pub(super) fn commands() -> CommandMap {
     HashMap::from([
@@ -24,7 +29,7 @@ pub(super) fn commands() -> CommandMap {
     ])
 }
 
-fn setup_gps_command() -> (&'static str, (Command, CommandHandler)) {
+fn setup_gps_command() -> (&'static str, (Command, CommandHandler, SecurityLevel)) {
     let command_name = "gps";
     let command = Command::new(command_name).arg(
         Arg::new("dump")
@@ -34,8 +39,10 @@ fn setup_gps_command() -> (&'static str, (Command, CommandHandler)) {
     );
 
     fn handler(ctx: CommandContext, matches: ArgMatches) -> ChatCommandResult {
-        ctx.map.world().run(|v_wpos: View<WorldPosition>| {
-            let wpos = v_wpos[ctx.my_entity_id];
+        let source = ctx.require_player()?;
+
+        source.map.world().run(|v_wpos: View<WorldPosition>| {
+            let wpos = v_wpos[source.my_entity_id];
             let output = format!(
                 "Player position: {}, {}, {}, {}",
                 wpos.x, wpos.y, wpos.z, wpos.o,
@@ -51,22 +58,23 @@ fn setup_gps_command() -> (&'static str, (Command, CommandHandler)) {
         Ok(())
     }
 
-    (command_name, (command, handler))
+    (command_name, (command, handler, SecurityLevel::Moderator))
 }
 
-fn setup_come_command() -> (&'static str, (Command, CommandHandler)) {
+fn setup_come_command() -> (&'static str, (Command, CommandHandler, SecurityLevel)) {
     let command_name = "come";
     let command = Command::new(command_name);
 
     fn handler(ctx: CommandContext, _matches: ArgMatches) -> ChatCommandResult {
         let player_target = ctx.require_target()?;
+        let source = ctx.require_player()?;
 
-        ctx.map.world().run(
+        source.map.world().run(
             |v_wpos: View<WorldPosition>,
              v_guid: View<Guid>,
              packet_broadcaster: UniqueView<WrappedPacketBroadcaster>,
              mut vm_movement: ViewMut<Movement>| {
-                let player_wpos = v_wpos[ctx.my_entity_id];
+                let player_wpos = v_wpos[source.my_entity_id];
                 let target_wpos = v_wpos[player_target];
                 let path = vec![player_wpos.vec3()];
 
@@ -86,10 +94,10 @@ fn setup_come_command() -> (&'static str, (Command, CommandHandler)) {
         )
     }
 
-    (command_name, (command, handler))
+    (command_name, (command, handler, SecurityLevel::Administrator))
 }
 
-fn setup_threat_command() -> (&'static str, (Command, CommandHandler)) {
+fn setup_threat_command() -> (&'static str, (Command, CommandHandler, SecurityLevel)) {
     let command_name = "threat";
     let command = Command::new(command_name).arg(
         Arg::new("list")
@@ -102,7 +110,7 @@ fn setup_threat_command() -> (&'static str, (Command, CommandHandler)) {
     fn handler(ctx: CommandContext, matches: ArgMatches) -> ChatCommandResult {
         let player_target = ctx.require_target()?;
 
-        ctx.map.world().run(
+        ctx.require_player()?.map.world().run(
             |v_threat_list: View<ThreatList>,
              v_player: View<Player>,
              v_creature: View<Creature>| {
@@ -131,11 +139,11 @@ fn setup_threat_command() -> (&'static str, (Command, CommandHandler)) {
         )
     }
 
-    (command_name, (command, handler))
+    (command_name, (command, handler, SecurityLevel::Administrator))
 }
 
 // TODO: Move to item.rs ?
-fn setup_item_command() -> (&'static str, (Command, CommandHandler)) {
+fn setup_item_command() -> (&'static str, (Command, CommandHandler, SecurityLevel)) {
     let command_name = "item";
     let command: Command = Command::new(command_name)
         .subcommand_required(true)
@@ -151,50 +159,106 @@ fn setup_item_command() -> (&'static str, (Command, CommandHandler)) {
                     .long("count")
                     .value_parser(clap::value_parser!(u32))
                     .default_value("1"),
+                Arg::new("character")
+                    .long("character")
+                    .value_name("NAME")
+                    .help("Character receiving the item, online or not (default: yourself)"),
             ]),
         );
 
     fn handler(ctx: CommandContext, matches: ArgMatches) -> ChatCommandResult {
         if let Some(subcommand_add) = matches.subcommand_matches("add") {
-            return ctx.map.world().run(
-                |mut vm_player: ViewMut<Player>,
-                 mut vm_attributes: ViewMut<Attributes>| {
-                    let Ok(mut player) = (&mut vm_player).get(ctx.my_entity_id) else {
-                        return Err(ChatCommandError::GenericError);
-                    };
-
-                    let Ok(mut attributes) =
-                        (&mut vm_attributes).get(ctx.my_entity_id)
-                    else {
-                        return Err(ChatCommandError::GenericError);
-                    };
-
-                    let item_id: &u32 = subcommand_add.get_one("id").unwrap();
-                    let count: &u32 = subcommand_add.get_one("count").unwrap();
-
-                    if ctx
-                        .world_context
-                        .data_store
-                        .get_item_template(*item_id)
-                        .is_none()
-                    {
-                        ctx.reply_error(format!("Item template {item_id} does not exist").as_str());
-                        return Err(ChatCommandError::GenericError);
-                    }
-
-                    match player.auto_store_new_item(*item_id, *count, &mut attributes) {
-                        Ok(_) => Ok(()),
-                        Err(err) => {
-                            ctx.reply_error(format!("Unable to add item ({err:?})").as_str());
-                            Err(ChatCommandError::GenericError)
+            let item_id: &u32 = subcommand_add.get_one("id").unwrap();
+            let count: &u32 = subcommand_add.get_one("count").unwrap();
+
+            let Some(item_template) = ctx.world_context.data_store.get_item_template(*item_id)
+            else {
+                ctx.reply_error(format!("Item template {item_id} does not exist").as_str());
+                return Err(ChatCommandError::GenericError);
+            };
+
+            return match ctx.character_from_args(subcommand_add, "character")? {
+                CharacterLookup::Online { map, entity_id } => map.world().run(
+                    |mut vm_player: ViewMut<Player>,
+                     mut vm_attributes: ViewMut<Attributes>| {
+                        let Ok(mut player) = (&mut vm_player).get(entity_id) else {
+                            return Err(ChatCommandError::GenericError);
+                        };
+
+                        let Ok(mut attributes) =
+                            (&mut vm_attributes).get(entity_id)
+                        else {
+                            return Err(ChatCommandError::GenericError);
+                        };
+
+                        match player.auto_store_new_item(*item_id, *count, &mut attributes) {
+                            Ok(_) => Ok(()),
+                            Err(err) => {
+                                ctx.reply_error(format!("Unable to add item ({err:?})").as_str());
+                                Err(ChatCommandError::GenericError)
+                            }
                         }
-                    }
-                },
-            );
+                    },
+                ),
+                CharacterLookup::Offline { guid, .. } => {
+                    add_item_offline(&ctx, guid, *item_id, *count, item_template.max_stack_count)
+                }
+            };
         }
 
         Err(ChatCommandError::InvalidArguments)
     }
 
-    (command_name, (command, handler))
+    (command_name, (command, handler, SecurityLevel::GameMaster))
+}
+
+// Stores the item in free backpack slots, without completing the existing stacks
+fn add_item_offline(
+    ctx: &CommandContext,
+    character_guid: ObjectGuid,
+    item_id: u32,
+    count: u32,
+    max_stack_count: u32,
+) -> ChatCommandResult {
+    let _claim = ctx.claim_offline_character(character_guid)?;
+    let mut conn = ctx.world_context.database.characters.get().unwrap();
+
+    let used_slots: HashSet<u32> =
+        ItemRepository::load_player_inventory(&conn, character_guid.counter())
+            .iter()
+            .map(|record| record.slot)
+            .collect();
+    let max_stack_count = max_stack_count.max(1);
+    let stacks_needed = count.div_ceil(max_stack_count) as usize;
+    let free_slots: Vec<u32> = (InventorySlot::BACKPACK_START..InventorySlot::BACKPACK_END)
+        .filter(|slot| !used_slots.contains(slot))
+        .take(stacks_needed)
+        .collect();
+    if free_slots.len() < stacks_needed {
+        ctx.reply_error("Unable to add item (InventoryFull)");
+        return Err(ChatCommandError::GenericError);
+    }
+
+    let transaction = conn.transaction().unwrap();
+    if !CharacterRepository::is_active(&transaction, character_guid.counter()) {
+        ctx.reply_error("The character was deleted");
+        return Err(ChatCommandError::GenericError);
+    }
+    let mut remaining_stack_count = count;
+    for slot in free_slots {
+        let stack_count = remaining_stack_count.min(max_stack_count);
+        let item_guid = ctx.world_context.next_item_guid();
+        ItemRepository::create(&transaction, item_guid, item_id, stack_count);
+        CharacterRepository::add_item_to_inventory(
+            &transaction,
+            character_guid.counter(),
+            item_guid,
+            slot,
+        );
+        remaining_stack_count -= stack_count;
+    }
+    transaction.commit().unwrap();
+
+    ctx.reply("Item added to the inventory of the offline character");
+    Ok(())
 }
@@ -4,20 +4,23 @@ use clap::{builder::BoolishValueParser, Arg, ArgMatches, Command};
 use shipyard::{View, ViewMut};
 
 use crate::{
-    chat_commands::ChatCommandError,
     ecs::components::movement::Movement,
     entities::{player::Player, position::WorldPosition},
     game::map_manager::MapKey,
     repositories::character::CharacterRepository,
+    shared::constants::SecurityLevel,
 };
 
-use super::{ChatCommandResult, CommandContext, CommandHandler, CommandMap};
+use super::{
+    CharacterLookup, ChatCommandError, ChatCommandResult, CommandContext, CommandHandler,
+    CommandMap,
+};
 
 pub(super) fn commands() -> CommandMap {
     HashMap::from([setup_fly_command(), setup_teleport_command()])
 }
 
-fn setup_fly_command() -> (&'static str, (Command, CommandHandler)) {
+fn setup_fly_command() -> (&'static str, (Command, CommandHandler, SecurityLevel)) {
     let command_name = "fly";
     let command = Command::new(command_name).arg(
         Arg::new("flying")
@@ -27,18 +30,22 @@ fn setup_fly_command() -> (&'static str, (Command, CommandHandler)) {
 
     fn handler(ctx: CommandContext, matches: ArgMatches) -> ChatCommandResult {
         let &flying = matches.get_one::<bool>("flying").unwrap();
+        let source = ctx.require_player()?;
 
-        ctx.map.world().run(|mut vm_movement: ViewMut<Movement>| {
-            vm_movement[ctx.my_entity_id].set_flying(flying, ctx.session.clone());
-        });
+        source
+            .map
+            .world()
+            .run(|mut vm_movement: ViewMut<Movement>| {
+                vm_movement[source.my_entity_id].set_flying(flying, source.session.clone());
+            });
 
         Ok(())
     }
 
-    (command_name, (command, handler))
+    (command_name, (command, handler, SecurityLevel::GameMaster))
 }
 
-fn setup_teleport_command() -> (&'static str, (Command, CommandHandler)) {
+fn setup_teleport_command() -> (&'static str, (Command, CommandHandler, SecurityLevel)) {
     let command_name = "teleport";
     let command = Command::new(command_name)
         .arg(
@@ -46,6 +53,7 @@ fn setup_teleport_command() -> (&'static str, (Command, CommandHandler)) {
                 .long("xyz")
                 .num_args(3..=4)
                 .value_parser(clap::value_parser!(f32))
+                .allow_negative_numbers(true)
                 .help("Coordinates as 'x y z' with optional map ID")
                 .required_unless_present("player"),
         )
@@ -67,68 +75,86 @@ fn setup_teleport_command() -> (&'static str, (Command, CommandHandler)) {
                 .value_parser(["near", "far"])
                 .default_value("near")
                 .help("Teleport type: near or far"),
+        )
+        .arg(
+            Arg::new("character")
+                .long("character")
+                .value_name("NAME")
+                .help("Character to teleport, online or not (default: yourself)"),
         );
 
     fn handler(ctx: CommandContext, matches: ArgMatches) -> ChatCommandResult {
-        ctx.map.world().run(
-            |mut vm_player: ViewMut<Player>,
-             v_wpos: View<WorldPosition>,
-             v_movement: View<Movement>| {
-                let player = &mut vm_player[ctx.my_entity_id];
-                let wpos = &mut v_wpos[ctx.my_entity_id].clone();
-
-                if matches.contains_id("xyz") {
-                    let map_key = MapKey::for_continent(
-                        matches
-                            .get_many::<f32>("xyz")
-                            .unwrap()
-                            .nth(3)
-                            .map(|x| *x as u32)
-                            .unwrap_or(wpos.map_key.map_id),
-                    );
-                    let x = *matches.get_many::<f32>("xyz").unwrap().next().unwrap();
-                    let y = *matches.get_many::<f32>("xyz").unwrap().nth(1).unwrap();
-                    let z = *matches.get_many::<f32>("xyz").unwrap().nth(2).unwrap();
-                    let o = wpos.o;
-                    wpos.update(&WorldPosition {
-                        map_key,
-                        zone: 0, // TODO: get zone from DBC
-                        x,
-                        y,
-                        z,
-                        o,
-                    });
-                } else if matches.contains_id("player") {
-                    let player_name = matches.get_one::<String>("player").unwrap();
-
-                    let conn = ctx.world_context.database.characters.get().unwrap();
-                    match CharacterRepository::fetch_guid_and_position_by_name(&conn, player_name) {
-                        Some((guid, position)) => {
-                            // Try to get the player position from the ECS first, in case the player is online
-                            match ctx.map.lookup_entity_ecs(&guid) {
-                                Some(target_entity_id) => {
-                                    let target_wpos = &v_wpos[target_entity_id];
-                                    wpos.update(target_wpos);
-                                }
-                                None => wpos.update(&position),
-                            }
-                        }
-                        None => {
-                            ctx.reply_error("Player not found");
-                            return Err(ChatCommandError::GenericError);
-                        }
-                    }
-                } else {
-                    unreachable!()
-                };
-
+        let character = ctx.character_from_args(&matches, "character")?;
+        let current_position = character_position(&character);
+
+        let mut destination = current_position;
+        if matches.contains_id("xyz") {
+            let map_key = MapKey::for_continent(
+                matches
+                    .get_many::<f32>("xyz")
+                    .unwrap()
+                    .nth(3)
+                    .map(|x| *x as u32)
+                    .unwrap_or(current_position.map_key.map_id),
+            );
+            let x = *matches.get_many::<f32>("xyz").unwrap().next().unwrap();
+            let y = *matches.get_many::<f32>("xyz").unwrap().nth(1).unwrap();
+            let z = *matches.get_many::<f32>("xyz").unwrap().nth(2).unwrap();
+            let o = current_position.o;
+            destination.update(&WorldPosition {
+                map_key,
+                zone: 0, // TODO: get zone from DBC
+                x,
+                y,
+                z,
+                o,
+            });
+        } else if matches.contains_id("player") {
+            let player_name = matches.get_one::<String>("player").unwrap();
+            // Use the position in the ECS if the player is online
+            destination.update(&character_position(&ctx.find_character(player_name)?));
+        } else {
+            unreachable!()
+        };
+
+        match character {
+            CharacterLookup::Online { map, entity_id } => {
                 let force_far = matches.get_one::<String>("type").unwrap() == "far";
-                player.teleport_to(wpos, force_far, v_wpos, v_movement);
+                map.world().run(
+                    |mut vm_player: ViewMut<Player>,
+                     v_wpos: View<WorldPosition>,
+                     v_movement: View<Movement>| {
+                        vm_player[entity_id].teleport_to(
+                            &destination,
+                            force_far,
+                            v_wpos,
+                            v_movement,
+                        );
+                    },
+                );
+            }
+            CharacterLookup::Offline { guid, .. } => {
+                let _claim = ctx.claim_offline_character(guid)?;
+                let conn = ctx.world_context.database.characters.get().unwrap();
+                if !CharacterRepository::update_position(&conn, guid.counter(), &destination) {
+                    ctx.reply_error("The character was deleted");
+                    return Err(ChatCommandError::GenericError);
+                }
+                ctx.reply("The character is offline, it will log in at the destination");
+            }
+        }
 
-                Ok(())
-            },
-        )
+        Ok(())
     }
 
-    (command_name, (command, handler))
+    (command_name, (command, handler, SecurityLevel::GameMaster))
+}
+
+fn character_position(character: &CharacterLookup) -> WorldPosition {
+    match character {
+        CharacterLookup::Online { map, entity_id } => map
+            .world()
+            .run(|v_wpos: View<WorldPosition>| v_wpos[*entity_id]),
+        CharacterLookup::Offline { position, .. } => *position,
+    }
 }
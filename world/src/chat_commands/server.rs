@@ -0,0 +1,146 @@
+use std::{collections::HashMap, time::Duration};
+
+use atomic_counter::AtomicCounter;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::{
+    chat_commands::ChatCommandError, game::shutdown::ShutdownKind, protocol::opcodes::Opcode,
+    shared::constants::SecurityLevel,
+};
+
+use super::{ChatCommandResult, CommandContext, CommandHandler, CommandMap};
+
+pub(super) fn commands() -> CommandMap {
+    HashMap::from([
+        setup_shutdown_command(),
+        setup_floodstats_command(),
+        setup_reloadconfig_command(),
+    ])
+}
+
+fn setup_shutdown_command() -> (&'static str, (Command, CommandHandler, SecurityLevel)) {
+    let command_name = "shutdown";
+    let command = Command::new(command_name)
+        .arg(
+            Arg::new("delay")
+                .value_parser(clap::value_parser!(u64))
+                .help("Delay in seconds before the server stops")
+                .required_unless_present("cancel"),
+        )
+        .arg(
+            Arg::new("restart")
+                .long("restart")
+                .short('r')
+                .action(ArgAction::SetTrue)
+                .help("Restart the server instead of shutting it down"),
+        )
+        .arg(
+            Arg::new("cancel")
+                .long("cancel")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["delay", "restart"])
+                .help("Cancel the countdown in progress"),
+        );
+
+    fn handler(ctx: CommandContext, matches: ArgMatches) -> ChatCommandResult {
+        let world_context = &ctx.world_context;
+
+        if matches.get_flag("cancel") {
+            if !world_context.shutdown.cancel(world_context) {
+                ctx.reply_error("No shutdown or restart in progress");
+                return Err(ChatCommandError::GenericError);
+            }
+
+            return Ok(());
+        }
+
+        let &delay = matches.get_one::<u64>("delay").unwrap();
+        let kind = if matches.get_flag("restart") {
+            ShutdownKind::Restart
+        } else {
+            ShutdownKind::Shutdown
+        };
+        world_context
+            .shutdown
+            .schedule(kind, Duration::from_secs(delay), world_context);
+
+        Ok(())
+    }
+
+    (
+        command_name,
+        (command, handler, SecurityLevel::Administrator),
+    )
+}
+
+fn setup_floodstats_command() -> (&'static str, (Command, CommandHandler, SecurityLevel)) {
+    let command_name = "floodstats";
+    // Packets dropped by the flood protection, in total and for each connected account
+    let command = Command::new(command_name);
+
+    fn handler(ctx: CommandContext, _matches: ArgMatches) -> ChatCommandResult {
+        let stats = &ctx.world_context.flood_stats;
+        ctx.reply(&format!(
+            "Dropped packets: {} - Kicked sessions: {}",
+            stats.dropped_packets.get(),
+            stats.kicked_sessions.get()
+        ));
+
+        for session in ctx
+            .world_context
+            .session_holder
+            .get_matching_sessions(|_| true)
+        {
+            let dropped = session.dropped_packets();
+            if dropped.is_empty() {
+                continue;
+            }
+
+            let details: Vec<String> = dropped
+                .iter()
+                .map(|&(opcode, count)| match Opcode::n(opcode) {
+                    Some(opcode) => format!("{opcode:?}: {count}"),
+                    None => format!("{opcode:#X}: {count}"),
+                })
+                .collect();
+            ctx.reply(&format!(
+                "Account {}: {}",
+                session.account_id,
+                details.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    (command_name, (command, handler, SecurityLevel::GameMaster))
+}
+
+fn setup_reloadconfig_command() -> (&'static str, (Command, CommandHandler, SecurityLevel)) {
+    let command_name = "reloadconfig";
+    // Same as sending SIGHUP to the server
+    let command = Command::new(command_name);
+
+    fn handler(ctx: CommandContext, _matches: ArgMatches) -> ChatCommandResult {
+        match ctx.world_context.reload_config() {
+            Ok(needs_restart) => {
+                ctx.reply("Config reloaded");
+                for setting in needs_restart {
+                    ctx.reply(&format!(
+                        "{setting} changed, restart the server to apply it"
+                    ));
+                }
+                Ok(())
+            }
+            Err(e) => {
+                ctx.reply_error(&format!("Unable to reload the config: {e}"));
+                Err(ChatCommandError::GenericError)
+            }
+        }
+    }
+
+    (
+        command_name,
+        (command, handler, SecurityLevel::Administrator),
+    )
+}
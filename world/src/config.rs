@@ -1,9 +1,14 @@
+use std::sync::Arc;
+
 use config::{Config, ConfigError, File};
+use parking_lot::RwLock;
+use rustbolt_auth::config::LockoutSection;
 use serde::Deserialize;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct WorldConfig {
     pub world: WorldSection,
+    pub auth: AuthSection,
     pub common: CommonSection, // TODO: Move to a common lib
 }
 
@@ -17,45 +22,232 @@ impl WorldConfig {
 
         s.try_deserialize()
     }
+
+    // Takes the settings of `new` that can change while the server is running and keeps the
+    // current value of the others, returning the names of the ones that differ
+    fn merge_reloadable(&self, mut new: WorldConfig) -> (WorldConfig, Vec<&'static str>) {
+        let mut needs_restart = Vec::new();
+
+        macro_rules! keep_current {
+            ($name:literal, $($field:ident).+) => {
+                if new.$($field).+ != self.$($field).+ {
+                    needs_restart.push($name);
+                    new.$($field).+ = self.$($field).+.clone();
+                }
+            };
+        }
+
+        keep_current!("common.data.directory", common.data.directory);
+        keep_current!("world.network.host", world.network.host);
+        keep_current!("world.network.port", world.network.port);
+        keep_current!("world.realm.id", world.realm.id);
+        keep_current!(
+            "world.realm.heartbeat_interval_secs",
+            world.realm.heartbeat_interval_secs
+        );
+        keep_current!(
+            "world.realm.queue_update_interval_secs",
+            world.realm.queue_update_interval_secs
+        );
+        // Read once when a session opens
+        keep_current!(
+            "world.update_packets.compression_threshold",
+            world.update_packets.compression_threshold
+        );
+        keep_current!("world.metrics.enabled", world.metrics.enabled);
+        keep_current!("world.metrics.host", world.metrics.host);
+        keep_current!("world.metrics.port", world.metrics.port);
+        keep_current!("world.admin_console.enabled", world.admin_console.enabled);
+        keep_current!("world.admin_console.host", world.admin_console.host);
+        keep_current!("world.admin_console.port", world.admin_console.port);
+        keep_current!("world.dev.load_terrain", world.dev.load_terrain);
+        keep_current!(
+            "world.dev.load_creature_templates",
+            world.dev.load_creature_templates
+        );
+        keep_current!(
+            "world.dev.load_game_object_templates",
+            world.dev.load_game_object_templates
+        );
+
+        (new, needs_restart)
+    }
+}
+
+// The config of the running server, which can be swapped with a newly loaded one
+pub struct LiveConfig {
+    current: RwLock<Arc<WorldConfig>>,
+}
+
+impl LiveConfig {
+    pub fn new(config: Arc<WorldConfig>) -> Self {
+        Self {
+            current: RwLock::new(config),
+        }
+    }
+
+    pub fn get(&self) -> Arc<WorldConfig> {
+        self.current.read().clone()
+    }
+
+    // Loads the config files again and applies the settings that can change live, returning the
+    // names of the changed settings that are only taken into account after a restart
+    pub fn reload(&self) -> Result<Vec<&'static str>, ConfigError> {
+        let new = WorldConfig::load()?;
+
+        let mut current = self.current.write();
+        let (merged, needs_restart) = current.merge_reloadable(new);
+        *current = Arc::new(merged);
+
+        Ok(needs_restart)
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct CommonSection {
     pub data: DataSection,
 }
 
-#[derive(Debug, Deserialize)]
+// Settings of the auth server also enforced by the admin console
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct AuthSection {
+    pub lockout: LockoutSection,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct WorldSection {
     pub network: NetworkSection,
+    pub realm: RealmSection,
     pub game: GameSection,
+    pub shutdown: ShutdownSection,
+    pub disconnect: DisconnectSection,
+    pub character_deletion: CharacterDeletionSection,
+    pub character_names: CharacterNamesSection,
+    pub update_packets: UpdatePacketsSection,
+    pub flood_protection: FloodProtectionSection,
+    pub movement_validation: MovementValidationSection,
+    pub metrics: MetricsSection,
+    pub admin_console: AdminConsoleSection,
     pub dev: DevSection,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct DataSection {
     pub directory: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct NetworkSection {
     pub host: String,
     pub port: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct RealmSection {
+    pub id: u32,
+    pub build: u16,
+    pub max_players: u32,
+    pub heartbeat_interval_secs: u64,
+    pub queue_bypass_security_level: u8,
+    pub queue_update_interval_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct GameSection {
     pub target_tick_time_ms: u64,
+    pub autosave_interval_secs: u64,
     pub player: PlayerSection,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct PlayerSection {
     pub maxlevel: u32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ShutdownSection {
+    pub signal_delay_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct DisconnectSection {
+    pub grace_period_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct CharacterDeletionSection {
+    pub purge_after_days: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct CharacterNamesSection {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub allowed_charsets: Vec<NameCharset>,
+    pub allow_mixed_case: bool,
+    pub profanity_list_file: String,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NameCharset {
+    Latin,
+    ExtendedLatin,
+    Cyrillic,
+    Korean,
+    Chinese,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct UpdatePacketsSection {
+    pub compression_threshold: usize,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct FloodProtectionSection {
+    pub enabled: bool,
+    pub kick_threshold: u32,
+    pub kick_window_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct MovementValidationSection {
+    pub enabled: bool,
+    pub violation_action: MovementViolationAction,
+    pub speed_tolerance: f32,
+    pub clock_drift_tolerance_ms: i64,
+    pub max_position_jump: f32,
+    pub below_terrain_tolerance: f32,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MovementViolationAction {
+    Log,
+    Teleport,
+    Disconnect,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct MetricsSection {
+    pub enabled: bool,
+    pub host: String,
+    pub port: String,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct AdminConsoleSection {
+    pub enabled: bool,
+    pub host: String,
+    pub port: String,
+    pub min_security_level: u8,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct DevSection {
     pub load_terrain: bool,
     pub load_creature_templates: bool,
     pub load_game_object_templates: bool,
+    pub capture_packets: bool,
+    pub capture_directory: String,
 }
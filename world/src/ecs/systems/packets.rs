@@ -12,7 +12,7 @@ pub fn process_packets(
     let world_context = world_context.0.clone();
 
     for (session, packet) in packet_queue.get_packets_and_reset_queue() {
-        let (_, handler) = world_context
+        let (_, handler, _) = world_context
             .opcode_handler
             .get_handler(packet.header.opcode);
 
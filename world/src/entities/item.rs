@@ -13,7 +13,7 @@ use super::{
 pub struct Item {
     guid: ObjectGuid,
     values: InternalValues,
-    needs_db_save: bool,
+    unsaved_changes: u32,
 }
 
 impl Item {
@@ -42,7 +42,7 @@ impl Item {
         Item {
             guid,
             values,
-            needs_db_save: !loaded_from_db,
+            unsaved_changes: if loaded_from_db { 0 } else { 1 },
         }
     }
 
@@ -71,15 +71,22 @@ impl Item {
         self.values
             .set_u32(ItemFields::ItemFieldStackCount.into(), new_stack_count);
 
-        self.needs_db_save = true;
+        self.unsaved_changes += 1;
+    }
+
+    // Number of changes since the item was last saved, captured with the autosave snapshots
+    pub fn unsaved_changes(&self) -> u32 {
+        self.unsaved_changes
     }
 
     pub fn needs_db_save(&self) -> bool {
-        self.needs_db_save
+        self.unsaved_changes > 0
     }
 
-    pub fn mark_saved(&mut self) {
-        self.needs_db_save = false;
+    // Only forgets the changes that were written, the ones made since the snapshot still have to
+    // be saved
+    pub fn mark_saved(&mut self, saved_changes: u32) {
+        self.unsaved_changes = self.unsaved_changes.saturating_sub(saved_changes);
     }
 
     pub fn build_create_data(&self) -> CreateData {
@@ -18,7 +18,7 @@ use crate::{
     datastore::data_types::{PlayerCreatePosition, GAME_TABLE_MAX_LEVEL},
     ecs::components::cooldowns::SpellCooldown,
     entities::player::player_data::FactionStanding,
-    game::world_context::WorldContext,
+    game::{movement_validator::MovementValidator, world_context::WorldContext},
     protocol::packets::{CmsgCharCreate, SmsgCreateObject},
     repositories::{character::CharacterRepository, item::ItemRepository},
     session::world_session::WorldSession,
@@ -78,6 +78,7 @@ pub struct Player {
     // write, release to read
     pub needs_nearby_game_objects_refresh: AtomicBool,
     pub teleport_destination: Option<WorldPosition>,
+    pub movement_validator: MovementValidator,
 }
 
 impl Player {
@@ -369,7 +370,7 @@ impl Player {
         values.set_u32(UnitFields::UnitFieldLevel.into(), character.level as u32);
         values.set_u32(
             UnitFields::PlayerFieldMaxLevel.into(),
-            world_context.config.world.game.player.maxlevel,
+            world_context.config.get().world.game.player.maxlevel,
         );
 
         values.set_u32(UnitFields::PlayerXp.into(), character.experience);
@@ -704,6 +705,7 @@ impl Player {
             has_just_leveled_up: Mutex::new(false),
             needs_nearby_game_objects_refresh: AtomicBool::new(false),
             teleport_destination: None,
+            movement_validator: MovementValidator::new(),
         }
     }
 
@@ -880,6 +882,8 @@ impl Player {
     }
 
     pub fn take_teleport_destination(&mut self) -> Option<WorldPosition> {
+        // The client now moves from the destination
+        self.movement_validator.reset();
         self.teleport_destination.take()
     }
 
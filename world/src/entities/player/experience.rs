@@ -32,10 +32,9 @@ impl Player {
         let current_xp = self.experience();
         let mut new_xp = current_xp + xp;
         let mut next_level_xp = self.experience_for_next_level();
+        let max_level = self.world_context.config.get().world.game.player.maxlevel;
 
-        while new_xp >= next_level_xp
-            && self.level() <= self.world_context.config.world.game.player.maxlevel
-        {
+        while new_xp >= next_level_xp && self.level() <= max_level {
             self.increment_level(attributes);
             new_xp -= next_level_xp;
             next_level_xp = self.experience_for_next_level();
@@ -56,7 +55,7 @@ impl Player {
 
     pub fn increment_level(&self, attributes: &mut Attributes) {
         let current_level = self.level();
-        if current_level >= self.world_context.config.world.game.player.maxlevel {
+        if current_level >= self.world_context.config.get().world.game.player.maxlevel {
             return;
         }
 
@@ -207,9 +207,16 @@ impl PlayerInventory {
         }
     }
 
-    pub fn mark_saved(&mut self) {
+    // Takes the (item guid, number of changes) written by a save, the items may have moved or
+    // been removed since
+    pub fn mark_saved(&mut self, saved_items: &[(u32, u32)]) {
         for item in self.items.values_mut() {
-            item.mark_saved();
+            if let Some(&(_, saved_changes)) = saved_items
+                .iter()
+                .find(|(guid, _)| *guid == item.guid().counter())
+            {
+                item.mark_saved(saved_changes);
+            }
         }
     }
 
@@ -0,0 +1,102 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use log::{error, info};
+
+use crate::{
+    database_context::DatabaseContext,
+    repositories::character::{CharacterRepository, CharacterSnapshot},
+};
+
+use super::{map_manager::MapKey, metrics::Metrics};
+
+// Characters written by an autosave: (character guid, (item guid, number of changes) saved)
+pub type SavedCharacters = Vec<(u32, Vec<(u32, u32)>)>;
+
+// Writes the autosave snapshots of a map to the database one after the other on a dedicated
+// thread, so that the tick is not stalled and an autosave never commits after a more recent one
+pub struct AutosaveWriter {
+    snapshots_tx: Option<Sender<Vec<CharacterSnapshot>>>,
+    saved_rx: Receiver<SavedCharacters>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AutosaveWriter {
+    pub fn new(map_key: MapKey, database: Arc<DatabaseContext>, metrics: Arc<Metrics>) -> Self {
+        let (snapshots_tx, snapshots_rx) = mpsc::channel::<Vec<CharacterSnapshot>>();
+        let (saved_tx, saved_rx) = mpsc::channel();
+
+        let thread = thread::Builder::new()
+            .name(format!("autosave-{}", map_key.map_id))
+            .spawn(move || {
+                // Ends when the map drops the sender, after writing the pending snapshots
+                while let Ok(snapshots) = snapshots_rx.recv() {
+                    let save_start = Instant::now();
+                    match Self::write(&database, &snapshots) {
+                        Ok(saved) => {
+                            metrics.db_save_duration.observe(save_start.elapsed());
+                            info!("autosaved {} character(s) on map {map_key}", saved.len());
+                            // The map is gone if this fails, nothing left to mark as saved
+                            let _ = saved_tx.send(saved);
+                        }
+                        Err(e) => error!("autosave failed on map {map_key}: {e}"),
+                    }
+                }
+            })
+            .expect("failed to spawn the autosave thread");
+
+        Self {
+            snapshots_tx: Some(snapshots_tx),
+            saved_rx,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn submit(&self, snapshots: Vec<CharacterSnapshot>) {
+        if let Some(snapshots_tx) = &self.snapshots_tx {
+            if snapshots_tx.send(snapshots).is_err() {
+                error!("the autosave thread is gone, snapshots dropped");
+            }
+        }
+    }
+
+    // Characters committed since the last call
+    pub fn take_saved(&self) -> SavedCharacters {
+        self.saved_rx.try_iter().flatten().collect()
+    }
+
+    // Waits for the pending snapshots to be written, no snapshots are accepted afterwards
+    pub fn flush(&mut self) {
+        self.snapshots_tx.take();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("the autosave thread panicked");
+            }
+        }
+    }
+
+    fn write(
+        database: &DatabaseContext,
+        snapshots: &[CharacterSnapshot],
+    ) -> Result<SavedCharacters, Box<dyn std::error::Error>> {
+        let mut conn = database.characters.get()?;
+        let transaction = conn.transaction()?;
+
+        let mut saved = Vec::with_capacity(snapshots.len());
+        for snapshot in snapshots {
+            // Skipped if the character was saved more recently (e.g. on logout)
+            if CharacterRepository::save_snapshot_to_db(&transaction, snapshot)? {
+                saved.push((snapshot.guid(), snapshot.saved_items()));
+            }
+        }
+
+        transaction.commit()?;
+        Ok(saved)
+    }
+}
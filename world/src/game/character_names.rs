@@ -0,0 +1,265 @@
+use std::fs;
+
+use log::{error, info};
+use parking_lot::RwLock;
+
+use crate::{
+    config::{CharacterNamesSection, NameCharset},
+    shared::response_codes::ResponseCodes,
+};
+
+// Server-side checks of the names picked when creating or renaming a character, the client
+// only runs some of them and can be bypassed
+#[derive(Default)]
+pub struct CharacterNameValidator {
+    profane_words: RwLock<Vec<String>>,
+}
+
+impl CharacterNameValidator {
+    pub fn new(config: &CharacterNamesSection) -> Self {
+        let validator = Self::default();
+        validator.load_profanity_list(config);
+        validator
+    }
+
+    // Keeps the current list if the file cannot be read
+    pub fn load_profanity_list(&self, config: &CharacterNamesSection) {
+        if config.profanity_list_file.is_empty() {
+            self.profane_words.write().clear();
+            return;
+        }
+
+        match fs::read_to_string(&config.profanity_list_file) {
+            Ok(content) => {
+                let words: Vec<String> = content
+                    .lines()
+                    .map(|line| line.trim().to_lowercase())
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .collect();
+                info!(
+                    "Loaded {} word(s) from the profanity list {}",
+                    words.len(),
+                    config.profanity_list_file
+                );
+                *self.profane_words.write() = words;
+            }
+            Err(e) => error!(
+                "unable to read the profanity list {}: {e}",
+                config.profanity_list_file
+            ),
+        }
+    }
+
+    // Checks the name on its own and returns it normalized, as it must be stored. The reserved and
+    // taken names are checked in the database.
+    pub fn validate(
+        &self,
+        name: &str,
+        config: &CharacterNamesSection,
+    ) -> Result<String, ResponseCodes> {
+        let name = normalize(name);
+        let letters: Vec<char> = name.chars().collect();
+
+        if letters.is_empty() {
+            return Err(ResponseCodes::CharNameNoName);
+        } else if letters.len() < config.min_length {
+            return Err(ResponseCodes::CharNameTooShort);
+        } else if letters.len() > config.max_length {
+            return Err(ResponseCodes::CharNameTooLong);
+        }
+
+        let mut name_charset: Option<NameCharset> = None;
+        for &letter in &letters {
+            let Some(charset) =
+                charset_of(letter).filter(|charset| config.allowed_charsets.contains(charset))
+            else {
+                return Err(ResponseCodes::CharNameInvalidCharacter);
+            };
+
+            let script = script_of(charset);
+            match name_charset {
+                Some(previous) if previous != script => {
+                    return Err(ResponseCodes::CharNameMixedLanguages)
+                }
+                _ => name_charset = Some(script),
+            }
+        }
+
+        let lowercase_letters: Vec<char> = name.to_lowercase().chars().collect();
+        if lowercase_letters
+            .windows(3)
+            .any(|w| w[0] == w[1] && w[1] == w[2])
+        {
+            return Err(ResponseCodes::CharNameThreeConsecutive);
+        }
+
+        if !config.allow_mixed_case && letters[1..].iter().any(|l| l.is_uppercase()) {
+            return Err(ResponseCodes::CharNameInvalidCharacter);
+        }
+
+        let lowercase_name = name.to_lowercase();
+        if self
+            .profane_words
+            .read()
+            .iter()
+            .any(|word| lowercase_name.contains(word.as_str()))
+        {
+            return Err(ResponseCodes::CharNameProfane);
+        }
+
+        Ok(name)
+    }
+}
+
+// Capitalizes the first letter. Names typed in a single case (e.g. "bob" or "BOB") also get the
+// other letters lowercased, the capitals of the other names are kept as typed.
+fn normalize(name: &str) -> String {
+    let mut letters = name.chars();
+    let Some(first_letter) = letters.next() else {
+        return String::new();
+    };
+
+    let rest = letters.as_str();
+    let single_case =
+        !rest.chars().any(char::is_uppercase) || !rest.chars().any(char::is_lowercase);
+    let rest = if single_case {
+        rest.to_lowercase()
+    } else {
+        rest.to_owned()
+    };
+
+    first_letter.to_uppercase().chain(rest.chars()).collect()
+}
+
+fn charset_of(letter: char) -> Option<NameCharset> {
+    match letter {
+        'A'..='Z' | 'a'..='z' => Some(NameCharset::Latin),
+        // Latin-1 Supplement and Latin Extended-A letters, without × and ÷
+        '\u{C0}'..='\u{17F}' if letter != '\u{D7}' && letter != '\u{F7}' => {
+            Some(NameCharset::ExtendedLatin)
+        }
+        '\u{400}'..='\u{4FF}' => Some(NameCharset::Cyrillic),
+        '\u{AC00}'..='\u{D7A3}' => Some(NameCharset::Korean),
+        '\u{4E00}'..='\u{9FFF}' => Some(NameCharset::Chinese),
+        _ => None,
+    }
+}
+
+// Accented letters can be mixed with the basic latin ones
+fn script_of(charset: NameCharset) -> NameCharset {
+    match charset {
+        NameCharset::ExtendedLatin => NameCharset::Latin,
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CharacterNamesSection {
+        CharacterNamesSection {
+            min_length: 2,
+            max_length: 12,
+            allowed_charsets: vec![
+                NameCharset::Latin,
+                NameCharset::ExtendedLatin,
+                NameCharset::Cyrillic,
+            ],
+            allow_mixed_case: false,
+            profanity_list_file: String::new(),
+        }
+    }
+
+    fn validate(name: &str, config: &CharacterNamesSection) -> Result<String, u8> {
+        CharacterNameValidator::default()
+            .validate(name, config)
+            .map_err(|code| code as u8)
+    }
+
+    #[test]
+    fn test_normalizes_case() {
+        assert_eq!(validate("bob", &config()), Ok("Bob".to_owned()));
+        assert_eq!(validate("BOB", &config()), Ok("Bob".to_owned()));
+        assert_eq!(validate("Bob", &config()), Ok("Bob".to_owned()));
+        assert_eq!(validate("élodie", &config()), Ok("Élodie".to_owned()));
+    }
+
+    #[test]
+    fn test_rejects_empty_name() {
+        assert_eq!(
+            validate("", &config()),
+            Err(ResponseCodes::CharNameNoName as u8)
+        );
+    }
+
+    #[test]
+    fn test_rejects_names_out_of_length_bounds() {
+        assert_eq!(
+            validate("A", &config()),
+            Err(ResponseCodes::CharNameTooShort as u8)
+        );
+        assert_eq!(
+            validate("Abcdefghijklm", &config()),
+            Err(ResponseCodes::CharNameTooLong as u8)
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_characters() {
+        assert_eq!(
+            validate("Bob1", &config()),
+            Err(ResponseCodes::CharNameInvalidCharacter as u8)
+        );
+        // Korean is not in the allowed charsets
+        assert_eq!(
+            validate("\u{D64D}\u{AE38}\u{B3D9}", &config()),
+            Err(ResponseCodes::CharNameInvalidCharacter as u8)
+        );
+    }
+
+    #[test]
+    fn test_rejects_mixed_languages() {
+        assert_eq!(
+            validate("Bob\u{434}", &config()),
+            Err(ResponseCodes::CharNameMixedLanguages as u8)
+        );
+        assert!(validate("Zoë", &config()).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_three_consecutive_letters() {
+        assert_eq!(
+            validate("Abbbc", &config()),
+            Err(ResponseCodes::CharNameThreeConsecutive as u8)
+        );
+    }
+
+    #[test]
+    fn test_rejects_internal_capitals_unless_allowed() {
+        assert_eq!(
+            validate("BoB", &config()),
+            Err(ResponseCodes::CharNameInvalidCharacter as u8)
+        );
+
+        let config = CharacterNamesSection {
+            allow_mixed_case: true,
+            ..config()
+        };
+        assert_eq!(validate("mcDonald", &config), Ok("McDonald".to_owned()));
+    }
+
+    #[test]
+    fn test_rejects_profane_names() {
+        let validator = CharacterNameValidator::default();
+        validator.profane_words.write().push("darn".to_owned());
+
+        assert_eq!(
+            validator
+                .validate("Darnit", &config())
+                .map_err(|code| code as u8),
+            Err(ResponseCodes::CharNameProfane as u8)
+        );
+        assert!(validator.validate("Dart", &config()).is_ok());
+    }
+}
@@ -11,14 +11,13 @@ use std::{
 };
 
 use log::{error, info, warn};
-use parking_lot::{ReentrantMutex, ReentrantMutexGuard, RwLock};
+use parking_lot::{Mutex, ReentrantMutex, ReentrantMutexGuard, RwLock};
 use shipyard::{
-    AllStoragesViewMut, EntitiesViewMut, EntityId, Get, IntoWorkload, Unique, UniqueViewMut, View,
-    ViewMut, World,
+    AllStoragesViewMut, EntitiesViewMut, EntityId, Get, IntoIter, IntoWorkload, Unique,
+    UniqueViewMut, View, ViewMut, World,
 };
 
 use crate::{
-    config::WorldConfig,
     ecs::{
         components::{
             applied_auras::AppliedAuras,
@@ -58,7 +57,8 @@ use crate::{
         server::ServerMessage,
     },
     repositories::{
-        character::CharacterRecord, creature::CreatureSpawnDbRecord,
+        character::{CharacterRecord, CharacterSnapshot},
+        creature::CreatureSpawnDbRecord,
         game_object::GameObjectSpawnDbRecord,
     },
     session::{
@@ -73,8 +73,10 @@ use crate::{
 
 use super::{
     aura_effect_handler::WrappedAuraEffectHandler,
+    autosave_writer::AutosaveWriter,
     entity_manager::{EntityManager, WrappedEntityManager},
     map_manager::MapKey,
+    metrics::MapMetrics,
     packet_broadcaster::{PacketBroadcaster, WrappedPacketBroadcaster},
     packet_queue::{PacketQueue, WrappedPacketQueue},
     spatial_grid::{SpatialGrid, WrappedSpatialGrid},
@@ -97,6 +99,8 @@ pub struct Map {
     packet_queue: Arc<PacketQueue>,
     visibility_distance: f32,
     running: Arc<AtomicBool>,
+    metrics: MapMetrics,
+    autosave_writer: Mutex<AutosaveWriter>,
 }
 
 impl Map {
@@ -170,6 +174,12 @@ impl Map {
             packet_queue,
             visibility_distance,
             running: Arc::new(AtomicBool::new(false)),
+            metrics: MapMetrics::new(),
+            autosave_writer: Mutex::new(AutosaveWriter::new(
+                key,
+                world_context.database.clone(),
+                world_context.metrics.clone(),
+            )),
         };
 
         for spawn in creature_spawns {
@@ -208,9 +218,8 @@ impl Map {
         map
     }
 
-    pub fn start(&self, config: Arc<WorldConfig>) {
+    pub fn start(&self) {
         self.running.store(true, Ordering::SeqCst);
-        let target_tick_time = config.world.game.target_tick_time_ms;
 
         let map_update_workload = || {
             (
@@ -237,8 +246,14 @@ impl Map {
         let mut time = Instant::now();
         let mut update_times: VecDeque<u128> = VecDeque::with_capacity(200);
         let mut last_update_time_print = Instant::now();
+        let mut last_autosave = Instant::now();
 
         while self.running.load(Ordering::SeqCst) {
+            // Read on every tick to pick up the config reloads
+            let config = self.world_context.config.get();
+            let target_tick_time = config.world.game.target_tick_time_ms;
+            let autosave_interval = Duration::from_secs(config.world.game.autosave_interval_secs);
+
             let tick_start_time = Instant::now();
             let elapsed_since_last_tick = tick_start_time.duration_since(time);
             time = tick_start_time;
@@ -256,9 +271,35 @@ impl Map {
 
                 world_guard.run(process_packets);
                 world_guard.run_workload(map_update_workload).unwrap();
+
+                // Send the update blocks grouped during the tick
+                for session in self.session_holder.get_matching_sessions(|_| true) {
+                    // Fails if the client is gone, the network task cleans the session up
+                    let _ = session.flush_updates();
+                }
+
+                world_guard.run(
+                    |v_player: View<Player>,
+                     v_creature: View<Creature>,
+                     v_game_object: View<GameObject>| {
+                        self.metrics.set_entity_counts(
+                            v_player.len(),
+                            v_creature.len(),
+                            v_game_object.len(),
+                        );
+                    },
+                );
+            }
+
+            if !autosave_interval.is_zero()
+                && tick_start_time.duration_since(last_autosave) >= autosave_interval
+            {
+                self.autosave_players();
+                last_autosave = tick_start_time;
             }
 
             let tick_duration = Instant::now().duration_since(tick_start_time);
+            self.metrics.tick_duration.observe(tick_duration);
             if update_times.len() == 200 {
                 update_times.pop_front();
             }
@@ -275,6 +316,58 @@ impl Map {
         }
     }
 
+    // Only the snapshots are taken on the map thread, they are written to the database by the
+    // autosave writer of the map
+    fn autosave_players(&self) {
+        let autosave_writer = self.autosave_writer.lock();
+        let saved_characters = autosave_writer.take_saved();
+
+        let snapshots: Vec<CharacterSnapshot> = self.world().run(
+            |mut vm_player: ViewMut<Player>,
+             v_powers: View<Powers>,
+             v_wpos: View<WorldPosition>,
+             v_cooldowns: View<Cooldowns>| {
+                // The items written by the previous autosaves no longer need to be saved
+                for player in (&mut vm_player).iter() {
+                    let guid = player.guid().counter();
+                    if let Some((_, saved_items)) = saved_characters
+                        .iter()
+                        .find(|(character_guid, _)| *character_guid == guid)
+                    {
+                        player.inventory_mut().mark_saved(saved_items);
+                    }
+                }
+
+                (&vm_player, &v_powers, &v_wpos, &v_cooldowns)
+                    .iter()
+                    .map(|(player, powers, position, cooldowns)| {
+                        CharacterSnapshot::capture(
+                            player,
+                            powers,
+                            position,
+                            cooldowns,
+                            self.world_context.next_save_generation(),
+                        )
+                    })
+                    .collect()
+            },
+        );
+
+        if !snapshots.is_empty() {
+            autosave_writer.submit(snapshots);
+        }
+    }
+
+    // Waits for the autosaves in progress, called once the map stopped ticking
+    pub fn flush_autosaves(&self) {
+        self.autosave_writer.lock().flush();
+    }
+
+    // The loop in start() exits at the end of the current tick
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+
     pub fn world(&self) -> WorldRef<'_> {
         let guard = self.world.lock();
 
@@ -288,6 +381,14 @@ impl Map {
         self.key.map_id
     }
 
+    pub fn key(&self) -> MapKey {
+        self.key
+    }
+
+    pub fn metrics(&self) -> &MapMetrics {
+        &self.metrics
+    }
+
     pub fn lookup_entity_ecs(&self, guid: &ObjectGuid) -> Option<EntityId> {
         self.entity_manager.lookup(guid)
     }
@@ -579,47 +680,120 @@ impl Map {
                 });
 
             // Send nearby entities to the new player
-            let smsg_create_object: Option<SmsgCreateObject>;
-            {
-                let world_guard = self.world();
+            self.send_entity_to_player(
+                &session,
+                other_entity_id,
+                other_entity_guid,
+                player_entity_id,
+            );
+        }
+    }
 
-                let (v_movement, v_player, v_creature, v_game_object, v_wpos) = world_guard
-                    .borrow::<(
-                        View<Movement>,
-                        View<Player>,
-                        View<Creature>,
-                        View<GameObject>,
-                        View<WorldPosition>,
-                    )>()
-                    .unwrap();
+    fn send_entity_to_player(
+        &self,
+        session: &WorldSession,
+        other_entity_id: EntityId,
+        other_entity_guid: ObjectGuid,
+        player_entity_id: EntityId,
+    ) {
+        let smsg_create_object: Option<SmsgCreateObject>;
+        {
+            let world_guard = self.world();
+
+            let (v_movement, v_player, v_creature, v_game_object, v_wpos) = world_guard
+                .borrow::<(
+                    View<Movement>,
+                    View<Player>,
+                    View<Creature>,
+                    View<GameObject>,
+                    View<WorldPosition>,
+                )>()
+                .unwrap();
+
+            let movement = v_movement.get(other_entity_id).ok().map(|m| {
+                m.build_update(
+                    self.world_context.clone(),
+                    &v_wpos[other_entity_id].as_position(),
+                )
+            });
+
+            if let Ok(player) = v_player.get(other_entity_id) {
+                smsg_create_object = Some(player.build_create_object(movement, false));
+            } else if let Ok(creature) = v_creature.get(other_entity_id) {
+                smsg_create_object = Some(creature.build_create_object(movement));
+            } else if let Ok(game_object) = v_game_object.get(other_entity_id) {
+                smsg_create_object =
+                    Some(game_object.build_create_object_for(&v_player[player_entity_id]));
+            } else {
+                unreachable!("cannot generate SMSG_CREATE_OBJECT for this entity type");
+            }
+        }
 
-                let movement = v_movement.get(other_entity_id).ok().map(|m| {
-                    m.build_update(
-                        self.world_context.clone(),
-                        &v_wpos[other_entity_id].as_position(),
-                    )
-                });
+        if let Some(smsg) = smsg_create_object {
+            session.create_entity(&other_entity_guid, smsg);
+        } else {
+            warn!(
+                "add_player_on_login: unable to generate a SmsgCreateObject for guid {:?}",
+                other_entity_id
+            );
+        }
+    }
 
-                if let Ok(player) = v_player.get(other_entity_id) {
-                    smsg_create_object = Some(player.build_create_object(movement, false));
-                } else if let Ok(creature) = v_creature.get(other_entity_id) {
-                    smsg_create_object = Some(creature.build_create_object(movement));
-                } else if let Ok(game_object) = v_game_object.get(other_entity_id) {
-                    smsg_create_object =
-                        Some(game_object.build_create_object_for(&v_player[player_entity_id]));
-                } else {
-                    unreachable!("cannot generate SMSG_CREATE_OBJECT for this entity type");
-                }
-            }
+    // Sends the character and its surroundings to a client that reconnected while the character
+    // was kept in the world. Other players already see it.
+    pub fn reattach_player(self: &Arc<Map>, session: Arc<WorldSession>) {
+        let Some(player_entity_id) = session.player_entity_id() else {
+            error!("Map::reattach_player: session has no player EntityId");
+            return;
+        };
+        let Some(player_guid) = session.player_guid() else {
+            error!("Map::reattach_player: player has no guid");
+            return;
+        };
 
-            if let Some(smsg) = smsg_create_object {
-                session.create_entity(&other_entity_guid, smsg);
-            } else {
-                warn!(
-                    "add_player_on_login: unable to generate a SmsgCreateObject for guid {:?}",
-                    other_entity_id
-                );
-            }
+        let player_position = self
+            .world()
+            .run(|v_wpos: View<WorldPosition>| v_wpos[player_entity_id])
+            .as_position();
+
+        self.world().run(
+            |mut vm_player: ViewMut<Player>,
+             v_movement: View<Movement>,
+             v_cooldowns: View<Cooldowns>| {
+                let player = &mut vm_player[player_entity_id];
+                player.movement_validator.reset();
+
+                let spell_cooldowns = v_cooldowns.get(player_entity_id).unwrap();
+                session.send_initial_spells(player, spell_cooldowns, self.world_context.clone());
+                session.send_initial_action_buttons(player);
+                session.send_initial_reputations(player);
+
+                let movement = v_movement
+                    .get(player_entity_id)
+                    .ok()
+                    .map(|m| m.build_update(self.world_context.clone(), &player_position));
+                let smsg_create_object = player.build_create_object(movement, true);
+
+                session.create_entity(&player_guid, smsg_create_object);
+            },
+        );
+
+        let entities_around: Vec<EntityId> = self.spatial_grid.search_ids_around_position(
+            &player_position,
+            self.visibility_distance,
+            true,
+            Some(&player_entity_id),
+        );
+        for other_entity_id in entities_around {
+            let other_entity_guid = self
+                .world()
+                .run(|v_guid: View<Guid>| v_guid[other_entity_id].0);
+            self.send_entity_to_player(
+                &session,
+                other_entity_id,
+                other_entity_guid,
+                player_entity_id,
+            );
         }
     }
 
@@ -1,8 +1,8 @@
-use std::{collections::HashMap, fmt::Display, sync::Arc};
+use std::{collections::HashMap, fmt::Display, sync::Arc, thread::JoinHandle};
 
 use atomic_counter::{AtomicCounter, RelaxedCounter};
-use log::info;
-use parking_lot::RwLock;
+use log::{error, info};
+use parking_lot::{Mutex, RwLock};
 
 use crate::{
     config::WorldConfig,
@@ -29,6 +29,7 @@ pub struct MapManager {
     data_store: Arc<DataStore>,
     next_instance_id: RelaxedCounter,
     terrains: RwLock<HashMap<u32, Arc<TerrainManager>>>,
+    continent_threads: Mutex<Vec<JoinHandle<()>>>,
 }
 
 impl MapManager {
@@ -39,6 +40,7 @@ impl MapManager {
             data_store,
             next_instance_id: RelaxedCounter::new(1),
             terrains: RwLock::new(HashMap::new()),
+            continent_threads: Mutex::new(Vec::new()),
         }
     }
 
@@ -82,13 +84,13 @@ impl MapManager {
                 game_object_spawns,
             ));
             self.maps.write().insert(key, map.clone());
-            let config = config.clone();
-            std::thread::Builder::new()
+            let thread = std::thread::Builder::new()
                 .name(format!("Map {}", map.id()))
                 .spawn(move || {
-                    map.start(config);
+                    map.start();
                 })
                 .unwrap();
+            self.continent_threads.lock().push(thread);
         }
     }
 
@@ -130,7 +132,7 @@ impl MapManager {
                         game_object_spawns,
                     ));
                     map_guard.insert(map_key, map.clone());
-                    map.start(self.config.clone());
+                    map.start();
 
                     Ok(map)
                 }
@@ -138,6 +140,29 @@ impl MapManager {
         }
     }
 
+    // Stops all maps, waits for the continents to finish their current tick and for the pending
+    // autosaves to be written
+    pub fn stop_all_maps(&self) {
+        info!("Stopping maps...");
+        for map in self.maps.read().values() {
+            map.stop();
+        }
+
+        for thread in self.continent_threads.lock().drain(..) {
+            if thread.join().is_err() {
+                error!("a map thread panicked while stopping");
+            }
+        }
+
+        for map in self.maps.read().values() {
+            map.flush_autosaves();
+        }
+    }
+
+    pub fn maps(&self) -> Vec<Arc<Map>> {
+        self.maps.read().values().cloned().collect()
+    }
+
     pub fn get_map(&self, map_key: MapKey) -> Option<Arc<Map>> {
         let guard = self.maps.read();
         guard.get(&map_key).cloned()
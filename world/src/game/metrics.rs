@@ -0,0 +1,356 @@
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use atomic_counter::{AtomicCounter, RelaxedCounter};
+use log::{error, info, warn};
+use tokio::{
+    io::{self, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::protocol::opcodes::Opcode;
+
+use super::{map_manager::MapKey, world_context::WorldContext};
+
+// Upper bounds of the histogram buckets, in seconds
+const TICK_DURATION_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.075, 0.1, 0.25, 0.5, 1.];
+const DB_SAVE_DURATION_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.];
+
+const OPCODE_COUNT: usize = Opcode::MAX as usize + 1;
+
+// Limits of the HTTP requests, the request line and the headers are read before answering
+const MAX_REQUEST_LINE_LENGTH: u64 = 1024;
+const MAX_REQUEST_LINES: usize = 100;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Histogram {
+    bounds: &'static [f64],
+    buckets: Vec<RelaxedCounter>, // Not cumulative, one per bound
+    count: RelaxedCounter,
+    sum_micros: RelaxedCounter,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: bounds.iter().map(|_| RelaxedCounter::new(0)).collect(),
+            count: RelaxedCounter::new(0),
+            sum_micros: RelaxedCounter::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(index) = self.bounds.iter().position(|&bound| secs <= bound) {
+            self.buckets[index].inc();
+        }
+        self.count.inc();
+        self.sum_micros.add(duration.as_micros() as usize);
+    }
+
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let bucket_labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{labels},")
+        };
+
+        let mut cumulative = 0;
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.get();
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{bucket_labels}le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let count = self.count.get();
+        let sum = self.sum_micros.get() as f64 / 1_000_000.;
+        let _ = writeln!(out, "{name}_bucket{{{bucket_labels}le=\"+Inf\"}} {count}");
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {sum}");
+        let _ = writeln!(out, "{name}_count{{{labels}}} {count}");
+    }
+}
+
+// Updated by the map thread at the end of each tick
+pub struct MapMetrics {
+    pub tick_duration: Histogram,
+    players: AtomicUsize,
+    creatures: AtomicUsize,
+    game_objects: AtomicUsize,
+}
+
+impl Default for MapMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MapMetrics {
+    pub fn new() -> Self {
+        Self {
+            tick_duration: Histogram::new(TICK_DURATION_BUCKETS),
+            players: AtomicUsize::new(0),
+            creatures: AtomicUsize::new(0),
+            game_objects: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn set_entity_counts(&self, players: usize, creatures: usize, game_objects: usize) {
+        self.players.store(players, Ordering::Relaxed);
+        self.creatures.store(creatures, Ordering::Relaxed);
+        self.game_objects.store(game_objects, Ordering::Relaxed);
+    }
+}
+
+pub struct Metrics {
+    packets_received: Vec<RelaxedCounter>, // Indexed by opcode
+    packets_sent: Vec<RelaxedCounter>,     // Indexed by opcode
+    pub db_save_duration: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            packets_received: (0..OPCODE_COUNT).map(|_| RelaxedCounter::new(0)).collect(),
+            packets_sent: (0..OPCODE_COUNT).map(|_| RelaxedCounter::new(0)).collect(),
+            db_save_duration: Histogram::new(DB_SAVE_DURATION_BUCKETS),
+        }
+    }
+
+    pub fn record_packet_received(&self, opcode: u32) {
+        if let Some(counter) = self.packets_received.get(opcode as usize) {
+            counter.inc();
+        }
+    }
+
+    pub fn record_packet_sent(&self, opcode: u16) {
+        if let Some(counter) = self.packets_sent.get(opcode as usize) {
+            counter.inc();
+        }
+    }
+}
+
+// Serves the metrics in the Prometheus text format on GET /metrics
+pub async fn serve_metrics(world_context: Arc<WorldContext>) {
+    let config = world_context.config.get();
+    let config = &config.world.metrics;
+    let address = format!("{}:{}", config.host, config.port);
+    let listener = match TcpListener::bind(&address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("unable to start the metrics endpoint on {address}: {e}");
+            return;
+        }
+    };
+    info!("Metrics available on http://{address}/metrics");
+
+    loop {
+        let socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(e) => {
+                warn!("metrics endpoint failed to accept a connection: {e}");
+                continue;
+            }
+        };
+
+        let world_context = world_context.clone();
+        tokio::spawn(async move {
+            if let Err(e) = answer_request(socket, &world_context).await {
+                warn!("metrics request failed: {e}");
+            }
+        });
+    }
+}
+
+async fn answer_request(socket: TcpStream, world_context: &WorldContext) -> io::Result<()> {
+    let mut reader = BufReader::new(socket);
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request_line(&mut reader))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "request took too long"))??;
+    let mut socket = reader.into_inner();
+
+    let response = if request.starts_with("GET /metrics ") {
+        let body = render(world_context);
+        format!(
+            "HTTP/1.1 200 OK\r\n\
+            Content-Type: text/plain; version=0.0.4\r\n\
+            Content-Length: {}\r\n\
+            Connection: close\r\n\r\n{body}",
+            body.len()
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+    };
+
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+// Only the request line matters, the headers are read up to the blank line ending them and ignored
+async fn read_request_line(reader: &mut BufReader<TcpStream>) -> io::Result<String> {
+    let mut request_line = None;
+    for _ in 0..MAX_REQUEST_LINES {
+        let mut line = Vec::new();
+        (&mut *reader)
+            .take(MAX_REQUEST_LINE_LENGTH)
+            .read_until(b'\n', &mut line)
+            .await?;
+        if !line.ends_with(b"\n") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request line too long or incomplete request",
+            ));
+        }
+
+        let line = String::from_utf8_lossy(&line).trim_end().to_owned();
+        if line.is_empty() {
+            return request_line
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "empty request"));
+        }
+        request_line.get_or_insert(line);
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "too many headers",
+    ))
+}
+
+fn render(world_context: &WorldContext) -> String {
+    let mut out = String::new();
+    let metrics = &world_context.metrics;
+
+    let maps = world_context.map_manager.maps();
+
+    let _ = writeln!(
+        out,
+        "# HELP rustbolt_map_tick_duration_seconds Duration of the map ticks"
+    );
+    let _ = writeln!(out, "# TYPE rustbolt_map_tick_duration_seconds histogram");
+    for map in &maps {
+        map.metrics().tick_duration.write(
+            &mut out,
+            "rustbolt_map_tick_duration_seconds",
+            &map_labels(map.key()),
+        );
+    }
+
+    let _ = writeln!(out, "# HELP rustbolt_map_entities Entities on each map");
+    let _ = writeln!(out, "# TYPE rustbolt_map_entities gauge");
+    for map in &maps {
+        let labels = map_labels(map.key());
+        let map_metrics = map.metrics();
+        for (kind, count) in [
+            ("player", &map_metrics.players),
+            ("creature", &map_metrics.creatures),
+            ("game_object", &map_metrics.game_objects),
+        ] {
+            let _ = writeln!(
+                out,
+                "rustbolt_map_entities{{{labels},kind=\"{kind}\"}} {}",
+                count.load(Ordering::Relaxed)
+            );
+        }
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP rustbolt_online_sessions Sessions in the world or on the characters list"
+    );
+    let _ = writeln!(out, "# TYPE rustbolt_online_sessions gauge");
+    let _ = writeln!(
+        out,
+        "rustbolt_online_sessions {}",
+        world_context.session_holder.count()
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP rustbolt_login_queue_length Sessions waiting in the login queue"
+    );
+    let _ = writeln!(out, "# TYPE rustbolt_login_queue_length gauge");
+    let _ = writeln!(
+        out,
+        "rustbolt_login_queue_length {}",
+        world_context.login_queue.len()
+    );
+
+    for (name, help, counters) in [
+        (
+            "rustbolt_packets_received_total",
+            "Packets received from the clients",
+            &metrics.packets_received,
+        ),
+        (
+            "rustbolt_packets_sent_total",
+            "Packets sent to the clients",
+            &metrics.packets_sent,
+        ),
+    ] {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} counter");
+        for (opcode, counter) in counters.iter().enumerate() {
+            let count = counter.get();
+            if count == 0 {
+                continue;
+            }
+
+            let opcode_name = Opcode::n(opcode as u32)
+                .map_or_else(|| format!("{opcode:#X}"), |opcode| format!("{opcode:?}"));
+            let _ = writeln!(out, "{name}{{opcode=\"{opcode_name}\"}} {count}");
+        }
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP rustbolt_flood_dropped_packets_total Packets dropped by the flood protection"
+    );
+    let _ = writeln!(out, "# TYPE rustbolt_flood_dropped_packets_total counter");
+    let _ = writeln!(
+        out,
+        "rustbolt_flood_dropped_packets_total {}",
+        world_context.flood_stats.dropped_packets.get()
+    );
+    let _ = writeln!(
+        out,
+        "# HELP rustbolt_flood_kicked_sessions_total Sessions kicked by the flood protection"
+    );
+    let _ = writeln!(out, "# TYPE rustbolt_flood_kicked_sessions_total counter");
+    let _ = writeln!(
+        out,
+        "rustbolt_flood_kicked_sessions_total {}",
+        world_context.flood_stats.kicked_sessions.get()
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP rustbolt_db_save_duration_seconds Duration of the character saves"
+    );
+    let _ = writeln!(out, "# TYPE rustbolt_db_save_duration_seconds histogram");
+    metrics
+        .db_save_duration
+        .write(&mut out, "rustbolt_db_save_duration_seconds", "");
+
+    out
+}
+
+fn map_labels(map_key: MapKey) -> String {
+    format!(
+        "map=\"{}\",instance=\"{}\"",
+        map_key.map_id,
+        map_key.instance_id.unwrap_or(0)
+    )
+}
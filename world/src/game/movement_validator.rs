@@ -0,0 +1,338 @@
+use std::{fmt, time::Instant};
+
+use enumflags2::make_bitflags;
+
+use crate::{
+    config::MovementValidationSection, ecs::components::movement::Movement,
+    entities::position::WorldPosition, protocol::packets::MovementInfo,
+    shared::constants::MovementFlag,
+};
+
+use super::terrain_manager::TerrainManager;
+
+// Distance that can always be covered between two packets, to absorb rounding on the client side
+const DISTANCE_SLACK: f32 = 0.5;
+
+#[derive(Debug)]
+pub enum MovementViolation {
+    TooFast { distance: f32, max_distance: f32 },
+    PositionJump { distance: f32 },
+    ClockDrift { drift_ms: i64 },
+    BelowTerrain { z: f32 },
+}
+
+impl fmt::Display for MovementViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovementViolation::TooFast {
+                distance,
+                max_distance,
+            } => write!(
+                f,
+                "moved {distance:.2} yards, at most {max_distance:.2} allowed"
+            ),
+            MovementViolation::PositionJump { distance } => {
+                write!(f, "jumped {distance:.2} yards")
+            }
+            MovementViolation::ClockDrift { drift_ms } => {
+                write!(f, "client clock is {drift_ms}ms ahead of the server")
+            }
+            MovementViolation::BelowTerrain { z } => {
+                write!(f, "went below the terrain (z = {z:.2})")
+            }
+        }
+    }
+}
+
+// Checks the positions sent by a client against the previous accepted position, the speed of the
+// player, the time elapsed on both sides and the terrain
+pub struct MovementValidator {
+    last_client_timestamp: Option<u32>,
+    // Client timestamp and server time of the first packet since the last reset, to measure how
+    // much faster the client clock runs
+    clock_reference: Option<(u32, Instant)>,
+}
+
+impl Default for MovementValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MovementValidator {
+    pub fn new() -> Self {
+        Self {
+            last_client_timestamp: None,
+            clock_reference: None,
+        }
+    }
+
+    // To call when the position of the player is set by the server (login, teleports)
+    pub fn reset(&mut self) {
+        self.last_client_timestamp = None;
+        self.clock_reference = None;
+    }
+
+    pub fn validate(
+        &mut self,
+        movement_info: &MovementInfo,
+        current_position: &WorldPosition,
+        movement: &Movement,
+        terrain_manager: &TerrainManager,
+        config: &MovementValidationSection,
+    ) -> Result<(), MovementViolation> {
+        let previous_timestamp = self.last_client_timestamp.replace(movement_info.timestamp);
+        let &mut (reference_timestamp, reference_instant) = self
+            .clock_reference
+            .get_or_insert((movement_info.timestamp, Instant::now()));
+
+        // The server lets units fly, the client flags can't be trusted
+        let can_fly = movement
+            .flags
+            .intersects(make_bitflags!(MovementFlag::{CanFly | PlayerFlying}));
+        let new_position = movement_info.position;
+        let distance = current_position
+            .as_position()
+            .distance_to(new_position, can_fly);
+
+        if distance > config.max_position_jump {
+            return Err(MovementViolation::PositionJump { distance });
+        }
+
+        if let Some(previous_timestamp) = previous_timestamp {
+            let elapsed_secs =
+                movement_info.timestamp.wrapping_sub(previous_timestamp) as f32 / 1000.;
+            let max_speed = if can_fly {
+                movement.speed_flight.max(movement.speed_run)
+            } else {
+                movement.speed_run.max(movement.speed_swim)
+            };
+            let max_distance = max_speed * config.speed_tolerance * elapsed_secs + DISTANCE_SLACK;
+
+            if distance > max_distance {
+                return Err(MovementViolation::TooFast {
+                    distance,
+                    max_distance,
+                });
+            }
+        }
+
+        let client_elapsed_ms = movement_info.timestamp.wrapping_sub(reference_timestamp) as i64;
+        let server_elapsed_ms = reference_instant.elapsed().as_millis() as i64;
+        let drift_ms = client_elapsed_ms - server_elapsed_ms;
+        if drift_ms > config.clock_drift_tolerance_ms {
+            // Start measuring again so that a single hiccup is only reported once
+            self.clock_reference = Some((movement_info.timestamp, Instant::now()));
+            return Err(MovementViolation::ClockDrift { drift_ms });
+        }
+
+        // No ground or floor under the player where the terrain is known means they went through it
+        let ground_or_floor_height = terrain_manager.get_ground_or_floor_height(
+            new_position.x,
+            new_position.y,
+            new_position.z + config.below_terrain_tolerance,
+        );
+        if ground_or_floor_height.is_none()
+            && terrain_manager.has_terrain_at(new_position.x, new_position.y)
+        {
+            return Err(MovementViolation::BelowTerrain { z: new_position.z });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use shared::models::terrain_info::{Terrain, TerrainBlock, TerrainChunk};
+
+    use super::*;
+    use crate::{
+        config::MovementViolationAction, ecs::components::movement::MovementKind,
+        entities::position::Position, game::map_manager::MapKey,
+    };
+
+    fn config() -> MovementValidationSection {
+        MovementValidationSection {
+            enabled: true,
+            violation_action: MovementViolationAction::Log,
+            speed_tolerance: 1.2,
+            clock_drift_tolerance_ms: 1000,
+            max_position_jump: 50.,
+            below_terrain_tolerance: 5.,
+        }
+    }
+
+    fn world_position(x: f32, y: f32, z: f32) -> WorldPosition {
+        WorldPosition {
+            map_key: MapKey::for_continent(0),
+            zone: 0,
+            x,
+            y,
+            z,
+            o: 0.,
+        }
+    }
+
+    fn movement_info(timestamp: u32, x: f32, y: f32, z: f32) -> MovementInfo {
+        MovementInfo {
+            movement_flags: 0,
+            movement_flags2: 0,
+            timestamp,
+            position: Position { x, y, z, o: 0. },
+            fall_time: 0,
+        }
+    }
+
+    // Flat ground at z = 0 on the block containing the test positions
+    fn flat_terrain() -> TerrainManager {
+        let chunks = (0..16)
+            .flat_map(|row| (0..16).map(move |col| (row, col)))
+            .map(|(row, col)| TerrainChunk::new(row, col, 0, 0., 0, [0.; 145], None))
+            .collect();
+        let mut blocks = HashMap::new();
+        blocks.insert(
+            TerrainManager::block_coords(10., 10.),
+            Terrain {
+                ground: TerrainBlock::new(chunks),
+                collision_mesh: None,
+            },
+        );
+
+        TerrainManager::from_blocks(blocks)
+    }
+
+    #[test]
+    fn test_accepts_movement_at_run_speed() {
+        let mut validator = MovementValidator::new();
+        let movement = Movement::new(MovementKind::Idle);
+        let terrain = flat_terrain();
+
+        let start = world_position(10., 10., 0.);
+        assert!(validator
+            .validate(
+                &movement_info(0, 10., 10., 0.),
+                &start,
+                &movement,
+                &terrain,
+                &config()
+            )
+            .is_ok());
+        assert!(validator
+            .validate(
+                &movement_info(500, 13., 10., 0.),
+                &start,
+                &movement,
+                &terrain,
+                &config()
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_rejects_movement_too_fast() {
+        let mut validator = MovementValidator::new();
+        let movement = Movement::new(MovementKind::Idle);
+        let terrain = TerrainManager::empty();
+
+        let start = world_position(10., 10., 0.);
+        validator
+            .validate(
+                &movement_info(0, 10., 10., 0.),
+                &start,
+                &movement,
+                &terrain,
+                &config(),
+            )
+            .unwrap();
+        let result = validator.validate(
+            &movement_info(1000, 30., 10., 0.),
+            &start,
+            &movement,
+            &terrain,
+            &config(),
+        );
+        assert!(matches!(result, Err(MovementViolation::TooFast { .. })));
+    }
+
+    #[test]
+    fn test_rejects_position_jump() {
+        let mut validator = MovementValidator::new();
+        let movement = Movement::new(MovementKind::Idle);
+        let terrain = TerrainManager::empty();
+
+        let start = world_position(10., 10., 0.);
+        let result = validator.validate(
+            &movement_info(0, 110., 10., 0.),
+            &start,
+            &movement,
+            &terrain,
+            &config(),
+        );
+        assert!(matches!(
+            result,
+            Err(MovementViolation::PositionJump { .. })
+        ));
+    }
+
+    #[test]
+    fn test_rejects_client_clock_running_ahead() {
+        let mut validator = MovementValidator::new();
+        let movement = Movement::new(MovementKind::Idle);
+        let terrain = TerrainManager::empty();
+
+        let start = world_position(10., 10., 0.);
+        validator
+            .validate(
+                &movement_info(0, 10., 10., 0.),
+                &start,
+                &movement,
+                &terrain,
+                &config(),
+            )
+            .unwrap();
+        let result = validator.validate(
+            &movement_info(5000, 10., 10., 0.),
+            &start,
+            &movement,
+            &terrain,
+            &config(),
+        );
+        assert!(matches!(result, Err(MovementViolation::ClockDrift { .. })));
+    }
+
+    #[test]
+    fn test_rejects_position_below_terrain() {
+        let mut validator = MovementValidator::new();
+        let movement = Movement::new(MovementKind::Idle);
+        let terrain = flat_terrain();
+
+        let start = world_position(10., 10., -10.);
+        let result = validator.validate(
+            &movement_info(0, 10., 10., -10.),
+            &start,
+            &movement,
+            &terrain,
+            &config(),
+        );
+        assert!(matches!(
+            result,
+            Err(MovementViolation::BelowTerrain { .. })
+        ));
+
+        // Within the tolerance
+        let start = world_position(10., 10., -4.);
+        validator.reset();
+        assert!(validator
+            .validate(
+                &movement_info(0, 10., 10., -4.),
+                &start,
+                &movement,
+                &terrain,
+                &config()
+            )
+            .is_ok());
+    }
+}
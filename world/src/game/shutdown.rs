@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use binrw::NullString;
+use log::info;
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+use crate::{
+    protocol::{packets::SmsgServerMessage, server::ServerMessage},
+    shared::constants::ServerMessageType,
+};
+
+use super::world_context::WorldContext;
+
+// Exit code of the process when the server stops for a restart, so that a supervisor can tell a
+// restart apart from a shutdown
+pub const RESTART_EXIT_CODE: i32 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShutdownKind {
+    Shutdown,
+    Restart,
+}
+
+struct PendingShutdown {
+    kind: ShutdownKind,
+    deadline: Instant,
+    last_announced_secs: Option<u64>,
+}
+
+// Countdown before the world server stops, announced to the players with SMSG_SERVER_MESSAGE
+#[derive(Default)]
+pub struct WorldShutdown {
+    pending: Mutex<Option<PendingShutdown>>,
+}
+
+impl WorldShutdown {
+    pub fn new() -> Self {
+        Self {
+            pending: Mutex::new(None),
+        }
+    }
+
+    // Replaces any countdown in progress
+    pub fn schedule(&self, kind: ShutdownKind, delay: Duration, world_context: &WorldContext) {
+        info!("{kind:?} scheduled in {} second(s)", delay.as_secs());
+
+        let mut pending = PendingShutdown {
+            kind,
+            deadline: Instant::now() + delay,
+            last_announced_secs: None,
+        };
+        Self::announce(&mut pending, delay.as_secs(), world_context);
+
+        *self.pending.lock() = Some(pending);
+    }
+
+    // Returns false if there was no countdown in progress
+    pub fn cancel(&self, world_context: &WorldContext) -> bool {
+        let Some(pending) = self.pending.lock().take() else {
+            return false;
+        };
+
+        info!("{:?} cancelled", pending.kind);
+
+        let message_type = match pending.kind {
+            ShutdownKind::Shutdown => ServerMessageType::ShutdownCancelled,
+            ShutdownKind::Restart => ServerMessageType::RestartCancelled,
+        };
+        Self::broadcast(message_type, "", world_context);
+
+        true
+    }
+
+    // Announces the remaining time when needed, returns the kind of shutdown once the countdown
+    // has elapsed
+    pub fn update(&self, world_context: &WorldContext) -> Option<ShutdownKind> {
+        let mut guard = self.pending.lock();
+        let pending = guard.as_mut()?;
+
+        let remaining = pending
+            .deadline
+            .saturating_duration_since(Instant::now())
+            .as_secs_f32()
+            .ceil() as u64;
+        if remaining == 0 {
+            return guard.take().map(|pending| pending.kind);
+        }
+
+        if Self::should_announce(remaining) {
+            Self::announce(pending, remaining, world_context);
+        }
+
+        None
+    }
+
+    fn should_announce(remaining_secs: u64) -> bool {
+        match remaining_secs {
+            0 => false,
+            1..=10 => true,
+            11..=60 => remaining_secs % 15 == 0,
+            61..=600 => remaining_secs % 60 == 0,
+            _ => remaining_secs % 300 == 0,
+        }
+    }
+
+    fn announce(pending: &mut PendingShutdown, remaining_secs: u64, world_context: &WorldContext) {
+        if pending.last_announced_secs == Some(remaining_secs) {
+            return;
+        }
+        pending.last_announced_secs = Some(remaining_secs);
+
+        let message_type = match pending.kind {
+            ShutdownKind::Shutdown => ServerMessageType::ShutdownTime,
+            ShutdownKind::Restart => ServerMessageType::RestartTime,
+        };
+        Self::broadcast(
+            message_type,
+            &Self::format_remaining_time(remaining_secs),
+            world_context,
+        );
+    }
+
+    fn broadcast(message_type: ServerMessageType, text: &str, world_context: &WorldContext) {
+        let packet = ServerMessage::new(SmsgServerMessage {
+            message_type: message_type as u32,
+            text: NullString::from(text),
+        });
+
+        for session in world_context.session_holder.get_matching_sessions(|_| true) {
+            let _ = session.send(&packet);
+        }
+    }
+
+    // Formats durations as "1h 5m 30s", omitting the zero units
+    fn format_remaining_time(secs: u64) -> String {
+        let parts: Vec<String> = [
+            (secs / 3600, "h"),
+            ((secs % 3600) / 60, "m"),
+            (secs % 60, "s"),
+        ]
+        .into_iter()
+        .filter(|&(value, _)| value > 0)
+        .map(|(value, unit)| format!("{value}{unit}"))
+        .collect();
+
+        if parts.is_empty() {
+            "0s".to_owned()
+        } else {
+            parts.join(" ")
+        }
+    }
+}
@@ -106,6 +106,30 @@ impl SpellCastTargets {
         }
     }
 
+    pub fn unit_guid(&self) -> Option<ObjectGuid> {
+        self.unit_guid
+    }
+
+    pub fn game_object_guid(&self) -> Option<ObjectGuid> {
+        self.game_object_guid
+    }
+
+    pub fn item_guid(&self) -> Option<ObjectGuid> {
+        self.item_guid
+    }
+
+    pub fn source_position(&self) -> Option<Position> {
+        self.source_position
+    }
+
+    pub fn destination_position(&self) -> Option<Position> {
+        self.destination_position
+    }
+
+    pub fn string_target(&self) -> Option<&str> {
+        self.string_target.as_deref()
+    }
+
     pub fn unit_target(&self) -> Option<EntityId> {
         assert!(
             self.is_initialized,
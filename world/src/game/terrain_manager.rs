@@ -49,19 +49,28 @@ impl TerrainManager {
         }
     }
 
+    #[cfg(test)]
+    pub fn from_blocks(blocks: HashMap<TerrainBlockCoords, Terrain>) -> Self {
+        Self { blocks }
+    }
+
+    // Coordinates of the terrain block containing this position
+    pub fn block_coords(position_x: f32, position_y: f32) -> TerrainBlockCoords {
+        let offset: f32 = MAP_WIDTH_IN_BLOCKS as f32 / 2.0;
+
+        TerrainBlockCoords {
+            row: (offset - (position_x / BLOCK_WIDTH)).floor() as usize,
+            col: (offset - (position_y / BLOCK_WIDTH)).floor() as usize,
+        }
+    }
+
     pub fn get_ground_or_floor_height(
         &self,
         position_x: f32,
         position_y: f32,
         position_z: f32,
     ) -> Option<f32> {
-        let offset: f32 = MAP_WIDTH_IN_BLOCKS as f32 / 2.0;
-        let block_row = (offset - (position_x / BLOCK_WIDTH)).floor() as usize;
-        let block_col = (offset - (position_y / BLOCK_WIDTH)).floor() as usize;
-        let terrain_block_coords = TerrainBlockCoords {
-            row: block_row,
-            col: block_col,
-        };
+        let terrain_block_coords = Self::block_coords(position_x, position_y);
 
         self.blocks.get(&terrain_block_coords).and_then(|terrain| {
             // Check if we are within a WMO first
@@ -98,14 +107,15 @@ impl TerrainManager {
         })
     }
 
+    // Whether the terrain block containing this position is loaded
+    pub fn has_terrain_at(&self, position_x: f32, position_y: f32) -> bool {
+        let terrain_block_coords = Self::block_coords(position_x, position_y);
+
+        self.blocks.contains_key(&terrain_block_coords)
+    }
+
     pub fn get_area_id(&self, position_x: f32, position_y: f32) -> Option<u32> {
-        let offset: f32 = MAP_WIDTH_IN_BLOCKS as f32 / 2.0;
-        let block_row = (offset - (position_x / BLOCK_WIDTH)).floor() as usize;
-        let block_col = (offset - (position_y / BLOCK_WIDTH)).floor() as usize;
-        let terrain_block_coords = TerrainBlockCoords {
-            row: block_row,
-            col: block_col,
-        };
+        let terrain_block_coords = Self::block_coords(position_x, position_y);
 
         self.blocks.get(&terrain_block_coords).map(|terrain| {
             // TODO: Area ID might come from the WMO
@@ -1,16 +1,25 @@
 use std::{sync::Arc, time::Duration};
 
 use atomic_counter::{AtomicCounter, RelaxedCounter};
+use config::ConfigError;
+use log::{error, info, warn};
 use tokio::time::Instant;
 
 use crate::{
-    chat_commands::ChatCommands, config::WorldConfig, create_wrapped_resource,
-    database_context::DatabaseContext, session::opcode_handler::OpcodeHandler, DataStore,
-    SessionHolder,
+    chat_commands::ChatCommands,
+    config::LiveConfig,
+    create_wrapped_resource,
+    database_context::DatabaseContext,
+    session::{
+        character_claims::CharacterClaims, flood_protection::FloodProtectionStats,
+        login_queue::LoginQueue, opcode_handler::OpcodeHandler,
+    },
+    DataStore, SessionHolder,
 };
 
 use super::{
-    aura_effect_handler::AuraEffectHandler, map_manager::MapManager,
+    aura_effect_handler::AuraEffectHandler, character_names::CharacterNameValidator,
+    map_manager::MapManager, metrics::Metrics, shutdown::WorldShutdown,
     spell_effect_handler::SpellEffectHandler,
 };
 
@@ -20,12 +29,19 @@ pub struct WorldContext {
     pub opcode_handler: Arc<OpcodeHandler>,
     pub spell_effect_handler: Arc<SpellEffectHandler>,
     pub aura_effect_handler: Arc<AuraEffectHandler>,
-    pub config: Arc<WorldConfig>,
+    pub config: LiveConfig,
     pub start_time: Instant,
     pub session_holder: Arc<SessionHolder<u32>>,
+    pub login_queue: LoginQueue,
+    pub flood_stats: FloodProtectionStats,
+    pub metrics: Arc<Metrics>,
     pub map_manager: Arc<MapManager>,
+    pub shutdown: WorldShutdown,
     pub chat_commands: ChatCommands,
+    pub character_names: CharacterNameValidator,
+    pub character_claims: CharacterClaims,
     pub next_item_guid_counter: RelaxedCounter,
+    pub next_save_generation_counter: RelaxedCounter,
 }
 
 impl WorldContext {
@@ -34,9 +50,41 @@ impl WorldContext {
         self.start_time.elapsed()
     }
 
+    // Lets the sessions waiting in the login queue in as long as the realm is not full
+    pub fn update_login_queue(&self) {
+        let max_players = self.config.get().world.realm.max_players as usize;
+        let free_slots = max_players.saturating_sub(self.session_holder.count());
+        self.login_queue.update(free_slots);
+    }
+
+    // Applies the settings that can change live and returns the changed ones that need a restart
+    pub fn reload_config(&self) -> Result<Vec<&'static str>, ConfigError> {
+        match self.config.reload() {
+            Ok(needs_restart) => {
+                info!("Config reloaded");
+                for setting in &needs_restart {
+                    warn!("{setting} changed but will only be applied after a restart");
+                }
+                // The realm may accept more players now
+                self.update_login_queue();
+                self.character_names
+                    .load_profanity_list(&self.config.get().world.character_names);
+                Ok(needs_restart)
+            }
+            Err(e) => {
+                error!("unable to reload the config: {e}");
+                Err(e)
+            }
+        }
+    }
+
     pub fn next_item_guid(&self) -> u32 {
         self.next_item_guid_counter.inc().try_into().unwrap()
     }
+
+    pub fn next_save_generation(&self) -> u64 {
+        self.next_save_generation_counter.inc() as u64
+    }
 }
 
 create_wrapped_resource!(WrappedWorldContext, WorldContext);
@@ -5,9 +5,10 @@ use crate::protocol::server::ServerMessage;
 use crate::repositories::account::AccountRepository;
 use crate::shared::constants::{ADDON_PUBLIC_KEY, STANDARD_ADDON_CRC};
 use crate::shared::response_codes::ResponseCodes;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 pub use crate::datastore::DataStore;
+pub use crate::entities::{object_guid::ObjectGuid, position::Position};
 use crate::protocol::packets::{
     ClientAddonInfo, CmsgAuthSession, ServerAddonInfo, SmsgAddonInfo, SmsgAuthChallenge,
     SmsgAuthResponse,
@@ -16,16 +17,16 @@ use binrw::io::Cursor;
 use binrw::BinReaderExt;
 use game::world_context::WorldContext;
 use hex::FromHex;
-use log::{error, trace};
+use log::{info, trace};
 use protocol::client::ClientMessageHeader;
 pub use session::session_holder::SessionHolder;
 use session::world_session::WorldSession;
-use tokio::io::AsyncReadExt;
 use tokio::net::TcpStream;
 use tokio::sync::mpsc::error::SendError;
 use wow_srp::normalized_string::NormalizedString;
 use wow_srp::tbc_header::ProofSeed;
 
+pub mod admin_console;
 pub mod chat_commands;
 pub mod config;
 pub mod database_context;
@@ -54,16 +55,21 @@ mod entities {
 pub mod game {
     pub mod aura;
     pub mod aura_effect_handler;
+    pub mod autosave_writer;
+    pub mod character_names;
     pub mod entity_manager;
     pub mod experience;
     pub mod gossip;
     pub mod loot;
     pub mod map;
     pub mod map_manager;
+    pub mod metrics;
     pub mod movement_spline;
+    pub mod movement_validator;
     pub mod packet_broadcaster;
     pub mod packet_queue;
     pub mod quad_tree;
+    pub mod shutdown;
     pub mod spatial_grid;
     pub mod spell;
     pub mod spell_cast_target;
@@ -71,7 +77,7 @@ pub mod game {
     pub mod terrain_manager;
     pub mod world_context;
 }
-mod protocol {
+pub mod protocol {
     pub mod client;
     pub mod handlers;
     pub mod opcodes;
@@ -80,6 +86,7 @@ mod protocol {
 }
 pub mod repositories {
     pub mod account;
+    pub mod account_data;
     pub mod character;
     pub mod creature;
     pub mod creature_static_data;
@@ -88,14 +95,20 @@ pub mod repositories {
     pub mod item;
     pub mod player_static_data;
     pub mod quest;
+    pub mod realm;
 }
 pub mod session {
+    pub mod character_claims;
+    pub mod flood_protection;
+    pub mod login_queue;
     pub mod opcode_handler;
+    pub mod packet_capture;
+    pub mod replay;
     pub mod session_holder;
     pub mod world_session;
     pub mod world_socket;
 }
-mod shared {
+pub mod shared {
     pub mod constants;
     pub mod response_codes;
 }
@@ -129,6 +142,11 @@ pub enum WorldSocketError {
     BinRwError(binrw::Error),
     DbError(r2d2::Error),
     SessionNotFound,
+    ClientVersionMismatch,
+    MalformedPacket(String),
+    PacketTooLarge(usize),
+    Flooding(u32), // Opcode that exceeded its rate limit
+    Kicked,
 }
 
 impl From<std::io::Error> for WorldSocketError {
@@ -181,41 +199,10 @@ impl WorldSocketState<SocketOpened> {
 
 impl WorldSocketState<ServerSentAuthChallenge> {
     async fn read_socket_plain(&mut self) -> Result<ClientMessage, WorldSocketError> {
-        let mut buf = [0_u8; 6];
+        let raw_header = ClientMessage::read_raw_header(&mut self.state.socket).await?;
+        let client_header: ClientMessageHeader = Cursor::new(raw_header).read_le()?;
 
-        match self.state.socket.read_exact(&mut buf[..6]).await {
-            Ok(0) => {
-                trace!("Client disconnected");
-                Err(WorldSocketError::ClientDisconnected)
-            }
-            Ok(n) if n < 6 => {
-                error!("Received less than 6 bytes, need to handle partial header");
-                Err(WorldSocketError::SocketError(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "Received an incomplete client header",
-                )))
-            }
-            Ok(_) => {
-                let mut reader = Cursor::new(buf);
-                let client_header: ClientMessageHeader = reader.read_le()?;
-                let bytes_to_read: usize = client_header.size as usize - 4; // Client opcode is u32
-                let mut buf_payload = [0_u8; 1024];
-                self.state
-                    .socket
-                    .read_exact(&mut buf_payload[..bytes_to_read])
-                    .await
-                    .unwrap();
-
-                Ok(ClientMessage {
-                    header: client_header,
-                    payload: buf_payload[..bytes_to_read].to_vec(),
-                })
-            }
-            Err(e) => {
-                error!("Socket error, closing");
-                Err(WorldSocketError::SocketError(e))
-            }
-        }
+        ClientMessage::read_payload(&mut self.state.socket, client_header).await
     }
 
     async fn handle_auth_session(
@@ -243,6 +230,30 @@ impl WorldSocketState<ServerSentAuthChallenge> {
             .try_into()
             .unwrap();
 
+        let realm_build = self.state.world_context.config.get().world.realm.build;
+        let last_build = AccountRepository::fetch_last_build(&mut conn, account_id);
+        if last_build != Some(realm_build) {
+            info!(
+                "refused session for account {} with client build {:?} (realm build is {})",
+                account_id, last_build, realm_build
+            );
+
+            // Header encryption is not set up yet
+            let packet = ServerMessage::new(SmsgAuthResponse {
+                result: ResponseCodes::AuthVersionMismatch as u8,
+                billing_time: 0,
+                billing_flags: 0,
+                billing_rested: 0,
+                expansion: 1,
+                position_in_queue: 0,
+            });
+            packet.send_unencrypted(&mut self.state.socket).await?;
+
+            return Err(WorldSocketError::ClientVersionMismatch);
+        }
+
+        let security_level = AccountRepository::fetch_security_level(&mut conn, account_id);
+
         let encryption = self
             .state
             .seed
@@ -254,34 +265,74 @@ impl WorldSocketState<ServerSentAuthChallenge> {
             )
             .unwrap();
 
+        let world_context = self.state.world_context.clone();
+
+        // Take the client back to its character if it is still in the world after a disconnection
+        if let Some(session) = session_holder.reattach_session(&account_id) {
+            info!("account {} reconnected during its grace period", account_id);
+            session.attach_socket(self.state.socket, encryption, &world_context);
+
+            Self::send_auth_success(&session, addon_infos);
+            return Ok(WorldSocketState {
+                state: ServerSentAuthResponse { session },
+            });
+        }
+
         let session = WorldSession::new(
             self.state.socket,
             encryption,
             account_id,
-            self.state.world_context.clone(),
+            security_level,
+            world_context.clone(),
         );
 
-        let packet = ServerMessage::new(SmsgAuthResponse {
-            result: ResponseCodes::AuthOk as u8,
-            billing_time: 0,
-            billing_flags: 0,
-            billing_rested: 0,
-            expansion: 1,
-            position_in_queue: 0,
-        });
-
-        session.send(&packet).unwrap();
+        let config = world_context.config.get();
+        let realm_config = &config.world.realm;
+        let realm_is_full = session_holder.count() + world_context.login_queue.pending_releases()
+            >= realm_config.max_players as usize
+            || !world_context.login_queue.is_empty();
+        let bypasses_queue =
+            session.security_level as u8 >= realm_config.queue_bypass_security_level;
+        let mut released_slot = None;
+        if realm_is_full && !bypasses_queue {
+            info!(
+                "realm is full, account {} enters the login queue",
+                account_id
+            );
 
-        let packet = ServerMessage::new(Self::build_addon_infos(addon_infos));
+            let mut release_rx = world_context.login_queue.enqueue(session.clone());
+            let socket = session.socket();
+            loop {
+                // Keep reading the socket to notice the clients that leave the queue. They are not
+                // expected to send anything while waiting, such packets are dropped.
+                tokio::select! {
+                    released = &mut release_rx => {
+                        released_slot =
+                            Some(released.map_err(|_| WorldSocketError::ClientDisconnected)?);
+                        break;
+                    }
+                    client_message = socket.read_packet() => {
+                        if let Err(e) = client_message {
+                            info!("account {} left the login queue", account_id);
+                            world_context.login_queue.remove(&session);
+                            return Err(e);
+                        }
+                    }
+                }
+            }
+        }
 
-        session.send(&packet).unwrap();
+        Self::send_auth_success(&session, addon_infos);
 
-        if let Some(previous_session) = session_holder.insert_session(session.account_id, session) {
+        if let Some(previous_session) = session_holder.replace_session(session.account_id, session)
+        {
             previous_session.shutdown(
                 &mut self.state.world_context.database.characters.get().unwrap(),
                 self.state.world_context.clone(),
             );
         }
+        // The session holder counts the session from now on
+        drop(released_slot);
 
         if let Some(session) = session_holder.get_session(&account_id) {
             Ok(WorldSocketState {
@@ -294,6 +345,23 @@ impl WorldSocketState<ServerSentAuthChallenge> {
         }
     }
 
+    fn send_auth_success(session: &WorldSession, addon_infos: Vec<ClientAddonInfo>) {
+        let packet = ServerMessage::new(SmsgAuthResponse {
+            result: ResponseCodes::AuthOk as u8,
+            billing_time: 0,
+            billing_flags: 0,
+            billing_rested: 0,
+            expansion: 1,
+            position_in_queue: 0,
+        });
+
+        session.send(&packet).unwrap();
+
+        let packet = ServerMessage::new(Self::build_addon_infos(addon_infos));
+
+        session.send(&packet).unwrap();
+    }
+
     fn extract_addon_infos(raw_data: &[u8]) -> Vec<ClientAddonInfo> {
         let mut reader = Cursor::new(&raw_data[..4]);
         let uncompressed_size: u32 = reader.read_le().unwrap();
@@ -358,10 +426,46 @@ pub async fn process(
 
     loop {
         let session = state.state.session.clone();
-        if let Err(WorldSocketError::ClientDisconnected) =
-            WorldSession::process_incoming_packet(session).await
-        {
-            break Ok(());
+        match WorldSession::process_incoming_packet(session.clone(), &world_context).await {
+            Ok(_) => (),
+            Err(WorldSocketError::ClientDisconnected) => {
+                let grace_period = Duration::from_secs(
+                    world_context
+                        .config
+                        .get()
+                        .world
+                        .disconnect
+                        .grace_period_secs,
+                );
+                if session.player_entity_id().is_some() && !grace_period.is_zero() {
+                    info!(
+                        "account {} disconnected, keeping its character in the world for {:?}",
+                        session.account_id, grace_period
+                    );
+                    session.detach(grace_period, world_context.clone());
+                    break Ok(());
+                }
+
+                session.shutdown(
+                    &mut world_context.database.characters.get()?,
+                    world_context.clone(),
+                );
+                session_holder.remove_session_if_current(&session.account_id, &session);
+                // A slot may have been freed for the next session in the login queue
+                world_context.update_login_queue();
+                break Ok(());
+            }
+            Err(e) => {
+                // Don't trust anything coming from this client anymore
+                session.shutdown(
+                    &mut world_context.database.characters.get()?,
+                    world_context.clone(),
+                );
+                session.close_socket().await;
+                session_holder.remove_session_if_current(&session.account_id, &session);
+                world_context.update_login_queue();
+                break Err(e);
+            }
         }
     }
 }
@@ -1,23 +1,40 @@
-use std::sync::Arc;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use atomic_counter::RelaxedCounter;
+use clap::{Parser, Subcommand};
 use env_logger::Env;
-use log::info;
+use log::{error, info, warn};
 use r2d2_sqlite::SqliteConnectionManager;
 use rustbolt_world::{
+    admin_console::serve_admin_console,
     chat_commands::ChatCommands,
-    config::WorldConfig,
+    config::{LiveConfig, WorldConfig},
     database_context::DatabaseContext,
     game::{
-        aura_effect_handler::AuraEffectHandler, map_manager::MapManager,
-        spell_effect_handler::SpellEffectHandler, world_context::WorldContext,
+        aura_effect_handler::AuraEffectHandler,
+        character_names::CharacterNameValidator,
+        map_manager::MapManager,
+        metrics::{serve_metrics, Metrics},
+        shutdown::{ShutdownKind, WorldShutdown, RESTART_EXIT_CODE},
+        spell_effect_handler::SpellEffectHandler,
+        world_context::WorldContext,
+    },
+    repositories::{character::CharacterRepository, item::ItemRepository, realm::RealmRepository},
+    session::{
+        character_claims::CharacterClaims, flood_protection::FloodProtectionStats,
+        login_queue::LoginQueue, opcode_handler::OpcodeHandler, replay::replay_capture,
     },
-    repositories::item::ItemRepository,
-    session::opcode_handler::OpcodeHandler,
-    DataStore, SessionHolder,
+    DataStore, SessionHolder, WorldSocketError,
 };
 use tokio::{net::TcpListener, sync::Semaphore, time::Instant};
 
+const CHARACTER_PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
 mod embedded_characters {
     use refinery::embed_migrations;
     embed_migrations!("../sql_migrations/characters");
@@ -29,25 +46,42 @@ mod embedded_world {
 }
 
 fn main() {
+    let cli = Cli::parse();
+
     // Load config
     let config = Arc::new(WorldConfig::load().expect("Error in config file"));
 
     // Setup logging
     env_logger::Builder::from_env(Env::default().default_filter_or("debug")).init();
 
+    // Replays modify the accounts and characters, keep them away from the live databases
+    let live_databases_directory =
+        PathBuf::from(format!("{}/databases", config.common.data.directory));
+    let databases_directory = match &cli.command {
+        Some(Commands::Replay { databases, .. }) => {
+            match prepare_replay_databases(databases, &live_databases_directory) {
+                Ok(()) => databases.clone(),
+                Err(e) => {
+                    error!("Unable to prepare the replay databases: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => live_databases_directory.clone(),
+    };
+
     // Setup database connection pools
-    let db_pool_auth = r2d2::Pool::new(SqliteConnectionManager::file(format!(
-        "{}/databases/auth.db",
-        config.common.data.directory
-    )))
+    let db_pool_auth = r2d2::Pool::new(
+        SqliteConnectionManager::file(databases_directory.join("auth.db"))
+            .with_init(|c| c.execute_batch("PRAGMA foreign_keys = ON;")),
+    )
     .expect("Failed to create r2d2 SQlite connection pool (Auth DB)");
     let db_pool_auth = Arc::new(db_pool_auth);
 
     let mutex = Semaphore::new(1);
-    let sqlite_connection_manager_char = SqliteConnectionManager::file(format!(
-        "{}/databases/characters.db",
-        config.common.data.directory
-    ))
+    let sqlite_connection_manager_char = SqliteConnectionManager::file(
+        databases_directory.join("characters.db"),
+    )
     .with_init(move |c| {
         if mutex.try_acquire().is_ok() {
             embedded_characters::migrations::runner().run(c).unwrap();
@@ -59,10 +93,10 @@ fn main() {
     let db_pool_char = Arc::new(db_pool_char);
 
     let mutex = Semaphore::new(1);
-    let sqlite_connection_manager_world = SqliteConnectionManager::file(format!(
-        "{}/databases/world.db",
-        config.common.data.directory
-    ))
+    // Static data, shared with the replays
+    let sqlite_connection_manager_world = SqliteConnectionManager::file(
+        live_databases_directory.join("world.db"),
+    )
     .with_init(move |c| {
         if mutex.try_acquire().is_ok() {
             embedded_world::migrations::runner().run(c).unwrap();
@@ -88,9 +122,12 @@ fn main() {
         world: db_pool_world.clone(),
     });
 
-    let first_available_item_guid = {
+    let (first_available_item_guid, first_save_generation) = {
         let characters_conn = db_pool_char.get().unwrap();
-        ItemRepository::get_first_available_guid(&characters_conn)
+        (
+            ItemRepository::get_first_available_guid(&characters_conn),
+            CharacterRepository::get_last_save_generation(&characters_conn) + 1,
+        )
     };
 
     let start_time = Instant::now();
@@ -104,12 +141,19 @@ fn main() {
         opcode_handler: opcode_handler.clone(),
         spell_effect_handler,
         aura_effect_handler,
-        config: config.clone(),
+        config: LiveConfig::new(config.clone()),
         start_time,
         session_holder: session_holder.clone(),
+        login_queue: LoginQueue::new(),
+        flood_stats: FloodProtectionStats::default(),
+        metrics: Arc::new(Metrics::new()),
         map_manager: map_manager.clone(),
+        shutdown: WorldShutdown::new(),
         chat_commands: ChatCommands::build(),
+        character_names: CharacterNameValidator::new(&config.world.character_names),
+        character_claims: CharacterClaims::default(),
         next_item_guid_counter: RelaxedCounter::new(first_available_item_guid as usize),
+        next_save_generation_counter: RelaxedCounter::new(first_save_generation as usize),
     });
 
     map_manager.clone().instantiate_continents(
@@ -124,6 +168,17 @@ fn main() {
         .build()
         .unwrap();
 
+    if let Some(Commands::Replay { capture, speed, .. }) = cli.command {
+        match network_runtime.block_on(replay_capture(&capture, speed, world_context.clone())) {
+            Ok(count) => info!("Replayed {count} packet(s) from {}", capture.display()),
+            Err(e) => warn!("Replay of {} failed: {e}", capture.display()),
+        }
+
+        network_runtime.shutdown_timeout(Duration::from_secs(1));
+        map_manager.stop_all_maps();
+        return;
+    }
+
     // Bind the listener to the address
     let listener = network_runtime.block_on(async {
         TcpListener::bind(format!(
@@ -134,26 +189,282 @@ fn main() {
         .unwrap()
     });
 
+    // Register the realm to the auth server and publish the character counts for the realm list
+    {
+        let realm_config = &config.world.realm;
+        let mut auth_conn = db_pool_auth.get().unwrap();
+        let characters_conn = db_pool_char.get().unwrap();
+        RealmRepository::sync_character_counts(
+            &mut auth_conn,
+            realm_config.id,
+            CharacterRepository::count_characters_per_account(&characters_conn),
+        );
+        RealmRepository::register(&auth_conn, realm_config.id, realm_config.max_players);
+    }
+
     info!("World server ready");
 
-    network_runtime.block_on(async {
-        let join_handle = tokio::spawn(async move {
+    let shutdown_kind = network_runtime.block_on(async {
+        // Let the auth server know that the realm is still alive
+        let heartbeat_session_holder = session_holder.clone();
+        let heartbeat_config = config.clone();
+        let heartbeat_world_context = world_context.clone();
+        let heartbeat_db_pool_auth = db_pool_auth.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(
+                heartbeat_config.world.realm.heartbeat_interval_secs,
+            ));
+            loop {
+                interval.tick().await;
+
+                let db_pool_auth = heartbeat_db_pool_auth.clone();
+                let realm_id = heartbeat_config.world.realm.id;
+                let online_players = heartbeat_session_holder.count() as u32;
+                // Can change with a config reload
+                let max_players = heartbeat_world_context.config.get().world.realm.max_players;
+                // A failed heartbeat is retried on the next tick, before the auth server
+                // considers the realm offline
+                let heartbeat = tokio::task::spawn_blocking(move || match db_pool_auth.get() {
+                    Ok(auth_conn) => {
+                        RealmRepository::heartbeat(
+                            &auth_conn,
+                            realm_id,
+                            online_players,
+                            max_players,
+                        )
+                        .map_err(|e| e.to_string())
+                    }
+                    Err(e) => Err(e.to_string()),
+                });
+                match heartbeat.await {
+                    Ok(Ok(())) => (),
+                    Ok(Err(e)) => error!("realm heartbeat failed: {e}"),
+                    Err(e) => error!("realm heartbeat task failed: {e}"),
+                }
+            }
+        });
+
+        // Let queued sessions in as slots free up and keep their clients informed of their position
+        let login_queue_world_context = world_context.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(
+                login_queue_world_context
+                    .config
+                    .get()
+                    .world
+                    .realm
+                    .queue_update_interval_secs,
+            ));
+            loop {
+                interval.tick().await;
+
+                login_queue_world_context.update_login_queue();
+            }
+        });
+
+        // Permanently delete the characters archived for longer than the configured age
+        let purge_world_context = world_context.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CHARACTER_PURGE_INTERVAL);
+            loop {
+                interval.tick().await;
+
+                let purge_after_days = purge_world_context
+                    .config
+                    .get()
+                    .world
+                    .character_deletion
+                    .purge_after_days;
+                if purge_after_days == 0 {
+                    continue;
+                }
+
+                let deleted_before = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+                    .saturating_sub(purge_after_days * 86400);
+                let mut characters_conn = purge_world_context.database.characters.get().unwrap();
+                let purged = CharacterRepository::purge_deleted_characters(
+                    &mut characters_conn,
+                    deleted_before,
+                );
+                if purged > 0 {
+                    info!("Purged {purged} character(s) deleted more than {purge_after_days} day(s) ago");
+                }
+            }
+        });
+
+        if config.world.metrics.enabled {
+            tokio::spawn(serve_metrics(world_context.clone()));
+        }
+
+        if config.world.admin_console.enabled {
+            tokio::spawn(serve_admin_console(world_context.clone()));
+        }
+
+        // Start the shutdown countdown on SIGTERM or Ctrl-C
+        let signal_world_context = world_context.clone();
+        tokio::spawn(async move {
+            wait_for_termination_signal().await;
+
+            info!("Termination signal received");
+            signal_world_context.shutdown.schedule(
+                ShutdownKind::Shutdown,
+                Duration::from_secs(
+                    signal_world_context
+                        .config
+                        .get()
+                        .world
+                        .shutdown
+                        .signal_delay_secs,
+                ),
+                &signal_world_context,
+            );
+        });
+
+        // Reload the config on SIGHUP
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            let reload_world_context = world_context.clone();
+            let mut sighup = signal(SignalKind::hangup()).expect("failed to listen for SIGHUP");
+            tokio::spawn(async move {
+                while sighup.recv().await.is_some() {
+                    info!("SIGHUP received, reloading the config");
+                    // Errors are logged, the current config stays in place
+                    let _ = reload_world_context.reload_config();
+                }
+            });
+        }
+
+        let accept_world_context = world_context.clone();
+        let accept_session_holder = session_holder.clone();
+        let accept_handle = tokio::spawn(async move {
             loop {
                 // The second item contains the IP and port of the new connection
                 let (socket, _) = listener.accept().await.unwrap();
 
-                let world_context = world_context.clone();
-                let session_holder = session_holder.clone();
+                let world_context = accept_world_context.clone();
+                let session_holder = accept_session_holder.clone();
 
                 // Spawn a new task for each inbound socket
                 tokio::spawn(async move {
-                    rustbolt_world::process(socket, world_context, session_holder)
-                        .await
-                        .expect("World socket error");
+                    match rustbolt_world::process(socket, world_context, session_holder).await {
+                        Ok(_) | Err(WorldSocketError::ClientVersionMismatch) => (),
+                        Err(e) => warn!("World socket closed: {:?}", e),
+                    }
                 });
             }
         });
 
-        let _ = tokio::join!(join_handle);
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        let shutdown_kind = loop {
+            interval.tick().await;
+
+            if let Some(kind) = world_context.shutdown.update(&world_context) {
+                break kind;
+            }
+        };
+
+        info!("Stopping the world server...");
+        accept_handle.abort();
+
+        // Save and log out every player
+        for session in session_holder.get_matching_sessions(|_| true) {
+            session.shutdown(
+                &mut world_context.database.characters.get().unwrap(),
+                world_context.clone(),
+            );
+            session.close_socket().await;
+        }
+
+        shutdown_kind
     });
+
+    // Stop the background tasks so that the heartbeat cannot bring the realm back online
+    network_runtime.shutdown_timeout(Duration::from_secs(1));
+    map_manager.stop_all_maps();
+
+    {
+        let auth_conn = db_pool_auth.get().unwrap();
+        RealmRepository::set_offline(&auth_conn, config.world.realm.id);
+    }
+
+    info!("World server stopped");
+
+    if shutdown_kind == ShutdownKind::Restart {
+        std::process::exit(RESTART_EXIT_CODE);
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_termination_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+    tokio::select! {
+        _ = sigterm.recv() => (),
+        _ = tokio::signal::ctrl_c() => (),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_termination_signal() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("failed to listen for Ctrl-C");
+}
+
+// Copies the live auth and characters databases to the replay directory unless it already has
+// its own, and refuses to use the live directory itself
+fn prepare_replay_databases(directory: &Path, live_directory: &Path) -> io::Result<()> {
+    fs::create_dir_all(directory)?;
+    if fs::canonicalize(directory)? == fs::canonicalize(live_directory)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the replay databases must not be the live ones",
+        ));
+    }
+
+    for database in ["auth.db", "characters.db"] {
+        let path = directory.join(database);
+        if !path.exists() {
+            fs::copy(live_directory.join(database), &path)?;
+            info!("Copied the live {database} to {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_speed(input: &str) -> Result<f32, String> {
+    match input.parse::<f32>() {
+        Ok(speed) if speed > 0.0 => Ok(speed),
+        _ => Err(format!("invalid speed: {input}")),
+    }
+}
+
+#[derive(Parser)]
+#[command(name = "rustbolt-world")]
+#[command(about = "Rustbolt world server", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Replay the client packets of a capture against the world instead of starting the server
+    Replay {
+        capture: PathBuf,
+        /// Directory of the auth and characters databases used by the replay, the live ones are
+        /// copied there if missing
+        #[arg(short, long, value_name = "DIRECTORY")]
+        databases: PathBuf,
+        /// Replay faster (e.g. 2) or slower (e.g. 0.5) than captured
+        #[arg(short, long, default_value = "1", value_parser = parse_speed)]
+        speed: f32,
+    },
 }
@@ -1,7 +1,15 @@
 use binrw::io::Cursor;
 use binrw::{binread, BinRead, BinReaderExt};
+use log::trace;
+use tokio::io::{AsyncRead, AsyncReadExt};
 use wow_srp::tbc_header::ClientHeader;
 
+use crate::{protocol::opcodes::Opcode, WorldSocketError};
+
+pub const CLIENT_HEADER_SIZE: usize = 6;
+// Legit client packets are far smaller, anything bigger is garbage
+pub const MAX_CLIENT_PAYLOAD_SIZE: usize = 10240;
+
 #[binread]
 #[derive(Debug, Clone)]
 pub struct ClientMessageHeader {
@@ -26,6 +34,57 @@ pub struct ClientMessage {
 }
 
 impl ClientMessage {
+    // The header is returned as raw bytes since it might need to be decrypted first
+    pub async fn read_raw_header<R: AsyncRead + Unpin>(
+        reader: &mut R,
+    ) -> Result<[u8; CLIENT_HEADER_SIZE], WorldSocketError> {
+        let mut header = [0_u8; CLIENT_HEADER_SIZE];
+        Self::read_exact(reader, &mut header).await?;
+
+        Ok(header)
+    }
+
+    pub async fn read_payload<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        header: ClientMessageHeader,
+    ) -> Result<ClientMessage, WorldSocketError> {
+        if Opcode::n(header.opcode).is_none() {
+            return Err(WorldSocketError::MalformedPacket(format!(
+                "unknown opcode {:#X}",
+                header.opcode
+            )));
+        }
+
+        let payload_size = (header.size as usize)
+            .checked_sub(4) // Client opcode is u32
+            .ok_or_else(|| {
+                WorldSocketError::MalformedPacket(format!("invalid size {}", header.size))
+            })?;
+        if payload_size > MAX_CLIENT_PAYLOAD_SIZE {
+            return Err(WorldSocketError::PacketTooLarge(payload_size));
+        }
+
+        let mut payload = vec![0_u8; payload_size];
+        Self::read_exact(reader, &mut payload).await?;
+
+        Ok(ClientMessage { header, payload })
+    }
+
+    // Waits until buf is full, however the bytes are split across TCP segments
+    async fn read_exact<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        buf: &mut [u8],
+    ) -> Result<(), WorldSocketError> {
+        match reader.read_exact(buf).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                trace!("Client disconnected");
+                Err(WorldSocketError::ClientDisconnected)
+            }
+            Err(e) => Err(WorldSocketError::SocketError(e)),
+        }
+    }
+
     pub fn read_as<T>(data: Vec<u8>) -> Result<T, binrw::Error>
     where
         T: for<'a> BinRead<Args<'a> = ()>,
@@ -1,19 +1,122 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::warn;
+
 use crate::protocol::client::ClientMessage;
 use crate::protocol::packets::*;
 use crate::protocol::server::ServerMessage;
+use crate::repositories::account_data::{
+    AccountDataOwner, AccountDataRecord, AccountDataRepository,
+};
 use crate::session::opcode_handler::{OpcodeHandler, PacketHandlerArgs};
+use crate::session::world_session::WorldSession;
+use crate::shared::constants::{AccountDataType, MAX_ACCOUNT_DATA_SIZE};
 
 impl OpcodeHandler {
     pub(crate) fn handle_cmsg_update_account_data(
-        PacketHandlerArgs { session, data, .. }: PacketHandlerArgs,
+        PacketHandlerArgs {
+            session,
+            world_context,
+            data,
+            ..
+        }: PacketHandlerArgs,
     ) {
         let cmsg_update_account_data: CmsgUpdateAccountData = ClientMessage::read_as(data).unwrap();
 
-        let packet = ServerMessage::new(SmsgUpdateAccountData {
-            account_data_id: cmsg_update_account_data.account_data_id,
-            data: 0,
+        let Some(data_type) = AccountDataType::n(cmsg_update_account_data.account_data_id) else {
+            warn!(
+                "account {} sent unknown account data type {}",
+                session.account_id, cmsg_update_account_data.account_data_id
+            );
+            return;
+        };
+        let Some(owner) = Self::account_data_owner(&session, data_type) else {
+            return;
+        };
+
+        let conn = world_context.database.characters.get().unwrap();
+        let decompressed_size = cmsg_update_account_data.decompressed_size;
+        if decompressed_size == 0 {
+            AccountDataRepository::delete(&conn, owner, data_type);
+            return;
+        }
+
+        // The data is stored compressed, make sure that it is valid before keeping it
+        let is_valid = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(
+            &cmsg_update_account_data.compressed_data,
+            MAX_ACCOUNT_DATA_SIZE,
+        )
+        .is_ok_and(|decompressed| decompressed.len() == decompressed_size as usize);
+        if !is_valid {
+            warn!(
+                "account {} sent invalid account data for type {:?}",
+                session.account_id, data_type
+            );
+            return;
+        }
+
+        let updated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+        AccountDataRepository::save(
+            &conn,
+            owner,
+            data_type,
+            &AccountDataRecord {
+                updated_at,
+                decompressed_size,
+                data: cmsg_update_account_data.compressed_data,
+            },
+        );
+    }
+
+    pub(crate) fn handle_cmsg_request_account_data(
+        PacketHandlerArgs {
+            session,
+            world_context,
+            data,
+            ..
+        }: PacketHandlerArgs,
+    ) {
+        let cmsg_request_account_data: CmsgRequestAccountData =
+            ClientMessage::read_as(data).unwrap();
+
+        let Some(data_type) = AccountDataType::n(cmsg_request_account_data.account_data_id) else {
+            return;
+        };
+
+        let conn = world_context.database.characters.get().unwrap();
+        let record = Self::account_data_owner(&session, data_type)
+            .and_then(|owner| AccountDataRepository::fetch(&conn, owner, data_type));
+
+        let packet = ServerMessage::new(match record {
+            Some(record) => SmsgUpdateAccountData {
+                account_data_id: data_type as u32,
+                decompressed_size: record.decompressed_size,
+                compressed_data: record.data,
+            },
+            None => SmsgUpdateAccountData {
+                account_data_id: data_type as u32,
+                decompressed_size: 0,
+                compressed_data: Vec::new(),
+            },
         });
 
         session.send(&packet).unwrap();
     }
+
+    // The per-character blocks need a character in the world
+    fn account_data_owner(
+        session: &WorldSession,
+        data_type: AccountDataType,
+    ) -> Option<AccountDataOwner> {
+        if data_type.is_per_character() {
+            session
+                .player_guid()
+                .map(|guid| AccountDataOwner::Character(guid.counter()))
+        } else {
+            Some(AccountDataOwner::Account(session.account_id))
+        }
+    }
 }
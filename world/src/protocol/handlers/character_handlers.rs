@@ -1,17 +1,24 @@
 use binrw::NullString;
-use shipyard::{Get, ViewMut};
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use shipyard::{Get, View, ViewMut};
 
 use crate::ecs::components::melee::Melee;
 use crate::ecs::components::unit::Unit;
 use crate::entities::object_guid::ObjectGuid;
 use crate::entities::player::player_data::ActionButton;
 use crate::entities::player::Player;
+use crate::entities::position::WorldPosition;
+use crate::game::world_context::WorldContext;
 use crate::protocol::client::ClientMessage;
 use crate::protocol::packets::*;
 use crate::protocol::server::ServerMessage;
+use crate::repositories::account_data::{AccountDataOwner, AccountDataRepository};
 use crate::repositories::character::CharacterRepository;
+use crate::repositories::realm::RealmRepository;
 use crate::session::opcode_handler::{OpcodeHandler, PacketHandlerArgs};
 use crate::session::world_session::{WSRunnableArgs, WorldSessionState};
+use crate::shared::constants::AtLoginFlag;
 use crate::shared::response_codes::ResponseCodes;
 
 impl OpcodeHandler {
@@ -26,20 +33,24 @@ impl OpcodeHandler {
         let cmsg_char_create: CmsgCharCreate = ClientMessage::read_as(data).unwrap();
         let mut conn = world_context.database.characters.get().unwrap();
 
-        let name_available =
-            CharacterRepository::is_name_available(&conn, cmsg_char_create.name.to_string());
-        let result = if name_available {
-            match Player::create_in_db(
+        let name = cmsg_char_create.name.to_string();
+        let result = match Self::check_new_character_name(&world_context, &conn, &name) {
+            Ok(name) => match Player::create_in_db(
                 &mut conn,
-                &cmsg_char_create,
+                &CmsgCharCreate {
+                    name: NullString::from(name),
+                    ..cmsg_char_create
+                },
                 session.account_id,
                 world_context.clone(),
             ) {
-                Ok(_) => ResponseCodes::CharCreateSuccess,
+                Ok(_) => {
+                    Self::refresh_realm_character_count(&world_context, session.account_id);
+                    ResponseCodes::CharCreateSuccess
+                }
                 Err(_) => ResponseCodes::CharCreateFailed,
-            }
-        } else {
-            ResponseCodes::CharCreateNameInUse
+            },
+            Err(code) => code,
         };
 
         let packet = ServerMessage::new(SmsgCharCreate {
@@ -87,6 +98,7 @@ impl OpcodeHandler {
         let cmsg_char_delete: CmsgCharDelete = ClientMessage::read_as(data).unwrap();
         let conn = world_context.database.characters.get().unwrap();
         CharacterRepository::delete_character(&conn, cmsg_char_delete, session.account_id);
+        Self::refresh_realm_character_count(&world_context, session.account_id);
 
         let packet = ServerMessage::new(SmsgCharDelete {
             result: ResponseCodes::CharDeleteSuccess as u8,
@@ -95,6 +107,49 @@ impl OpcodeHandler {
         session.send(&packet).unwrap();
     }
 
+    pub(crate) fn handle_cmsg_char_rename(
+        PacketHandlerArgs {
+            session,
+            world_context,
+            data,
+            ..
+        }: PacketHandlerArgs,
+    ) {
+        let cmsg_char_rename: CmsgCharRename = ClientMessage::read_as(data).unwrap();
+        let name = cmsg_char_rename.name.to_string();
+        let conn = world_context.database.characters.get().unwrap();
+
+        let result =
+            Self::check_new_character_name(&world_context, &conn, &name).and_then(|name| {
+                if CharacterRepository::rename_character(
+                    &conn,
+                    cmsg_char_rename.guid,
+                    session.account_id,
+                    &name,
+                ) {
+                    Ok(name)
+                } else {
+                    // Not a character of this account, or not flagged for renaming
+                    Err(ResponseCodes::CharNameFailure)
+                }
+            });
+
+        let packet = ServerMessage::new(match result {
+            Ok(name) => SmsgCharRename {
+                result: ResponseCodes::CharNameSuccess as u8,
+                guid: Some(cmsg_char_rename.guid),
+                name: Some(NullString::from(name)),
+            },
+            Err(code) => SmsgCharRename {
+                result: code as u8,
+                guid: None,
+                name: None,
+            },
+        });
+
+        session.send(&packet).unwrap();
+    }
+
     pub(crate) fn handle_cmsg_player_login(
         PacketHandlerArgs {
             session,
@@ -105,6 +160,16 @@ impl OpcodeHandler {
     ) {
         let cmsg_player_login: CmsgPlayerLogin = ClientMessage::read_as(data).unwrap();
 
+        // Kept until the character is in the world so that it can't be edited offline meanwhile
+        let Some(_claim) = world_context.character_claims.claim(cmsg_player_login.guid) else {
+            let packet = ServerMessage::new(SmsgCharacterLoginFailed {
+                result: ResponseCodes::CharLoginFailed as u8,
+            });
+
+            session.send(&packet).unwrap();
+            return;
+        };
+
         let account_id = session.account_id;
         let conn = world_context.database.characters.get().unwrap();
 
@@ -117,6 +182,53 @@ impl OpcodeHandler {
             "Attempt to load a character belonging to another account"
         );
 
+        // The client asks for a new name first, don't trust it to do so
+        if character_data.at_login.contains(AtLoginFlag::Rename) {
+            let packet = ServerMessage::new(SmsgCharacterLoginFailed {
+                result: ResponseCodes::CharLoginFailed as u8,
+            });
+
+            session.send(&packet).unwrap();
+            return;
+        }
+
+        // The character may still be in the world if the client reconnected during its grace period
+        let reattach = match session.player_guid() {
+            Some(player_guid) if player_guid.raw() == cmsg_player_login.guid => true,
+            Some(_) => {
+                // Another character was picked, the one left in the world logs out
+                session.shutdown(
+                    &mut world_context.database.characters.get().unwrap(),
+                    world_context.clone(),
+                );
+                // The session was unregistered along with its character but is still connected
+                world_context
+                    .session_holder
+                    .insert_session(account_id, session.clone());
+                false
+            }
+            None => false,
+        };
+
+        let (position, bindpoint) = if reattach {
+            session
+                .run(&|WSRunnableArgs {
+                           map,
+                           player_entity_id,
+                       }| {
+                    map.world()
+                        .run(|v_wpos: View<WorldPosition>, v_player: View<Player>| {
+                            (
+                                v_wpos[player_entity_id],
+                                v_player[player_entity_id].bindpoint(),
+                            )
+                        })
+                })
+                .expect("reattached session has no character in the world")
+        } else {
+            (character_data.position, character_data.bindpoint())
+        };
+
         let msg_set_dungeon_difficulty = ServerMessage::new(MsgSetDungeonDifficulty {
             difficulty: 0, // FIXME
             unk: 1,
@@ -126,17 +238,30 @@ impl OpcodeHandler {
         session.send(&msg_set_dungeon_difficulty).unwrap();
 
         let smsg_login_verify_world = ServerMessage::new(SmsgLoginVerifyWorld {
-            map: character_data.position.map_key.map_id,
-            position_x: character_data.position.x,
-            position_y: character_data.position.y,
-            position_z: character_data.position.z,
-            orientation: character_data.position.o,
+            map: position.map_key.map_id,
+            position_x: position.x,
+            position_y: position.y,
+            position_z: position.z,
+            orientation: position.o,
         });
 
         session.send(&smsg_login_verify_world).unwrap();
 
-        let smsg_account_data_times =
-            ServerMessage::new(SmsgAccountDataTimes { data: [0_u32; 32] });
+        let mut account_data_times = [0_u32; 32];
+        let character_guid = ObjectGuid::from_raw(character_data.guid).unwrap();
+        for owner in [
+            AccountDataOwner::Account(account_id),
+            AccountDataOwner::Character(character_guid.counter()),
+        ] {
+            for (data_type, updated_at) in AccountDataRepository::fetch_times(&conn, owner) {
+                if let Some(time) = account_data_times.get_mut(data_type as usize) {
+                    *time = updated_at;
+                }
+            }
+        }
+        let smsg_account_data_times = ServerMessage::new(SmsgAccountDataTimes {
+            data: account_data_times,
+        });
 
         session.send(&smsg_account_data_times).unwrap();
 
@@ -154,7 +279,16 @@ impl OpcodeHandler {
 
         session.send(&smsg_motd).unwrap();
 
-        session.send_initial_packets_before_add_to_map(&character_data.bindpoint());
+        session.send_initial_packets_before_add_to_map(&bindpoint);
+
+        if reattach {
+            if let Some(map) = session.current_map() {
+                map.reattach_player(session.clone());
+
+                session.send_initial_packets_after_add_to_map(world_context.clone());
+            }
+            return;
+        }
 
         if let Some(map) = world_context
             .map_manager
@@ -295,4 +429,37 @@ impl OpcodeHandler {
             });
         });
     }
+
+    // Validates a name picked for a new character or a rename, and makes sure that it is free
+    fn check_new_character_name(
+        world_context: &WorldContext,
+        conn: &PooledConnection<SqliteConnectionManager>,
+        name: &str,
+    ) -> Result<String, ResponseCodes> {
+        let name = world_context
+            .character_names
+            .validate(name, &world_context.config.get().world.character_names)?;
+
+        if CharacterRepository::is_name_reserved(conn, &name) {
+            Err(ResponseCodes::CharNameReserved)
+        } else if !CharacterRepository::is_name_available(conn, name.clone()) {
+            Err(ResponseCodes::CharCreateNameInUse)
+        } else {
+            Ok(name)
+        }
+    }
+
+    // Keep the character count displayed in the realm list up to date
+    pub(crate) fn refresh_realm_character_count(world_context: &WorldContext, account_id: u32) {
+        let conn = world_context.database.characters.get().unwrap();
+        let num_chars = CharacterRepository::count_characters(&conn, account_id);
+
+        let auth_conn = world_context.database.auth.get().unwrap();
+        RealmRepository::update_character_count(
+            &auth_conn,
+            world_context.config.get().world.realm.id,
+            account_id,
+            num_chars,
+        );
+    }
 }
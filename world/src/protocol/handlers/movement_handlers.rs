@@ -4,6 +4,7 @@ use log::{error, warn};
 use shipyard::{Get, UniqueView, View, ViewMut};
 
 use crate::{
+    config::MovementViolationAction,
     ecs::components::{
         behavior::Behavior, guid::Guid, movement::Movement, nearby_players::NearbyPlayers,
         unwind::Unwind,
@@ -11,7 +12,10 @@ use crate::{
     entities::{
         creature::Creature, game_object::GameObject, player::Player, position::WorldPosition,
     },
-    game::spatial_grid::WrappedSpatialGrid,
+    game::{
+        spatial_grid::WrappedSpatialGrid, terrain_manager::WrappedTerrainManager,
+        world_context::WorldContext,
+    },
     protocol::{
         client::ClientMessage,
         opcodes::Opcode,
@@ -25,15 +29,86 @@ use crate::{
 
 impl OpcodeHandler {
     pub fn handle_movement_packet(opcode: Opcode) -> PacketHandler {
-        fn handle_movement(opcode: Opcode, session: Arc<WorldSession>, data: Vec<u8>) {
+        fn handle_movement(
+            opcode: Opcode,
+            session: Arc<WorldSession>,
+            world_context: Arc<WorldContext>,
+            data: Vec<u8>,
+        ) {
             let movement_info: MovementInfo = ClientMessage::read_as(data).unwrap();
             let player_guid = session.player_guid().unwrap();
+            let player_entity_id = session.player_entity_id().unwrap();
 
             // TODO: Validate movement (unitBeingMoved guid)
-            // TODO: Validate position
             // TODO: Handle fall if Opcode == MsgMoveFallLand
 
             let map = session.current_map().unwrap();
+            let config = world_context.config.get();
+            let config = &config.world.movement_validation;
+            let Some(validation) = map.world().run(
+                |terrain_manager: UniqueView<WrappedTerrainManager>,
+                 v_movement: View<Movement>,
+                 v_wpos: View<WorldPosition>,
+                 mut vm_player: ViewMut<Player>| {
+                    let player = &mut vm_player[player_entity_id];
+
+                    // Movements sent before the client acknowledged a teleport start from the
+                    // previous position
+                    if player.teleport_destination.is_some() {
+                        return None;
+                    }
+
+                    if !config.enabled {
+                        return Some(Ok(()));
+                    }
+
+                    let validation = player.movement_validator.validate(
+                        &movement_info,
+                        &v_wpos[player_entity_id],
+                        &v_movement[player_entity_id],
+                        &terrain_manager,
+                        config,
+                    );
+                    if let Err(violation) = &validation {
+                        warn!(
+                            "movement violation from player {} ({opcode:?}): {violation}",
+                            player.name
+                        );
+                    }
+
+                    Some(validation)
+                },
+            ) else {
+                return;
+            };
+
+            if validation.is_err() {
+                match config.violation_action {
+                    MovementViolationAction::Log => (),
+                    MovementViolationAction::Teleport => {
+                        // Send the player back to the last valid position
+                        map.world().run(
+                            |v_wpos: View<WorldPosition>,
+                             v_movement: View<Movement>,
+                             mut vm_player: ViewMut<Player>| {
+                                let current_position = v_wpos[player_entity_id];
+                                vm_player[player_entity_id].teleport_to(
+                                    &current_position,
+                                    false,
+                                    v_wpos,
+                                    v_movement,
+                                );
+                            },
+                        );
+                        return;
+                    }
+                    MovementViolationAction::Disconnect => {
+                        session.kick();
+                        return;
+                    }
+                }
+            }
+
             // Register new position
             map.world().run(
                 |spatial_grid: UniqueView<WrappedSpatialGrid>,
@@ -48,7 +123,7 @@ impl OpcodeHandler {
                  mut vm_unwind: ViewMut<Unwind>| {
                     spatial_grid.update_entity_position(
                         &player_guid,
-                        session.player_entity_id().unwrap(),
+                        player_entity_id,
                         Some(session.clone()),
                         &movement_info.position,
                         &v_movement,
@@ -69,8 +144,13 @@ impl OpcodeHandler {
         }
 
         Box::new(
-            move |PacketHandlerArgs { session, data, .. }: PacketHandlerArgs| {
-                handle_movement(opcode, session, data)
+            move |PacketHandlerArgs {
+                      session,
+                      world_context,
+                      data,
+                      ..
+                  }: PacketHandlerArgs| {
+                handle_movement(opcode, session, world_context, data)
             },
         ) as PacketHandler
     }
@@ -1064,3 +1064,18 @@ pub enum Opcode {
     SmsgSplineMoveUnsetFlying = 0x422,
     SmsgSummonCancel = 0x423,
 }
+
+impl Opcode {
+    // Opcode with the highest value, to update when opcodes are added at the end of the enum
+    pub const MAX: Opcode = Opcode::SmsgSummonCancel;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Opcode;
+
+    #[test]
+    fn max_is_the_highest_opcode() {
+        assert!((Opcode::MAX as u32 + 1..=u16::MAX as u32).all(|value| Opcode::n(value).is_none()));
+    }
+}
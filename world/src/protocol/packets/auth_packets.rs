@@ -1,15 +1,15 @@
 use crate::protocol::opcodes::Opcode;
 use crate::protocol::server::ServerMessagePayload;
-use binrw::{binread, binwrite, NullString};
+use binrw::{binread, binrw, binwrite, NullString};
 use opcode_derive::server_opcode;
 
-#[binwrite]
+#[binrw]
 #[server_opcode]
 pub struct SmsgAuthChallenge {
     pub server_seed: u32,
 }
 
-#[binread]
+#[binrw]
 #[derive(Debug)]
 pub struct CmsgAuthSession {
     pub _build: u32,
@@ -25,7 +25,7 @@ impl CmsgAuthSession {
     }
 }
 
-#[binwrite]
+#[binrw]
 #[server_opcode]
 pub struct SmsgAuthResponse {
     pub result: u8,
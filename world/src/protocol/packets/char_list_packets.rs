@@ -1,10 +1,10 @@
-use binrw::{binread, binwrite, NullString};
+use binrw::{binread, binrw, binwrite, NullString};
 use opcode_derive::server_opcode;
 
 use crate::protocol::server::ServerMessagePayload;
 use crate::{protocol::opcodes::Opcode, shared::constants::InventoryType};
 
-#[binwrite]
+#[binrw]
 pub struct CharEnumData {
     pub guid: u64,
     pub name: NullString,
@@ -24,15 +24,17 @@ pub struct CharEnumData {
     pub position_z: f32,
     pub guild_id: u32,
     pub flags: u32,
+    #[br(map = |b: u8| b != 0)]
     #[bw(map = |b: &bool| if *b { 1_u8 } else { 0_u8 })]
     pub first_login: bool,
     pub pet_display_id: u32,
     pub pet_level: u32,
     pub pet_family: u32,
+    #[br(count = 20)] // Always sent for all the equipment slots, empty or not
     pub equipment: Vec<CharEnumEquip>,
 }
 
-#[binwrite]
+#[binrw]
 pub struct CharEnumEquip {
     pub display_id: u32,
     pub slot: u8,
@@ -50,13 +52,34 @@ impl CharEnumEquip {
     }
 }
 
-#[binwrite]
+#[binrw]
 #[server_opcode]
 pub struct SmsgCharEnum {
     pub number_of_characters: u8,
+    #[br(count = number_of_characters)]
     pub character_data: Vec<CharEnumData>,
 }
 
+#[binread]
+pub struct CmsgCharRename {
+    pub guid: u64,
+    pub name: NullString,
+}
+
+#[binwrite]
+#[server_opcode]
+pub struct SmsgCharRename {
+    pub result: u8,
+    pub guid: Option<u64>,        // Only on success
+    pub name: Option<NullString>, // Only on success
+}
+
+#[binwrite]
+#[server_opcode]
+pub struct SmsgCharacterLoginFailed {
+    pub result: u8,
+}
+
 #[binread]
 pub struct CmsgRealmSplit {
     pub client_state: u32,
@@ -70,7 +93,7 @@ pub struct SmsgRealmSplit {
     pub split_date: NullString, // "01/01/01"
 }
 
-#[binread]
+#[binrw]
 #[derive(Debug)]
 pub struct CmsgCharCreate {
     pub name: NullString,
@@ -84,7 +107,7 @@ pub struct CmsgCharCreate {
     pub facialstyle: u8,
 }
 
-#[binwrite]
+#[binrw]
 #[server_opcode]
 pub struct SmsgCharCreate {
     pub result: u8, // https://github.com/mangosone/server/blob/d62fdfe93b96bef5daa36433116d2f0eeb9fc3d0/src/game/Server/SharedDefines.h#L250
@@ -101,7 +124,7 @@ pub struct SmsgCharDelete {
     pub result: u8, // Enum see SmsgCharCreate
 }
 
-#[binread]
+#[binrw]
 pub struct CmsgPlayerLogin {
     pub guid: u64,
 }
@@ -1,4 +1,4 @@
-use binrw::{binread, binwrite, NullString};
+use binrw::{binread, binrw, binwrite, NullString};
 use opcode_derive::server_opcode;
 
 use crate::entities::object_guid::ObjectGuid;
@@ -6,16 +6,18 @@ use crate::protocol::opcodes::Opcode;
 use crate::protocol::server::ServerMessagePayload;
 use crate::shared::constants::{ChatMessageType, Language};
 
-#[binread]
+#[binrw]
 pub struct CmsgMessageChat {
     #[br(map = |ct: u32| ChatMessageType::n(ct).expect("non-existing ChatMessageType"))]
+    #[bw(map = |ct: &ChatMessageType| *ct as u32)]
     pub chat_type: ChatMessageType,
-    #[br(map = |ct: u32| Language::n(ct).expect("non-existing Language"))]
+    #[br(map = |l: u32| Language::n(l).expect("non-existing Language"))]
+    #[bw(map = |l: &Language| *l as u32)]
     pub language: Language,
     #[br(if(chat_type == ChatMessageType::Whisper))]
-    _recipient: Option<NullString>,
+    pub recipient: Option<NullString>,
     #[br(if(chat_type == ChatMessageType::Channel))]
-    _channel: Option<NullString>,
+    pub channel: Option<NullString>,
     pub msg: NullString,
 }
 
@@ -1,16 +1,16 @@
-use binrw::{binread, binwrite};
+use binrw::{binrw, binwrite};
 use opcode_derive::server_opcode;
 
 use crate::entities::object_guid::{ObjectGuid, PackedObjectGuid};
 use crate::protocol::opcodes::Opcode;
 use crate::protocol::server::ServerMessagePayload;
 
-#[binread]
+#[binrw]
 pub struct CmsgAttackSwing {
     pub guid: ObjectGuid,
 }
 
-#[binwrite]
+#[binrw]
 #[server_opcode]
 pub struct SmsgAttackStop {
     pub attacker_guid: PackedObjectGuid,
@@ -1,4 +1,4 @@
-use binrw::{binread, binwrite};
+use binrw::{binread, binrw, binwrite};
 use opcode_derive::server_opcode;
 
 use crate::entities::object_guid::ObjectGuid;
@@ -6,19 +6,21 @@ use crate::protocol::opcodes::Opcode;
 use crate::protocol::server::ServerMessagePayload;
 use crate::shared::constants::{LootSlotType, LootType};
 
-#[binread]
+#[binrw]
 pub struct CmsgLoot {
     pub target_guid: u64,
 }
 
-#[binwrite]
+#[binrw]
 #[server_opcode]
 pub struct SmsgLootResponse {
     pub target_guid: u64,
+    #[br(map = |lt: u8| LootType::n(lt).expect("non-existing LootType"))]
     #[bw(map = |lt: &LootType| *lt as u8)]
     pub loot_type: LootType,
     pub money: u32,
     pub item_count: u8,
+    #[br(count = item_count)]
     pub items: Vec<LootResponseItem>,
 }
 
@@ -39,7 +41,7 @@ impl SmsgLootResponse {
     }
 }
 
-#[binwrite]
+#[binrw]
 pub struct LootResponseItem {
     pub index: u8, // Index in the loot window
     pub id: u32,
@@ -47,6 +49,7 @@ pub struct LootResponseItem {
     pub display_info_id: u32, // From ItemDisplayInfo.dbc
     pub random_suffix: u32,
     pub random_property_id: u32,
+    #[br(map = |lst: u8| LootSlotType::n(lst).expect("non-existing LootSlotType"))]
     #[bw(map = |lst: &LootSlotType| *lst as u8)]
     pub slot_type: LootSlotType,
 }
@@ -82,7 +85,7 @@ impl SmsgLootReleaseResponse {
     }
 }
 
-#[binread]
+#[binrw]
 pub struct CmsgAutostoreLootItem {
     pub loot_index: u8,
 }
@@ -1,4 +1,4 @@
-use binrw::{binread, binwrite, NullString};
+use binrw::{binread, binrw, binwrite, NullString};
 use opcode_derive::server_opcode;
 
 use crate::protocol::opcodes::Opcode;
@@ -16,7 +16,7 @@ pub struct SmsgPong {
     pub ping: u32,
 }
 
-#[binwrite]
+#[binrw]
 #[server_opcode]
 pub struct SmsgLoginVerifyWorld {
     pub map: u32,
@@ -42,7 +42,7 @@ pub struct SmsgTutorialFlags {
 #[binwrite]
 #[server_opcode]
 pub struct SmsgAccountDataTimes {
-    pub data: [u32; 32], // All 0
+    pub data: [u32; 32], // Last update of each AccountDataType, 0 if not stored
 }
 
 #[binwrite]
@@ -59,6 +59,13 @@ pub struct SmsgMotd {
     pub message: NullString,
 }
 
+#[binwrite]
+#[server_opcode]
+pub struct SmsgServerMessage {
+    pub message_type: u32, // ServerMessageType
+    pub text: NullString,
+}
+
 #[binwrite]
 #[server_opcode]
 pub struct SmsgTimeSyncReq {
@@ -68,13 +75,22 @@ pub struct SmsgTimeSyncReq {
 #[binread]
 pub struct CmsgUpdateAccountData {
     pub account_data_id: u32,
+    pub decompressed_size: u32, // 0 to clear the block
+    #[br(parse_with = binrw::helpers::until_eof)]
+    pub compressed_data: Vec<u8>,
+}
+
+#[binread]
+pub struct CmsgRequestAccountData {
+    pub account_data_id: u32,
 }
 
 #[binwrite]
 #[server_opcode]
 pub struct SmsgUpdateAccountData {
     pub account_data_id: u32,
-    pub data: u32,
+    pub decompressed_size: u32,
+    pub compressed_data: Vec<u8>,
 }
 
 #[binwrite]
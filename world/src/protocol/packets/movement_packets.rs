@@ -1,4 +1,4 @@
-use binrw::{binread, binwrite};
+use binrw::{binrw, binwrite};
 use enumflags2::BitFlags;
 use opcode_derive::server_opcode;
 use shared::models::terrain_info::Vector3;
@@ -132,7 +132,7 @@ impl SmsgMonsterMove {
     }
 }
 
-#[binwrite]
+#[binrw]
 #[server_opcode]
 pub struct MsgMoveTeleportAck {
     pub packed_guid: PackedObjectGuid,
@@ -140,7 +140,7 @@ pub struct MsgMoveTeleportAck {
     pub movement_info: MovementInfo,
 }
 
-#[binread]
+#[binrw]
 pub struct MsgMoveTeleportAckFromClient {
     pub _guid: ObjectGuid,
     pub _counter: u32,
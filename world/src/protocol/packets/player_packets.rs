@@ -1,4 +1,4 @@
-use binrw::{binread, binwrite};
+use binrw::{binread, binrw, binwrite};
 use opcode_derive::server_opcode;
 
 use crate::entities::object_guid::ObjectGuid;
@@ -79,7 +79,7 @@ pub struct SmsgInitializeFactions {
     pub factions: Vec<FactionInit>,
 }
 
-#[binread]
+#[binrw]
 pub struct CmsgSetSelection {
     pub guid: u64,
 }
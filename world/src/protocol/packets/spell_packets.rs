@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use binrw::{binread, binwrite, BinRead, BinWrite, NullString};
+use binrw::{binread, binrw, binwrite, BinRead, BinWrite, NullString};
 use enumflags2::{make_bitflags, BitFlags};
 use opcode_derive::server_opcode;
 
@@ -86,7 +86,60 @@ impl BinRead for SpellCastTargets {
     }
 }
 
-#[binread]
+// Used by clients that build CMSG_CAST_SPELL, mirrors the BinRead implementation above
+impl BinWrite for SpellCastTargets {
+    type Args<'a> = ();
+
+    fn write_options<W: std::io::prelude::Write + std::io::prelude::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::prelude::BinResult<()> {
+        let mut target_mask: BitFlags<SpellCastTargetFlags> = BitFlags::empty();
+        if self.unit_guid().is_some() {
+            target_mask |= SpellCastTargetFlags::Unit;
+        }
+        if self.game_object_guid().is_some() {
+            target_mask |= SpellCastTargetFlags::Object;
+        }
+        if self.item_guid().is_some() {
+            target_mask |= SpellCastTargetFlags::Item;
+        }
+        if self.source_position().is_some() {
+            target_mask |= SpellCastTargetFlags::SourceLocation;
+        }
+        if self.destination_position().is_some() {
+            target_mask |= SpellCastTargetFlags::DestLocation;
+        }
+        if self.string_target().is_some() {
+            target_mask |= SpellCastTargetFlags::String;
+        }
+        target_mask.bits().write_options(writer, endian, args)?;
+
+        for guid in [self.unit_guid(), self.game_object_guid(), self.item_guid()]
+            .into_iter()
+            .flatten()
+        {
+            guid.as_packed().write_options(writer, endian, args)?;
+        }
+
+        for position in [self.source_position(), self.destination_position()]
+            .into_iter()
+            .flatten()
+        {
+            [position.x, position.y, position.z].write_options(writer, endian, args)?;
+        }
+
+        if let Some(string) = self.string_target() {
+            NullString::from(string).write_options(writer, endian, args)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[binrw]
 pub struct CmsgCastSpell {
     pub spell_id: u32,
     pub cast_count: u8,
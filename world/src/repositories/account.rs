@@ -1,5 +1,8 @@
+use log::warn;
 use rusqlite::Connection;
 
+use crate::shared::constants::SecurityLevel;
+
 pub struct AccountRepository;
 
 impl AccountRepository {
@@ -14,4 +17,54 @@ impl AccountRepository {
 
         rows.next().unwrap().map(|row| (row.get("id").unwrap(), row.get("session_key").unwrap()))
     }
+
+    pub fn fetch_security_level(conn: &mut Connection, account_id: u32) -> SecurityLevel {
+        let mut stmt = conn
+            .prepare("SELECT security_level FROM accounts WHERE id = :account_id")
+            .unwrap();
+        let mut rows = stmt.query(&[(":account_id", &account_id)]).unwrap();
+
+        let level: u8 = rows
+            .next()
+            .unwrap()
+            .map(|row| row.get("security_level").unwrap())
+            .unwrap_or(0);
+
+        SecurityLevel::n(level).unwrap_or_else(|| {
+            warn!("invalid security level {level} for account {account_id}, using Player");
+            SecurityLevel::Player
+        })
+    }
+
+    // Client build recorded by the auth server during the last logon
+    pub fn fetch_last_build(conn: &mut Connection, account_id: u32) -> Option<u16> {
+        let mut stmt = conn
+            .prepare("SELECT last_build FROM accounts WHERE id = :account_id")
+            .unwrap();
+        let mut rows = stmt.query(&[(":account_id", &account_id)]).unwrap();
+
+        rows.next()
+            .unwrap()
+            .and_then(|row| row.get("last_build").unwrap())
+    }
+
+    // SRP verifier and salt (hex-encoded) written by the auth server, used to check passwords
+    // outside of the client logon
+    pub fn fetch_id_and_verifier(
+        conn: &mut Connection,
+        username: &str,
+    ) -> Option<(u32, String, String)> {
+        let mut stmt = conn
+            .prepare("SELECT id, verifier, salt FROM accounts WHERE UPPER(username) = UPPER(:username)")
+            .unwrap();
+        let mut rows = stmt.query(&[(":username", &username)]).unwrap();
+
+        rows.next().unwrap().map(|row| {
+            (
+                row.get("id").unwrap(),
+                row.get("verifier").unwrap(),
+                row.get("salt").unwrap(),
+            )
+        })
+    }
 }
@@ -0,0 +1,106 @@
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::named_params;
+
+use crate::shared::constants::AccountDataType;
+
+pub struct AccountDataRepository;
+
+// The global blocks belong to the account, the others to the character in the world
+#[derive(Clone, Copy)]
+pub enum AccountDataOwner {
+    Account(u32),
+    Character(u32),
+}
+
+impl AccountDataOwner {
+    fn table_and_column(&self) -> (&'static str, &'static str, u32) {
+        match *self {
+            AccountDataOwner::Account(account_id) => ("account_data", "account_id", account_id),
+            AccountDataOwner::Character(guid) => ("character_account_data", "character_guid", guid),
+        }
+    }
+}
+
+pub struct AccountDataRecord {
+    pub updated_at: u32,
+    pub decompressed_size: u32,
+    pub data: Vec<u8>, // zlib compressed, as sent by the client
+}
+
+impl AccountDataRepository {
+    pub fn fetch(
+        conn: &PooledConnection<SqliteConnectionManager>,
+        owner: AccountDataOwner,
+        data_type: AccountDataType,
+    ) -> Option<AccountDataRecord> {
+        let (table, column, id) = owner.table_and_column();
+        let mut stmt = conn
+            .prepare_cached(&format!("SELECT updated_at, decompressed_size, data FROM {table} WHERE {column} = :id AND type = :type"))
+            .unwrap();
+        let mut rows = stmt
+            .query(named_params! { ":id": id, ":type": data_type as u32 })
+            .unwrap();
+
+        rows.next().unwrap().map(|row| AccountDataRecord {
+            updated_at: row.get("updated_at").unwrap(),
+            decompressed_size: row.get("decompressed_size").unwrap(),
+            data: row.get("data").unwrap(),
+        })
+    }
+
+    // Returns (type, updated_at) for every block stored for the owner
+    pub fn fetch_times(
+        conn: &PooledConnection<SqliteConnectionManager>,
+        owner: AccountDataOwner,
+    ) -> Vec<(u32, u32)> {
+        let (table, column, id) = owner.table_and_column();
+        let mut stmt = conn
+            .prepare_cached(&format!(
+                "SELECT type, updated_at FROM {table} WHERE {column} = :id"
+            ))
+            .unwrap();
+        let rows = stmt
+            .query_map(named_params! { ":id": id }, |row| {
+                Ok((row.get("type")?, row.get("updated_at")?))
+            })
+            .unwrap();
+
+        rows.filter_map(|r| r.ok()).collect()
+    }
+
+    pub fn save(
+        conn: &PooledConnection<SqliteConnectionManager>,
+        owner: AccountDataOwner,
+        data_type: AccountDataType,
+        record: &AccountDataRecord,
+    ) {
+        let (table, column, id) = owner.table_and_column();
+        let mut stmt = conn
+            .prepare_cached(&format!("INSERT INTO {table}({column}, type, updated_at, decompressed_size, data) VALUES (:id, :type, :updated_at, :decompressed_size, :data) ON CONFLICT({column}, type) DO UPDATE SET updated_at = excluded.updated_at, decompressed_size = excluded.decompressed_size, data = excluded.data"))
+            .unwrap();
+        stmt.execute(named_params! {
+            ":id": id,
+            ":type": data_type as u32,
+            ":updated_at": record.updated_at,
+            ":decompressed_size": record.decompressed_size,
+            ":data": record.data,
+        })
+        .unwrap();
+    }
+
+    pub fn delete(
+        conn: &PooledConnection<SqliteConnectionManager>,
+        owner: AccountDataOwner,
+        data_type: AccountDataType,
+    ) {
+        let (table, column, id) = owner.table_and_column();
+        let mut stmt = conn
+            .prepare_cached(&format!(
+                "DELETE FROM {table} WHERE {column} = :id AND type = :type"
+            ))
+            .unwrap();
+        stmt.execute(named_params! { ":id": id, ":type": data_type as u32 })
+            .unwrap();
+    }
+}
@@ -1,5 +1,6 @@
 use std::{collections::HashMap, sync::Arc, time::SystemTime};
 
+use enumflags2::BitFlags;
 use r2d2::PooledConnection;
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{named_params, Error, Transaction};
@@ -21,8 +22,8 @@ use crate::{
     game::map_manager::MapKey,
     protocol::packets::{CharEnumData, CharEnumEquip, CmsgCharCreate, CmsgCharDelete},
     shared::constants::{
-        ActionButtonType, CharacterClass, CharacterRace, InventorySlot, InventoryType,
-        PlayerQuestStatus, PowerType, MAX_QUEST_OBJECTIVES_COUNT,
+        ActionButtonType, AtLoginFlag, CharacterClass, CharacterRace, InventorySlot, InventoryType,
+        PlayerQuestStatus, PowerType, CHARACTER_FLAG_RENAME, MAX_QUEST_OBJECTIVES_COUNT,
     },
 };
 
@@ -36,7 +37,7 @@ impl CharacterRepository {
         name: String,
     ) -> bool {
         let mut stmt = conn
-            .prepare_cached("SELECT COUNT(guid) FROM characters WHERE name = :name")
+            .prepare_cached("SELECT COUNT(guid) FROM characters WHERE name = :name COLLATE NOCASE")
             .unwrap();
         let mut count = stmt
             .query_map(named_params! { ":name": name }, |row| {
@@ -47,6 +48,17 @@ impl CharacterRepository {
         count.next().unwrap().map(|c| c == 0).unwrap_or(true)
     }
 
+    pub fn is_name_reserved(conn: &PooledConnection<SqliteConnectionManager>, name: &str) -> bool {
+        let mut stmt = conn
+            .prepare_cached("SELECT COUNT(name) FROM reserved_names WHERE name = :name")
+            .unwrap();
+        let count: u32 = stmt
+            .query_row(named_params! { ":name": name }, |row| row.get(0))
+            .unwrap();
+
+        count > 0
+    }
+
     pub fn create_character(
         transaction: &Transaction,
         source: &CmsgCharCreate,
@@ -143,12 +155,39 @@ impl CharacterRepository {
         rows.filter_map(|r| r.ok()).collect()
     }
 
+    pub fn count_characters(
+        conn: &PooledConnection<SqliteConnectionManager>,
+        account_id: u32,
+    ) -> u32 {
+        let mut stmt = conn
+            .prepare_cached("SELECT COUNT(guid) FROM characters WHERE account_id = :account_id")
+            .unwrap();
+        stmt.query_row(named_params! { ":account_id": account_id }, |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    // Returns (account_id, number of characters) for every account having characters
+    pub fn count_characters_per_account(
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> Vec<(u32, u32)> {
+        let mut stmt = conn
+            .prepare_cached("SELECT account_id, COUNT(guid) FROM characters WHERE deleted_at IS NULL GROUP BY account_id")
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+
+        rows.filter_map(|r| r.ok()).collect()
+    }
+
     pub fn fetch_characters(
         conn: &PooledConnection<SqliteConnectionManager>,
         account_id: u32,
         data_store: Arc<DataStore>,
     ) -> Vec<CharEnumData> {
-        let mut stmt = conn.prepare_cached("SELECT guid, name, race, class, level, gender, skin, face, hairstyle, haircolor, facialstyle, map_id, zone_id, position_x, position_y, position_z FROM characters WHERE account_id = :account_id").unwrap();
+        let mut stmt = conn.prepare_cached("SELECT guid, name, race, class, level, gender, skin, face, hairstyle, haircolor, facialstyle, map_id, zone_id, position_x, position_y, position_z, at_login FROM characters WHERE account_id = :account_id").unwrap();
         let chars = stmt
             .query_map(named_params! { ":account_id": account_id }, |row| {
                 let char_guid: u64 = row.get("guid").unwrap();
@@ -204,6 +243,14 @@ impl CharacterRepository {
                 }).unwrap_or(CharEnumEquip::none(inv_type)))
                 .collect();
 
+                let at_login: BitFlags<AtLoginFlag> =
+                    BitFlags::from_bits_truncate(row.get("at_login").unwrap());
+                let flags = if at_login.contains(AtLoginFlag::Rename) {
+                    CHARACTER_FLAG_RENAME
+                } else {
+                    0
+                };
+
                 Ok(CharEnumData {
                     guid: row.get("guid").unwrap(),
                     name: row.get::<&str, String>("name").unwrap().into(),
@@ -222,7 +269,7 @@ impl CharacterRepository {
                     position_y: row.get("position_y").unwrap(),
                     position_z: row.get("position_z").unwrap(),
                     guild_id: 0,
-                    flags: 0,
+                    flags,
                     first_login: true, // TODO: Set to false after first login
                     pet_display_id: 0,
                     pet_level: 0,
@@ -240,19 +287,103 @@ impl CharacterRepository {
         source: CmsgCharDelete,
         account_id: u32,
     ) {
+        // Archive the character instead of deleting it, it no longer belongs to any account until
+        // it is restored
+        let deleted_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
         let mut stmt_delete = conn
             .prepare_cached(
-                "DELETE FROM characters WHERE guid = :guid AND account_id = :account_id",
+                "UPDATE characters SET deleted_at = :deleted_at, deleted_account_id = account_id, account_id = 0 WHERE guid = :guid AND account_id = :account_id AND deleted_at IS NULL",
             )
             .unwrap();
         stmt_delete
             .execute(named_params! {
+                ":deleted_at": deleted_at,
                 ":guid": source.guid,
                 ":account_id": account_id,
             })
             .unwrap();
     }
 
+    pub fn fetch_deleted_characters(
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> Vec<DeletedCharacterRecord> {
+        let mut stmt = conn.prepare_cached("SELECT guid, name, level, deleted_account_id, deleted_at FROM characters WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC").unwrap();
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(DeletedCharacterRecord {
+                    guid: row.get("guid")?,
+                    name: row.get("name")?,
+                    level: row.get("level")?,
+                    account_id: row.get("deleted_account_id")?,
+                    deleted_at: row.get("deleted_at")?,
+                })
+            })
+            .unwrap();
+
+        rows.filter_map(|r| r.ok()).collect()
+    }
+
+    // Gives an archived character back to its original account, returns that account
+    pub fn restore_character(
+        conn: &PooledConnection<SqliteConnectionManager>,
+        guid: u32,
+    ) -> Option<u32> {
+        let mut stmt = conn.prepare_cached("UPDATE characters SET account_id = deleted_account_id, deleted_at = NULL, deleted_account_id = NULL WHERE guid = :guid AND deleted_at IS NOT NULL RETURNING account_id").unwrap();
+        let mut rows = stmt.query(named_params! { ":guid": guid }).unwrap();
+
+        rows.next()
+            .unwrap()
+            .map(|row| row.get("account_id").unwrap())
+    }
+
+    // Permanently deletes the characters archived before the given timestamp along with
+    // everything they own, returns the number of characters purged
+    pub fn purge_deleted_characters(
+        conn: &mut PooledConnection<SqliteConnectionManager>,
+        deleted_before: u64,
+    ) -> usize {
+        let transaction = conn.transaction().unwrap();
+
+        // Foreign keys are not enforced, the owned rows have to be deleted explicitly
+        let purged_guids =
+            "SELECT guid FROM characters WHERE deleted_at IS NOT NULL AND deleted_at < :deleted_before";
+        transaction
+            .execute(
+                &format!("DELETE FROM items WHERE guid IN (SELECT item_guid FROM character_inventory WHERE character_guid IN ({purged_guids}))"),
+                named_params! { ":deleted_before": deleted_before },
+            )
+            .unwrap();
+        for table in [
+            "character_inventory",
+            "character_spells",
+            "character_skills",
+            "character_action_buttons",
+            "character_reputations",
+            "character_quests",
+            "character_spell_cooldowns",
+            "character_account_data",
+        ] {
+            transaction
+                .execute(
+                    &format!("DELETE FROM {table} WHERE character_guid IN ({purged_guids})"),
+                    named_params! { ":deleted_before": deleted_before },
+                )
+                .unwrap();
+        }
+        let purged = transaction
+            .execute(
+                "DELETE FROM characters WHERE deleted_at IS NOT NULL AND deleted_at < :deleted_before",
+                named_params! { ":deleted_before": deleted_before },
+            )
+            .unwrap();
+
+        transaction.commit().unwrap();
+        purged
+    }
+
     pub fn fetch_basic_character_data(
         conn: &PooledConnection<SqliteConnectionManager>,
         guid: u64,
@@ -262,7 +393,7 @@ impl CharacterRepository {
                 "SELECT account_id, race, class, level, gender, name, haircolor, hairstyle, face, skin, facialstyle,
                 map_id, zone_id, position_x, position_y, position_z, orientation, current_health, current_mana, current_rage,
                 current_energy, experience, money, bindpoint_map_id, bindpoint_area_id, bindpoint_position_x, bindpoint_position_y,
-                bindpoint_position_z, bindpoint_orientation, action_bar_toggles
+                bindpoint_position_z, bindpoint_orientation, action_bar_toggles, at_login
                 FROM characters WHERE guid = :guid")
             .unwrap();
         let mut rows = stmt
@@ -308,6 +439,7 @@ impl CharacterRepository {
             bindpoint_position_z: row.get("bindpoint_position_z").unwrap(),
             bindpoint_orientation: row.get("bindpoint_orientation").unwrap(),
             action_bar_toggles: row.get("action_bar_toggles").unwrap(),
+            at_login: BitFlags::from_bits_truncate(row.get("at_login").unwrap()),
         })
     }
 
@@ -391,7 +523,7 @@ impl CharacterRepository {
         conn: &PooledConnection<SqliteConnectionManager>,
         name: &str,
     ) -> Option<(ObjectGuid, WorldPosition)> {
-        let mut stmt = conn.prepare_cached("SELECT guid, map_id, zone_id, position_x, position_y, position_z, orientation FROM characters WHERE name = :name").unwrap();
+        let mut stmt = conn.prepare_cached("SELECT guid, map_id, zone_id, position_x, position_y, position_z, orientation FROM characters WHERE name = :name AND deleted_at IS NULL").unwrap();
         let mut rows = stmt.query(named_params! { ":name": name }).unwrap();
 
         rows.next().unwrap().and_then(|row| {
@@ -410,6 +542,74 @@ impl CharacterRepository {
         })
     }
 
+    // Used to move offline characters, returns false if the character does not exist or was
+    // deleted
+    pub fn update_position(
+        conn: &PooledConnection<SqliteConnectionManager>,
+        character_guid: u32,
+        position: &WorldPosition,
+    ) -> bool {
+        let mut stmt = conn.prepare_cached("UPDATE characters SET map_id = :map_id, zone_id = :zone_id, position_x = :x, position_y = :y, position_z = :z, orientation = :o WHERE guid = :guid AND deleted_at IS NULL").unwrap();
+        let updated = stmt
+            .execute(named_params! {
+                ":map_id": position.map_key.map_id,
+                ":zone_id": position.zone,
+                ":x": position.x,
+                ":y": position.y,
+                ":z": position.z,
+                ":o": position.o,
+                ":guid": character_guid,
+            })
+            .unwrap();
+
+        updated > 0
+    }
+
+    // Whether the character exists and is not deleted
+    pub fn is_active(transaction: &Transaction, character_guid: u32) -> bool {
+        let mut stmt = transaction
+            .prepare_cached("SELECT 1 FROM characters WHERE guid = :guid AND deleted_at IS NULL")
+            .unwrap();
+
+        stmt.exists(named_params! { ":guid": character_guid })
+            .unwrap()
+    }
+
+    pub fn add_at_login_flag(
+        conn: &PooledConnection<SqliteConnectionManager>,
+        character_guid: u32,
+        flag: AtLoginFlag,
+    ) {
+        let mut stmt = conn
+            .prepare_cached("UPDATE characters SET at_login = at_login | :flag WHERE guid = :guid")
+            .unwrap();
+        stmt.execute(named_params! {
+            ":flag": flag as u32,
+            ":guid": character_guid,
+        })
+        .unwrap();
+    }
+
+    // Only renames characters of the account that are flagged for it, returns whether it did
+    pub fn rename_character(
+        conn: &PooledConnection<SqliteConnectionManager>,
+        guid: u64,
+        account_id: u32,
+        name: &str,
+    ) -> bool {
+        let mut stmt = conn.prepare_cached("UPDATE characters SET name = :name, at_login = at_login & ~:flag WHERE guid = :guid AND account_id = :account_id AND at_login & :flag != 0").unwrap();
+        let updated = stmt
+            .execute(named_params! {
+                ":name": name,
+                ":flag": AtLoginFlag::Rename as u32,
+                ":guid": guid,
+                ":account_id": account_id,
+            })
+            .unwrap();
+
+        updated == 1
+    }
+
     pub fn add_item_to_inventory(
         transaction: &Transaction,
         character_guid: u32,
@@ -523,14 +723,39 @@ impl CharacterRepository {
         result.filter_map(|res| res.ok()).collect()
     }
 
+    pub fn get_last_save_generation(conn: &PooledConnection<SqliteConnectionManager>) -> u64 {
+        let mut stmt = conn
+            .prepare_cached("SELECT MAX(save_generation) FROM characters")
+            .unwrap();
+        let mut generation = stmt
+            .query_map([], |row| row.get::<usize, Option<u64>>(0))
+            .unwrap();
+
+        generation.next().unwrap().unwrap().unwrap_or(0)
+    }
+
+    // The items are marked as saved once the caller committed the transaction
     pub fn save_to_db(
         transaction: &Transaction,
-        player: &mut Player,
+        player: &Player,
         powers: &Powers,
         position: &WorldPosition,
         cooldowns: &Cooldowns,
-    ) -> Result<(), Error> {
-        let guid = player.guid().counter();
+        save_generation: u64,
+    ) -> Result<CharacterSnapshot, Error> {
+        let snapshot =
+            CharacterSnapshot::capture(player, powers, position, cooldowns, save_generation);
+        Self::save_snapshot_to_db(transaction, &snapshot)?;
+        Ok(snapshot)
+    }
+
+    // Returns false without writing anything if a more recent snapshot of the character was
+    // already saved
+    pub fn save_snapshot_to_db(
+        transaction: &Transaction,
+        snapshot: &CharacterSnapshot,
+    ) -> Result<bool, Error> {
+        let guid = snapshot.guid;
 
         // Save character data
         let mut stmt = transaction
@@ -541,27 +766,28 @@ impl CharacterRepository {
                 current_health = :current_health, current_mana = :current_mana, current_rage = :current_rage, current_energy = :current_energy,
                 experience = :experience, money = :money, bindpoint_map_id = :bindpoint_map_id, bindpoint_area_id = :bindpoint_area_id,
                 bindpoint_position_x = :bindpoint_position_x, bindpoint_position_y = :bindpoint_position_y, bindpoint_position_z = :bindpoint_position_z,
-                bindpoint_orientation = :bindpoint_orientation, action_bar_toggles = :action_bar_toggles
-                WHERE guid = :guid",
-            )
-            .unwrap();
+                bindpoint_orientation = :bindpoint_orientation, action_bar_toggles = :action_bar_toggles,
+                save_generation = :save_generation
+                WHERE guid = :guid AND save_generation < :save_generation",
+            )?;
 
-        let bindpoint = player.bindpoint();
+        let position = &snapshot.position;
+        let bindpoint = &snapshot.bindpoint;
 
-        stmt.execute(named_params! {
-            ":level": player.level(),
+        let updated = stmt.execute(named_params! {
+            ":level": snapshot.level,
             ":map_id": position.map_key.map_id,
             ":zone_id": position.zone,
             ":x": position.x,
             ":y": position.y,
             ":z": position.z,
             ":o": position.o,
-            ":current_health": powers.current_health(),
-            ":current_mana": powers.current_power(&PowerType::Mana),
-            ":current_rage": powers.current_power(&PowerType::Rage),
-            ":current_energy": powers.current_power(&PowerType::Energy),
-            ":experience": player.experience(),
-            ":money": player.money(),
+            ":current_health": snapshot.current_health,
+            ":current_mana": snapshot.current_mana,
+            ":current_rage": snapshot.current_rage,
+            ":current_energy": snapshot.current_energy,
+            ":experience": snapshot.experience,
+            ":money": snapshot.money,
             ":guid": guid,
             ":bindpoint_map_id": bindpoint.map_id,
             ":bindpoint_area_id": bindpoint.area_id,
@@ -569,84 +795,77 @@ impl CharacterRepository {
             ":bindpoint_position_y": bindpoint.y,
             ":bindpoint_position_z": bindpoint.z,
             ":bindpoint_orientation": bindpoint.o,
-            ":action_bar_toggles": player.action_bar_toggles(),
+            ":action_bar_toggles": snapshot.action_bar_toggles,
+            ":save_generation": snapshot.save_generation,
         })?;
+        if updated == 0 {
+            return Ok(false);
+        }
 
         // Save quest data
         let mut stmt = transaction
-            .prepare_cached("DELETE FROM character_quests WHERE character_guid = :guid")
-            .unwrap();
+            .prepare_cached("DELETE FROM character_quests WHERE character_guid = :guid")?;
         stmt.execute(named_params! { ":guid": guid })?;
 
         // TODO: Save the current timer here
-        let mut stmt = transaction.prepare_cached("INSERT INTO character_quests (character_guid, quest_id, status, entity_count1, entity_count2, entity_count3, entity_count4) VALUES (:guid, :quest_id, :status, :entity_count1, :entity_count2, :entity_count3, :entity_count4)").unwrap();
-        player
-            .quest_statuses()
-            .iter()
-            .for_each(|(quest_id, context)| {
-                stmt.execute(named_params! {
-                    ":guid": guid,
-                    ":quest_id": quest_id,
-                    ":status": context.status as u32,
-                    ":entity_count1": context.entity_counts[0],
-                    ":entity_count2": context.entity_counts[1],
-                    ":entity_count3": context.entity_counts[2],
-                    ":entity_count4": context.entity_counts[3],
-                })
-                .unwrap();
-            });
+        let mut stmt = transaction.prepare_cached("INSERT INTO character_quests (character_guid, quest_id, status, entity_count1, entity_count2, entity_count3, entity_count4) VALUES (:guid, :quest_id, :status, :entity_count1, :entity_count2, :entity_count3, :entity_count4)")?;
+        for (quest_id, context) in &snapshot.quest_statuses {
+            stmt.execute(named_params! {
+                ":guid": guid,
+                ":quest_id": quest_id,
+                ":status": context.status as u32,
+                ":entity_count1": context.entity_counts[0],
+                ":entity_count2": context.entity_counts[1],
+                ":entity_count3": context.entity_counts[2],
+                ":entity_count4": context.entity_counts[3],
+            })?;
+        }
 
         // Save inventory data
         // FIXME: We're creating orphaned items in table `items` with the current implementation
         // because we don't DELETE FROM items for deleted items
         let mut stmt = transaction
-            .prepare_cached("DELETE FROM character_inventory WHERE character_guid = :guid")
-            .unwrap();
+            .prepare_cached("DELETE FROM character_inventory WHERE character_guid = :guid")?;
         stmt.execute(named_params! { ":guid": guid })?;
 
-        for (slot, item) in player.inventory().list() {
-            if item.needs_db_save() {
-                ItemRepository::upsert(transaction, item);
+        for item in &snapshot.inventory {
+            if item.unsaved_changes > 0 {
+                ItemRepository::upsert(transaction, item.guid, item.entry, item.stack_count);
             }
 
-            let mut stmt = transaction.prepare_cached("INSERT INTO character_inventory(character_guid, item_guid, slot) VALUES (:character_guid, :item_guid, :slot)").unwrap();
+            let mut stmt = transaction.prepare_cached("INSERT INTO character_inventory(character_guid, item_guid, slot) VALUES (:character_guid, :item_guid, :slot)")?;
             stmt.execute(named_params! {
                 ":character_guid": guid,
-                ":item_guid": item.guid().counter(),
-                ":slot": slot,
-            })
-            .unwrap();
+                ":item_guid": item.guid,
+                ":slot": item.slot,
+            })?;
         }
 
-        player.inventory_mut().mark_saved();
-
         // Save spell cooldowns
         let mut stmt = transaction
-            .prepare_cached("DELETE FROM character_spell_cooldowns WHERE character_guid = :guid")
-            .unwrap();
+            .prepare_cached("DELETE FROM character_spell_cooldowns WHERE character_guid = :guid")?;
         stmt.execute(named_params! { ":guid": guid })?;
 
         let now = SystemTime::now();
-        for (spell_id, cooldown) in cooldowns.list() {
-            if cooldown.end < now {
+        for &(spell_id, item_id, end) in &snapshot.cooldowns {
+            if end < now {
                 continue; // Skip expired cooldowns
             }
 
-            let mut stmt = transaction.prepare_cached("INSERT INTO character_spell_cooldowns(character_guid, spell_id, item_id, cooldown_end_timestamp) VALUES (:guid, :spell_id, :item_id, :timestamp)").unwrap();
+            let mut stmt = transaction.prepare_cached("INSERT INTO character_spell_cooldowns(character_guid, spell_id, item_id, cooldown_end_timestamp) VALUES (:guid, :spell_id, :item_id, :timestamp)")?;
             stmt.execute(named_params! {
                 ":guid": guid,
                 ":spell_id": spell_id,
-                ":item_id": cooldown.item_id,
-                ":timestamp": cooldown.end.duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as i64,
+                ":item_id": item_id,
+                ":timestamp": end.duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as i64,
             })?;
         }
 
         // Save action buttons
         let mut stmt = transaction
-            .prepare_cached("DELETE FROM character_action_buttons WHERE character_guid = :guid")
-            .unwrap();
+            .prepare_cached("DELETE FROM character_action_buttons WHERE character_guid = :guid")?;
         stmt.execute(named_params! { ":guid": guid })?;
-        for action in player.action_buttons().values() {
+        for action in &snapshot.action_buttons {
             Self::add_action(
                 transaction,
                 guid,
@@ -658,17 +877,120 @@ impl CharacterRepository {
 
         // Save spells
         let mut stmt = transaction
-            .prepare_cached("DELETE FROM character_spells WHERE character_guid = :guid")
-            .unwrap();
+            .prepare_cached("DELETE FROM character_spells WHERE character_guid = :guid")?;
         stmt.execute(named_params! { ":guid": guid })?;
-        for &spell_id in player.spells() {
+        for &spell_id in &snapshot.spells {
             Self::add_spell(transaction, guid, spell_id);
         }
 
-        Ok(())
+        Ok(true)
     }
 }
 
+// Everything saved for a character, copied out of the map so that it can be written to the
+// database from another thread
+pub struct CharacterSnapshot {
+    guid: u32,
+    save_generation: u64,
+    level: u32,
+    position: WorldPosition,
+    current_health: u32,
+    current_mana: u32,
+    current_rage: u32,
+    current_energy: u32,
+    experience: u32,
+    money: u32,
+    bindpoint: BindPoint,
+    action_bar_toggles: u8,
+    quest_statuses: Vec<(u32, QuestLogContext)>,
+    inventory: Vec<InventoryItemSnapshot>,
+    cooldowns: Vec<(u32, Option<u32>, SystemTime)>, // Spell id, item id, end
+    action_buttons: Vec<ActionButton>,
+    spells: Vec<u32>,
+}
+
+struct InventoryItemSnapshot {
+    slot: u32,
+    guid: u32,
+    entry: u32,
+    stack_count: u32,
+    unsaved_changes: u32,
+}
+
+impl CharacterSnapshot {
+    // The generation orders the snapshots of a character, take it from
+    // WorldContext::next_save_generation
+    pub fn capture(
+        player: &Player,
+        powers: &Powers,
+        position: &WorldPosition,
+        cooldowns: &Cooldowns,
+        save_generation: u64,
+    ) -> Self {
+        let inventory = player
+            .inventory()
+            .list()
+            .iter()
+            .map(|(&slot, item)| InventoryItemSnapshot {
+                slot,
+                guid: item.guid().counter(),
+                entry: item.entry(),
+                stack_count: item.stack_count(),
+                unsaved_changes: item.unsaved_changes(),
+            })
+            .collect();
+
+        Self {
+            guid: player.guid().counter(),
+            save_generation,
+            level: player.level(),
+            position: *position,
+            current_health: powers.current_health(),
+            current_mana: powers.current_power(&PowerType::Mana),
+            current_rage: powers.current_power(&PowerType::Rage),
+            current_energy: powers.current_power(&PowerType::Energy),
+            experience: player.experience(),
+            money: player.money(),
+            bindpoint: player.bindpoint(),
+            action_bar_toggles: player.action_bar_toggles(),
+            quest_statuses: player
+                .quest_statuses()
+                .iter()
+                .map(|(&quest_id, &context)| (quest_id, context))
+                .collect(),
+            inventory,
+            cooldowns: cooldowns
+                .list()
+                .map(|(&spell_id, cooldown)| (spell_id, cooldown.item_id, cooldown.end))
+                .collect(),
+            action_buttons: player.action_buttons().values().cloned().collect(),
+            spells: player.spells().to_vec(),
+        }
+    }
+
+    pub fn guid(&self) -> u32 {
+        self.guid
+    }
+
+    // (item guid, number of changes) written with the snapshot, to pass to
+    // PlayerInventory::mark_saved once the snapshot is committed
+    pub fn saved_items(&self) -> Vec<(u32, u32)> {
+        self.inventory
+            .iter()
+            .filter(|item| item.unsaved_changes > 0)
+            .map(|item| (item.guid, item.unsaved_changes))
+            .collect()
+    }
+}
+
+pub struct DeletedCharacterRecord {
+    pub guid: u32,
+    pub name: String,
+    pub level: u32,
+    pub account_id: u32, // The account the character was deleted from
+    pub deleted_at: u64,
+}
+
 pub struct CharacterRecord {
     pub guid: u64,
     pub account_id: u32,
@@ -692,6 +1014,7 @@ pub struct CharacterRecord {
     pub bindpoint_position_z: f32,
     pub bindpoint_orientation: f32,
     pub action_bar_toggles: u8,
+    pub at_login: BitFlags<AtLoginFlag>,
 }
 
 impl CharacterRecord {
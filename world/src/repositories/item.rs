@@ -5,7 +5,6 @@ use rusqlite::{named_params, Transaction};
 
 use crate::{
     datastore::data_types::ItemTemplate,
-    entities::item::Item,
     protocol::packets::{
         ItemTemplateDamage, ItemTemplateSocket, ItemTemplateSpell, ItemTemplateStat,
     },
@@ -46,7 +45,7 @@ impl ItemRepository {
         transaction.last_insert_rowid() as u32
     }
 
-    pub fn upsert(transaction: &Transaction, item: &Item) {
+    pub fn upsert(transaction: &Transaction, guid: u32, entry: u32, stack_count: u32) {
         let mut stmt = transaction
             .prepare_cached(
                 "INSERT INTO items (guid, entry, stack_count)
@@ -55,9 +54,9 @@ impl ItemRepository {
             )
             .unwrap();
         stmt.execute(named_params! {
-            ":guid": item.guid().counter(),
-            ":entry": item.entry(),
-            ":stack_count": item.stack_count(),
+            ":guid": guid,
+            ":entry": entry,
+            ":stack_count": stack_count,
         })
         .unwrap();
     }
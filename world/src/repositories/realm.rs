@@ -0,0 +1,128 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::named_params;
+
+// Lives in the auth database, shared with the auth server which builds the realm list from it
+pub struct RealmRepository;
+
+impl RealmRepository {
+    pub fn register(
+        conn: &PooledConnection<SqliteConnectionManager>,
+        realm_id: u32,
+        max_players: u32,
+    ) {
+        let mut stmt = conn
+            .prepare_cached(
+                "UPDATE realms SET is_online = TRUE, online_players = 0, max_players = :max_players, last_heartbeat = :now WHERE id = :realm_id",
+            )
+            .unwrap();
+        let updated = stmt
+            .execute(named_params! {
+                ":realm_id": realm_id,
+                ":max_players": max_players,
+                ":now": Self::now_timestamp(),
+            })
+            .unwrap();
+
+        assert!(
+            updated == 1,
+            "realm {realm_id} does not exist in the auth database"
+        );
+    }
+
+    pub fn heartbeat(
+        conn: &PooledConnection<SqliteConnectionManager>,
+        realm_id: u32,
+        online_players: u32,
+        max_players: u32,
+    ) -> rusqlite::Result<()> {
+        let mut stmt = conn.prepare_cached(
+            "UPDATE realms SET is_online = TRUE, online_players = :online_players, max_players = :max_players, last_heartbeat = :now WHERE id = :realm_id",
+        )?;
+        stmt.execute(named_params! {
+            ":realm_id": realm_id,
+            ":online_players": online_players,
+            ":max_players": max_players,
+            ":now": Self::now_timestamp(),
+        })?;
+
+        Ok(())
+    }
+
+    pub fn set_offline(conn: &PooledConnection<SqliteConnectionManager>, realm_id: u32) {
+        let mut stmt = conn
+            .prepare_cached(
+                "UPDATE realms SET is_online = FALSE, online_players = 0 WHERE id = :realm_id",
+            )
+            .unwrap();
+        stmt.execute(named_params! { ":realm_id": realm_id })
+            .unwrap();
+    }
+
+    pub fn update_character_count(
+        conn: &PooledConnection<SqliteConnectionManager>,
+        realm_id: u32,
+        account_id: u32,
+        num_chars: u32,
+    ) {
+        let mut stmt = conn
+            .prepare_cached(
+                "INSERT INTO realm_characters(realm_id, account_id, num_chars) VALUES (:realm_id, :account_id, :num_chars)
+                ON CONFLICT(realm_id, account_id) DO UPDATE SET num_chars = excluded.num_chars",
+            )
+            .unwrap();
+        stmt.execute(named_params! {
+            ":realm_id": realm_id,
+            ":account_id": account_id,
+            ":num_chars": num_chars,
+        })
+        .unwrap();
+    }
+
+    // Replaces all the character counts of the realm, used at startup in case the characters
+    // database was modified while the server was down
+    pub fn sync_character_counts(
+        conn: &mut PooledConnection<SqliteConnectionManager>,
+        realm_id: u32,
+        counts: Vec<(u32, u32)>,
+    ) {
+        let transaction = conn.transaction().unwrap();
+
+        {
+            let mut stmt_delete = transaction
+                .prepare_cached("DELETE FROM realm_characters WHERE realm_id = :realm_id")
+                .unwrap();
+            stmt_delete
+                .execute(named_params! { ":realm_id": realm_id })
+                .unwrap();
+
+            // Skip accounts that were deleted from the auth database
+            let mut stmt_insert = transaction
+                .prepare_cached(
+                    "INSERT INTO realm_characters(realm_id, account_id, num_chars)
+                    SELECT :realm_id, id, :num_chars FROM accounts WHERE id = :account_id",
+                )
+                .unwrap();
+            for (account_id, num_chars) in counts {
+                stmt_insert
+                    .execute(named_params! {
+                        ":realm_id": realm_id,
+                        ":account_id": account_id,
+                        ":num_chars": num_chars,
+                    })
+                    .unwrap();
+            }
+        }
+
+        transaction.commit().unwrap();
+    }
+
+    fn now_timestamp() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+}
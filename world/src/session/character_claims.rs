@@ -0,0 +1,38 @@
+use std::collections::HashSet;
+
+use parking_lot::Mutex;
+
+// Characters being logged in or edited offline by a chat command. A character can only be claimed
+// once at a time so that an offline edit never races with the login loading the same character.
+#[derive(Default)]
+pub struct CharacterClaims {
+    claimed_guids: Mutex<HashSet<u64>>,
+}
+
+impl CharacterClaims {
+    // Returns None if the character is already claimed, the claim ends when the returned value is
+    // dropped
+    pub fn claim(&self, character_guid: u64) -> Option<CharacterClaim<'_>> {
+        self.claimed_guids
+            .lock()
+            .insert(character_guid)
+            .then_some(CharacterClaim {
+                claims: self,
+                character_guid,
+            })
+    }
+}
+
+pub struct CharacterClaim<'a> {
+    claims: &'a CharacterClaims,
+    character_guid: u64,
+}
+
+impl Drop for CharacterClaim<'_> {
+    fn drop(&mut self) {
+        self.claims
+            .claimed_guids
+            .lock()
+            .remove(&self.character_guid);
+    }
+}
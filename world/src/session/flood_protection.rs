@@ -0,0 +1,116 @@
+use std::{collections::HashMap, time::Duration};
+
+use atomic_counter::RelaxedCounter;
+use tokio::time::Instant;
+
+use crate::config::FloodProtectionSection;
+
+// Packets a session can send with a given opcode: up to burst at once, refilled at per_second
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    burst: u32,
+    per_second: u32,
+}
+
+impl RateLimit {
+    pub const DEFAULT: RateLimit = RateLimit::new(100, 50);
+    // Queries answered from the DataStore, the client sends a lot of them when logging in
+    pub const QUERY: RateLimit = RateLimit::new(200, 20);
+    pub const CHAT: RateLimit = RateLimit::new(10, 2);
+    // Opcodes that hit the database
+    pub const CHARACTER_MANAGEMENT: RateLimit = RateLimit::new(5, 1);
+
+    pub const fn new(burst: u32, per_second: u32) -> RateLimit {
+        RateLimit { burst, per_second }
+    }
+}
+
+pub enum PacketVerdict {
+    Accept,
+    Drop,
+    Kick,
+}
+
+struct TokenBucket {
+    tokens: f32,
+    last_refill: Instant,
+}
+
+// Per-session rate limiting of the client packets
+pub struct FloodProtection {
+    buckets: HashMap<u32, TokenBucket>,
+    dropped_per_opcode: HashMap<u32, u64>,
+    window_start: Instant,
+    dropped_in_window: u32,
+}
+
+impl Default for FloodProtection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FloodProtection {
+    pub fn new() -> Self {
+        Self {
+            buckets: HashMap::new(),
+            dropped_per_opcode: HashMap::new(),
+            window_start: Instant::now(),
+            dropped_in_window: 0,
+        }
+    }
+
+    // Packets over the budget of their opcode are dropped, the session must be kicked once it
+    // has dropped too many packets within the configured window
+    pub fn check(
+        &mut self,
+        opcode: u32,
+        rate_limit: RateLimit,
+        config: &FloodProtectionSection,
+    ) -> PacketVerdict {
+        if !config.enabled {
+            return PacketVerdict::Accept;
+        }
+
+        let now = Instant::now();
+        let bucket = self.buckets.entry(opcode).or_insert(TokenBucket {
+            tokens: rate_limit.burst as f32,
+            last_refill: now,
+        });
+
+        let refill = (now - bucket.last_refill).as_secs_f32() * rate_limit.per_second as f32;
+        bucket.tokens = (bucket.tokens + refill).min(rate_limit.burst as f32);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1. {
+            bucket.tokens -= 1.;
+            return PacketVerdict::Accept;
+        }
+
+        *self.dropped_per_opcode.entry(opcode).or_insert(0) += 1;
+
+        if now - self.window_start > Duration::from_secs(config.kick_window_secs) {
+            self.window_start = now;
+            self.dropped_in_window = 0;
+        }
+        self.dropped_in_window += 1;
+
+        if self.dropped_in_window >= config.kick_threshold {
+            PacketVerdict::Kick
+        } else {
+            PacketVerdict::Drop
+        }
+    }
+
+    // Dropped packets per opcode since the session started
+    pub fn dropped_packets(&self) -> &HashMap<u32, u64> {
+        &self.dropped_per_opcode
+    }
+}
+
+// Counters over all the sessions since the server started
+#[derive(Default)]
+pub struct FloodProtectionStats {
+    pub dropped_packets: RelaxedCounter,
+    pub kicked_sessions: RelaxedCounter,
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use log::trace;
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+use crate::{
+    protocol::{packets::SmsgAuthResponse, server::ServerMessage},
+    shared::response_codes::ResponseCodes,
+};
+
+use super::world_session::WorldSession;
+
+struct QueuedSession {
+    session: Arc<WorldSession>,
+    release_tx: oneshot::Sender<ReleasedSlot>,
+}
+
+// Slot taken by a session released from the queue until it is in the session holder. Dropping it
+// (once the session is inserted or if its client went away in the meantime) frees the slot.
+pub struct ReleasedSlot {
+    pending_releases: Arc<AtomicUsize>,
+}
+
+impl ReleasedSlot {
+    fn new(pending_releases: &Arc<AtomicUsize>) -> Self {
+        pending_releases.fetch_add(1, Ordering::AcqRel);
+
+        Self {
+            pending_releases: pending_releases.clone(),
+        }
+    }
+}
+
+impl Drop for ReleasedSlot {
+    fn drop(&mut self) {
+        self.pending_releases.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// Sessions waiting for a free slot when the realm is full, in order of arrival
+#[derive(Default)]
+pub struct LoginQueue {
+    sessions: Mutex<VecDeque<QueuedSession>>,
+    // Released sessions that are not in the session holder yet
+    pending_releases: Arc<AtomicUsize>,
+}
+
+impl LoginQueue {
+    pub fn new() -> Self {
+        Self {
+            sessions: Mutex::new(VecDeque::new()),
+            pending_releases: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    // Adds the session at the end of the queue and lets the client know its position.
+    // The returned receiver completes when the session is allowed to enter the world, it fails if
+    // the session was dropped from the queue because the client went away. The received slot
+    // must be kept until the session is in the session holder.
+    pub fn enqueue(&self, session: Arc<WorldSession>) -> oneshot::Receiver<ReleasedSlot> {
+        let (release_tx, release_rx) = oneshot::channel();
+
+        let mut sessions = self.sessions.lock();
+        let position = sessions.len() + 1;
+        Self::send_position(&session, position);
+        sessions.push_back(QueuedSession {
+            session,
+            release_tx,
+        });
+
+        release_rx
+    }
+
+    // Drops a session whose client disconnected while waiting, the others move up on the next
+    // update
+    pub fn remove(&self, session: &Arc<WorldSession>) {
+        self.sessions
+            .lock()
+            .retain(|queued| !Arc::ptr_eq(&queued.session, session));
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.lock().is_empty()
+    }
+
+    // Sessions released from the queue that are not in the session holder yet
+    pub fn pending_releases(&self) -> usize {
+        self.pending_releases.load(Ordering::Acquire)
+    }
+
+    // Releases the sessions at the front of the queue as long as there are free slots, then sends
+    // their new position to the remaining clients. The slots of the sessions released by previous
+    // updates but not in the session holder yet are not free.
+    pub fn update(&self, free_slots: usize) {
+        let mut sessions = self.sessions.lock();
+        let mut free_slots = free_slots.saturating_sub(self.pending_releases());
+
+        while free_slots > 0 {
+            let Some(queued) = sessions.pop_front() else {
+                break;
+            };
+
+            // The slot is given back right away if the client is gone
+            let slot = ReleasedSlot::new(&self.pending_releases);
+            if queued.release_tx.send(slot).is_ok() {
+                trace!(
+                    "released account {} from the login queue",
+                    queued.session.account_id
+                );
+                free_slots -= 1;
+            }
+        }
+
+        let mut position = 0;
+        sessions.retain(|queued| {
+            // Drop the clients that disconnected while waiting
+            if queued.release_tx.is_closed() || !Self::send_position(&queued.session, position + 1)
+            {
+                return false;
+            }
+
+            position += 1;
+            true
+        });
+    }
+
+    fn send_position(session: &WorldSession, position: usize) -> bool {
+        let packet = ServerMessage::new(SmsgAuthResponse {
+            result: ResponseCodes::AuthWaitQueue as u8,
+            billing_time: 0,
+            billing_flags: 0,
+            billing_rested: 0,
+            expansion: 1,
+            position_in_queue: position as u32,
+        });
+
+        session.send(&packet).is_ok()
+    }
+}
@@ -10,7 +10,7 @@ use crate::{
     shared::constants::{PlayerQuestStatus, QuestGiverStatus},
 };
 
-use super::world_session::WorldSession;
+use super::{flood_protection::RateLimit, world_session::WorldSession};
 
 pub struct PacketHandlerArgs {
     pub session: Arc<WorldSession>,
@@ -21,11 +21,15 @@ pub type PacketHandler = Box<dyn Send + Sync + Fn(PacketHandlerArgs)>;
 
 macro_rules! define_handler {
     ($opcode:expr, $processing_mode:expr, $handler:expr) => {
+        define_handler!($opcode, $processing_mode, $handler, RateLimit::DEFAULT)
+    };
+    ($opcode:expr, $processing_mode:expr, $handler:expr, $rate_limit:expr) => {
         (
             $opcode as u32,
             (
                 $processing_mode,
                 Box::new(|args| $handler(args)) as PacketHandler,
+                $rate_limit,
             ),
         )
     };
@@ -39,13 +43,14 @@ macro_rules! define_movement_handler {
                 OpcodeProcessingMode::ProcessInMap,
                 Box::new(|args| OpcodeHandler::handle_movement_packet($opcode)(args))
                     as PacketHandler,
+                RateLimit::DEFAULT,
             ),
         )
     };
 }
 
 pub struct OpcodeHandler {
-    handlers: HashMap<u32, (OpcodeProcessingMode, PacketHandler)>,
+    handlers: HashMap<u32, (OpcodeProcessingMode, PacketHandler, RateLimit)>,
 }
 
 impl Default for OpcodeHandler {
@@ -70,22 +75,32 @@ impl OpcodeHandler {
                 define_handler!(
                     Opcode::CmsgCharCreate,
                     ProcessImmediately,
-                    OpcodeHandler::handle_cmsg_char_create
+                    OpcodeHandler::handle_cmsg_char_create,
+                    RateLimit::CHARACTER_MANAGEMENT
                 ),
                 define_handler!(
                     Opcode::CmsgCharEnum,
                     ProcessImmediately,
-                    OpcodeHandler::handle_cmsg_char_enum
+                    OpcodeHandler::handle_cmsg_char_enum,
+                    RateLimit::CHARACTER_MANAGEMENT
                 ),
                 define_handler!(
                     Opcode::CmsgCharDelete,
                     ProcessImmediately,
-                    OpcodeHandler::handle_cmsg_char_delete
+                    OpcodeHandler::handle_cmsg_char_delete,
+                    RateLimit::CHARACTER_MANAGEMENT
+                ),
+                define_handler!(
+                    Opcode::CmsgCharRename,
+                    ProcessImmediately,
+                    OpcodeHandler::handle_cmsg_char_rename,
+                    RateLimit::CHARACTER_MANAGEMENT
                 ),
                 define_handler!(
                     Opcode::CmsgPlayerLogin,
                     ProcessImmediately,
-                    OpcodeHandler::handle_cmsg_player_login
+                    OpcodeHandler::handle_cmsg_player_login,
+                    RateLimit::CHARACTER_MANAGEMENT
                 ),
                 define_handler!(
                     Opcode::CmsgPing,
@@ -105,12 +120,14 @@ impl OpcodeHandler {
                 define_handler!(
                     Opcode::CmsgItemQuerySingle,
                     ProcessImmediately,
-                    OpcodeHandler::handle_cmsg_item_query_single
+                    OpcodeHandler::handle_cmsg_item_query_single,
+                    RateLimit::QUERY
                 ),
                 define_handler!(
                     Opcode::CmsgNameQuery,
                     ProcessImmediately,
-                    OpcodeHandler::handle_cmsg_name_query
+                    OpcodeHandler::handle_cmsg_name_query,
+                    RateLimit::QUERY
                 ),
                 define_handler!(
                     Opcode::CmsgQueryTime,
@@ -122,6 +139,11 @@ impl OpcodeHandler {
                     ProcessImmediately,
                     OpcodeHandler::handle_cmsg_update_account_data
                 ),
+                define_handler!(
+                    Opcode::CmsgRequestAccountData,
+                    ProcessImmediately,
+                    OpcodeHandler::handle_cmsg_request_account_data
+                ),
                 define_handler!(
                     Opcode::CmsgTimeSyncResp,
                     ProcessImmediately,
@@ -173,17 +195,20 @@ impl OpcodeHandler {
                     Opcode::CmsgMessageChat,
                     ProcessImmediately, // TODO: This should actually be process immediately first then
                     // forwarded to the map if it's a local message (say, yell, ...)
-                    OpcodeHandler::handle_cmsg_message_chat
+                    OpcodeHandler::handle_cmsg_message_chat,
+                    RateLimit::CHAT
                 ),
                 define_handler!(
                     Opcode::CmsgTextEmote,
                     ProcessInMap,
-                    OpcodeHandler::handle_cmsg_text_emote
+                    OpcodeHandler::handle_cmsg_text_emote,
+                    RateLimit::CHAT
                 ),
                 define_handler!(
                     Opcode::CmsgCreatureQuery,
                     ProcessImmediately,
-                    OpcodeHandler::handle_cmsg_creature_query
+                    OpcodeHandler::handle_cmsg_creature_query,
+                    RateLimit::QUERY
                 ),
                 define_handler!(
                     Opcode::CmsgAttackSwing,
@@ -223,12 +248,14 @@ impl OpcodeHandler {
                 define_handler!(
                     Opcode::CmsgNpcTextQuery,
                     ProcessImmediately,
-                    OpcodeHandler::handle_cmsg_npc_text_query
+                    OpcodeHandler::handle_cmsg_npc_text_query,
+                    RateLimit::QUERY
                 ),
                 define_handler!(
                     Opcode::CmsgQuestGiverQueryQuest,
                     ProcessImmediately,
-                    OpcodeHandler::handle_cmsg_quest_giver_query_quest
+                    OpcodeHandler::handle_cmsg_quest_giver_query_quest,
+                    RateLimit::QUERY
                 ),
                 define_handler!(
                     Opcode::CmsgQuestGiverAcceptQuest,
@@ -243,7 +270,8 @@ impl OpcodeHandler {
                 define_handler!(
                     Opcode::CmsgQuestQuery,
                     ProcessImmediately,
-                    OpcodeHandler::handle_cmsg_quest_query
+                    OpcodeHandler::handle_cmsg_quest_query,
+                    RateLimit::QUERY
                 ),
                 define_handler!(
                     Opcode::CmsgQuestLogRemoveQuest,
@@ -293,7 +321,8 @@ impl OpcodeHandler {
                 define_handler!(
                     Opcode::CmsgItemNameQuery,
                     ProcessImmediately,
-                    OpcodeHandler::handle_cmsg_item_name_query
+                    OpcodeHandler::handle_cmsg_item_name_query,
+                    RateLimit::QUERY
                 ),
                 define_handler!(
                     Opcode::CmsgAutostoreLootItem,
@@ -323,7 +352,8 @@ impl OpcodeHandler {
                 define_handler!(
                     Opcode::CmsgGameObjectQuery,
                     ProcessImmediately,
-                    OpcodeHandler::handle_cmsg_game_object_query
+                    OpcodeHandler::handle_cmsg_game_object_query,
+                    RateLimit::QUERY
                 ),
                 define_handler!(
                     Opcode::CmsgUseItem,
@@ -384,7 +414,7 @@ impl OpcodeHandler {
         }
     }
 
-    pub fn get_handler(&self, opcode: u32) -> &(OpcodeProcessingMode, PacketHandler) {
+    pub fn get_handler(&self, opcode: u32) -> &(OpcodeProcessingMode, PacketHandler, RateLimit) {
         self.handlers
             .get(&opcode)
             .inspect(|_| {
@@ -400,6 +430,12 @@ impl OpcodeHandler {
             })
     }
 
+    pub fn rate_limit(&self, opcode: u32) -> RateLimit {
+        self.handlers
+            .get(&opcode)
+            .map_or(RateLimit::DEFAULT, |&(_, _, rate_limit)| rate_limit)
+    }
+
     pub(crate) fn send_initial_gossip_menu(
         guid: u64,
         session: Arc<WorldSession>,
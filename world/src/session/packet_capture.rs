@@ -0,0 +1,142 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, info};
+use parking_lot::Mutex;
+
+use crate::protocol::opcodes::Opcode;
+
+const CAPTURE_HEADER_PREFIX: &str = "# rustbolt packet capture, account ";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PacketDirection {
+    ClientToServer,
+    ServerToClient,
+}
+
+impl PacketDirection {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PacketDirection::ClientToServer => "C",
+            PacketDirection::ServerToClient => "S",
+        }
+    }
+}
+
+pub struct CapturedPacket {
+    pub elapsed_ms: u64,
+    pub direction: PacketDirection,
+    pub opcode: u32,
+    pub payload: Vec<u8>,
+}
+
+// Decrypted packets of a session written to a text file, one packet per line:
+// <ms since session start> <C|S> <opcode name> <opcode> <hex payload>
+pub struct PacketCapture {
+    writer: Mutex<BufWriter<File>>,
+    start: Instant,
+}
+
+impl PacketCapture {
+    pub fn create(directory: &str, account_id: u32) -> io::Result<PacketCapture> {
+        fs::create_dir_all(directory)?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let path: PathBuf = [directory, &format!("{account_id}_{timestamp}.capture")]
+            .iter()
+            .collect();
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+        writeln!(writer, "{CAPTURE_HEADER_PREFIX}{account_id}")?;
+        info!(
+            "capturing packets of account {account_id} to {}",
+            path.display()
+        );
+
+        Ok(PacketCapture {
+            writer: Mutex::new(writer),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&self, direction: PacketDirection, opcode: u32, payload: &[u8]) {
+        let opcode_name =
+            Opcode::n(opcode).map_or_else(|| "Unknown".to_owned(), |opcode| format!("{opcode:?}"));
+        let payload = if payload.is_empty() {
+            "-".to_owned()
+        } else {
+            hex::encode_upper(payload)
+        };
+
+        let mut writer = self.writer.lock();
+        let result = writeln!(
+            writer,
+            "{} {} {} {:#06X} {}",
+            self.start.elapsed().as_millis(),
+            direction.as_str(),
+            opcode_name,
+            opcode,
+            payload
+        )
+        .and_then(|_| writer.flush());
+
+        if let Err(e) = result {
+            error!("failed to write to packet capture: {e}");
+        }
+    }
+
+    // Returns the account id of the captured session and its packets
+    pub fn read(path: &Path) -> io::Result<(u32, Vec<CapturedPacket>)> {
+        let invalid_data =
+            |line: usize| io::Error::new(io::ErrorKind::InvalidData, format!("line {line}"));
+
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let account_id = lines
+            .next()
+            .transpose()?
+            .and_then(|header| {
+                header
+                    .strip_prefix(CAPTURE_HEADER_PREFIX)
+                    .and_then(|account_id| account_id.parse::<u32>().ok())
+            })
+            .ok_or_else(|| invalid_data(1))?;
+
+        let mut packets = Vec::new();
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            let line_number = index + 2;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [elapsed_ms, direction, _opcode_name, opcode, payload] = fields[..] else {
+                return Err(invalid_data(line_number));
+            };
+
+            let direction = match direction {
+                "C" => PacketDirection::ClientToServer,
+                "S" => PacketDirection::ServerToClient,
+                _ => return Err(invalid_data(line_number)),
+            };
+            let payload = if payload == "-" {
+                Vec::new()
+            } else {
+                hex::decode(payload).map_err(|_| invalid_data(line_number))?
+            };
+
+            packets.push(CapturedPacket {
+                elapsed_ms: elapsed_ms.parse().map_err(|_| invalid_data(line_number))?,
+                direction,
+                opcode: u32::from_str_radix(opcode.trim_start_matches("0x"), 16)
+                    .map_err(|_| invalid_data(line_number))?,
+                payload,
+            });
+        }
+
+        Ok((account_id, packets))
+    }
+}
@@ -0,0 +1,105 @@
+use std::{io, path::Path, sync::Arc, time::Duration};
+
+use log::info;
+use rand::Rng;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::Instant,
+};
+use wow_srp::{normalized_string::NormalizedString, tbc_header::ProofSeed};
+
+use crate::{
+    game::world_context::WorldContext,
+    protocol::client::{ClientMessage, ClientMessageHeader},
+    repositories::account::AccountRepository,
+};
+
+use super::{
+    packet_capture::{PacketCapture, PacketDirection},
+    world_session::WorldSession,
+};
+
+// Feeds the client packets of a capture to a new session of the captured account, as if they had
+// been received from the network. Packets are spaced like in the capture, divided by speed.
+// Returns the number of replayed packets.
+pub async fn replay_capture(
+    path: &Path,
+    speed: f32,
+    world_context: Arc<WorldContext>,
+) -> io::Result<usize> {
+    let (account_id, packets) = PacketCapture::read(path)?;
+    info!(
+        "replaying {} packet(s) for account {account_id}",
+        packets.len()
+    );
+
+    // The session needs a socket: connect it to a client that ignores everything it receives
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mut client_stream = TcpStream::connect(listener.local_addr()?).await?;
+    let (server_stream, _) = listener.accept().await?;
+    tokio::spawn(async move {
+        let _ = tokio::io::copy(&mut client_stream, &mut tokio::io::sink()).await;
+    });
+
+    // Header encryption from a throwaway session key, nothing is decrypted on the client side
+    let username = NormalizedString::new("REPLAY").unwrap();
+    let mut session_key = [0_u8; 40];
+    rand::thread_rng().fill(&mut session_key[..]);
+    let server_seed = ProofSeed::new();
+    let client_seed = ProofSeed::new();
+    let client_seed_value = client_seed.seed();
+    let (client_proof, _) =
+        client_seed.into_proof_and_header_crypto(&username, session_key, server_seed.seed());
+    let encryption = server_seed
+        .into_header_crypto(&username, session_key, client_proof, client_seed_value)
+        .unwrap();
+
+    let security_level = AccountRepository::fetch_security_level(
+        &mut world_context.database.auth.get().unwrap(),
+        account_id,
+    );
+    let session = WorldSession::new(
+        server_stream,
+        encryption,
+        account_id,
+        security_level,
+        world_context.clone(),
+    );
+    world_context
+        .session_holder
+        .insert_session(account_id, session.clone());
+
+    let start = Instant::now();
+    let mut replayed = 0;
+    for packet in packets
+        .into_iter()
+        .filter(|packet| packet.direction == PacketDirection::ClientToServer)
+    {
+        let delay = Duration::from_millis(packet.elapsed_ms).div_f32(speed);
+        tokio::time::sleep_until(start + delay).await;
+
+        let client_message = ClientMessage {
+            header: ClientMessageHeader {
+                size: packet.payload.len() as u16 + 4, // + 4 for the opcode size
+                opcode: packet.opcode,
+            },
+            payload: packet.payload,
+        };
+        session
+            .queue_client_message(client_message)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "session is closed"))?;
+        replayed += 1;
+    }
+
+    // Let the maps process the last packets before leaving the world
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    session.shutdown(
+        &mut world_context.database.characters.get().unwrap(),
+        world_context.clone(),
+    );
+    world_context
+        .session_holder
+        .remove_session_if_current(&account_id, &session);
+
+    Ok(replayed)
+}
@@ -47,10 +47,58 @@ where
         }
     }
 
+    // Stores the session and returns the one it replaced if the caller must log it out, that is
+    // unless its grace period already ended and it is being logged out
+    pub fn replace_session(
+        &self,
+        key: Key,
+        session: Arc<WorldSession>,
+    ) -> Option<Arc<WorldSession>> {
+        self.sessions
+            .write()
+            .insert(key, session)
+            .filter(|previous| previous.take_over_logout())
+    }
+
+    // Removes the session stored under key only if it is this one and not a newer session that
+    // replaced it
+    pub fn remove_session_if_current(&self, key: &Key, session: &WorldSession) {
+        let mut sessions = self.sessions.write();
+        if sessions
+            .get(key)
+            .is_some_and(|current| std::ptr::eq(current.as_ref(), session))
+        {
+            sessions.remove(key);
+        }
+    }
+
+    // Returns the detached session stored under key after cancelling its grace period
+    pub fn reattach_session(&self, key: &Key) -> Option<Arc<WorldSession>> {
+        let sessions = self.sessions.write();
+        sessions
+            .get(key)
+            .filter(|session| session.cancel_grace_period())
+            .cloned()
+    }
+
+    // Returns true if the grace period of the session stored under key ended before its client
+    // reconnected, the caller then logs it out
+    pub fn end_grace_period(&self, key: &Key, session: &WorldSession) -> bool {
+        let sessions = self.sessions.write();
+        sessions
+            .get(key)
+            .is_some_and(|current| std::ptr::eq(current.as_ref(), session))
+            && session.end_grace_period()
+    }
+
     pub fn get_session(&self, key: &Key) -> Option<Arc<WorldSession>> {
         self.sessions.read().get(key).cloned()
     }
 
+    pub fn count(&self) -> usize {
+        self.sessions.read().len()
+    }
+
     pub fn get_matching_sessions(
         &self,
         predicate: impl Fn(&Key) -> bool,
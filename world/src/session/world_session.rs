@@ -1,3 +1,4 @@
+use atomic_counter::AtomicCounter;
 use binrw::{io::Cursor, BinWriterExt, NullString};
 use bytemuck::cast_slice;
 use chrono::{Datelike, Timelike};
@@ -11,28 +12,28 @@ use std::{
         atomic::{AtomicU32, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use log::{error, trace};
+use log::{error, info, trace, warn};
 use tokio::{
     net::TcpStream,
     sync::{
         mpsc::{self, error::SendError, UnboundedSender},
-        Mutex,
+        Mutex, Notify,
     },
     task::JoinHandle,
 };
 use wow_srp::tbc_header::HeaderCrypto;
 
 use crate::{
-    ecs::components::{cooldowns::Cooldowns, powers::Powers},
+    ecs::components::{cooldowns::Cooldowns, powers::Powers, unit::Unit},
     entities::{
         object_guid::ObjectGuid,
         player::{player_data::BindPoint, Player},
         position::WorldPosition,
     },
-    game::{map::Map, world_context::WorldContext},
+    game::{map::Map, metrics::Metrics, world_context::WorldContext},
     protocol::{
         client::ClientMessage,
         opcodes::Opcode,
@@ -47,16 +48,23 @@ use crate::{
     },
     repositories::character::CharacterRepository,
     shared::constants::{
-        ChatMessageType, Language, MAX_VISIBLE_REPUTATIONS, PLAYER_MAX_ACTION_BUTTONS,
+        ChatMessageType, Language, SecurityLevel, MAX_VISIBLE_REPUTATIONS,
+        PLAYER_MAX_ACTION_BUTTONS,
     },
     WorldSocketError,
 };
 
 use super::{
-    opcode_handler::OpcodeProcessingMode, opcode_handler::PacketHandlerArgs,
+    flood_protection::{FloodProtection, PacketVerdict},
+    opcode_handler::OpcodeProcessingMode,
+    opcode_handler::PacketHandlerArgs,
+    packet_capture::{PacketCapture, PacketDirection},
     world_socket::WorldSocket,
 };
 
+// Keeps the uncompressed update packets well within the u16 size of the packet header
+const MAX_UPDATE_BATCH_SIZE: usize = 32 * 1024;
+
 #[derive(PartialEq, Eq)]
 pub enum WorldSessionState {
     OnCharactersList,
@@ -64,10 +72,23 @@ pub enum WorldSessionState {
     InMapTransfer,
 }
 
+// Transitions happen under the lock of the session holder so that a reconnection and the end of
+// the grace period cannot both win
+enum ConnectionState {
+    Connected,
+    // The client lost the connection, the character logs out when the grace period ends
+    Detached(JoinHandle<()>),
+    LoggingOut,
+}
+
 pub struct WorldSession {
-    socket: WorldSocket,
-    session_to_socket_tx: UnboundedSender<(ServerMessageHeader, Vec<u8>)>,
+    // Both replaced when the client reconnects during the grace period
+    socket: RwLock<Arc<WorldSocket>>,
+    session_to_socket_tx: RwLock<Option<UnboundedSender<(ServerMessageHeader, Vec<u8>)>>>, // None while detached
+    socket_to_session_tx: UnboundedSender<ClientMessage>,
+    capture: Option<Arc<PacketCapture>>,
     pub account_id: u32,
+    pub security_level: SecurityLevel,
     pub state: RwLock<WorldSessionState>,
     current_map: RwLock<Option<Arc<Map>>>,
     player_entity_id: RwLock<Option<EntityId>>,
@@ -76,6 +97,11 @@ pub struct WorldSession {
     server_time_sync: parking_lot::Mutex<TimeSync>,
     time_sync_handle: parking_lot::Mutex<Option<JoinHandle<()>>>,
     known_guids: RwLock<Vec<ObjectGuid>>,
+    flood_protection: parking_lot::Mutex<FloodProtection>,
+    kick_notify: Notify,
+    connection_state: parking_lot::Mutex<ConnectionState>,
+    pending_updates: parking_lot::Mutex<PendingUpdates>,
+    update_compression_threshold: usize,
 }
 
 impl WorldSession {
@@ -83,33 +109,42 @@ impl WorldSession {
         socket: TcpStream,
         encryption: HeaderCrypto,
         account_id: u32,
+        security_level: SecurityLevel,
         world_context: Arc<WorldContext>,
     ) -> Arc<WorldSession> {
-        let (read_half, write_half) = tokio::io::split(socket);
-
-        let read_half = Arc::new(Mutex::new(read_half));
-        let write_half = Arc::new(Mutex::new(write_half));
-        let encryption = Arc::new(Mutex::new(encryption));
-
-        let (session_to_socket_tx, session_to_socket_rx) =
-            mpsc::unbounded_channel::<(ServerMessageHeader, Vec<u8>)>();
-
         let (socket_to_session_tx, mut socket_to_session_rx) =
             mpsc::unbounded_channel::<ClientMessage>();
 
-        let socket = WorldSocket::new(
-            write_half,
-            read_half,
+        let config = world_context.config.get();
+        let dev_config = &config.world.dev;
+        let capture = if dev_config.capture_packets {
+            match PacketCapture::create(&dev_config.capture_directory, account_id) {
+                Ok(capture) => Some(Arc::new(capture)),
+                Err(e) => {
+                    error!("unable to start the packet capture for account {account_id}: {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let (socket, session_to_socket_tx) = Self::open_socket(
+            socket,
             encryption,
             account_id,
-            session_to_socket_rx,
-            socket_to_session_tx,
+            socket_to_session_tx.clone(),
+            capture.clone(),
+            world_context.metrics.clone(),
         );
 
         let session = Arc::new(WorldSession {
-            socket,
-            session_to_socket_tx,
+            socket: RwLock::new(socket),
+            session_to_socket_tx: RwLock::new(Some(session_to_socket_tx)),
+            socket_to_session_tx,
+            capture,
             account_id,
+            security_level,
             state: RwLock::new(WorldSessionState::OnCharactersList),
             current_map: RwLock::new(None),
             player_entity_id: RwLock::new(None),
@@ -123,13 +158,18 @@ impl WorldSession {
             }),
             time_sync_handle: parking_lot::Mutex::new(None),
             known_guids: RwLock::new(Vec::new()),
+            flood_protection: parking_lot::Mutex::new(FloodProtection::new()),
+            kick_notify: Notify::new(),
+            connection_state: parking_lot::Mutex::new(ConnectionState::Connected),
+            pending_updates: parking_lot::Mutex::new(PendingUpdates::default()),
+            update_compression_threshold: config.world.update_packets.compression_threshold,
         });
 
         let world_context_clone = world_context.clone();
         let session_clone = session.clone();
         tokio::spawn(async move {
             while let Some(client_message) = socket_to_session_rx.recv().await {
-                let (processing_mode, handler) = world_context_clone
+                let (processing_mode, handler, _) = world_context_clone
                     .opcode_handler
                     .get_handler(client_message.header.opcode);
 
@@ -156,13 +196,179 @@ impl WorldSession {
         session
     }
 
+    fn open_socket(
+        socket: TcpStream,
+        encryption: HeaderCrypto,
+        account_id: u32,
+        socket_to_session_tx: UnboundedSender<ClientMessage>,
+        capture: Option<Arc<PacketCapture>>,
+        metrics: Arc<Metrics>,
+    ) -> (
+        Arc<WorldSocket>,
+        UnboundedSender<(ServerMessageHeader, Vec<u8>)>,
+    ) {
+        let (read_half, write_half) = tokio::io::split(socket);
+
+        let read_half = Arc::new(Mutex::new(read_half));
+        let write_half = Arc::new(Mutex::new(write_half));
+        let encryption = Arc::new(Mutex::new(encryption));
+
+        let (session_to_socket_tx, session_to_socket_rx) =
+            mpsc::unbounded_channel::<(ServerMessageHeader, Vec<u8>)>();
+
+        let socket = WorldSocket::new(
+            write_half,
+            read_half,
+            encryption,
+            account_id,
+            session_to_socket_rx,
+            socket_to_session_tx,
+            capture,
+            metrics,
+        );
+
+        (Arc::new(socket), session_to_socket_tx)
+    }
+
+    pub(crate) fn socket(&self) -> Arc<WorldSocket> {
+        self.socket.read().clone()
+    }
+
+    // Keeps the character in the world after the client lost the connection, until it reconnects
+    // or the grace period ends
+    pub fn detach(self: &Arc<Self>, grace_period: Duration, world_context: Arc<WorldContext>) {
+        // Ends the socket task, the packets sent in the meantime are dropped
+        self.session_to_socket_tx.write().take();
+        if let Some(handle) = self.time_sync_handle.lock().take() {
+            handle.abort();
+        }
+
+        let session = self.clone();
+        let handle = tokio::spawn(async move {
+            tokio::time::sleep(grace_period).await;
+
+            // Don't let the character vanish in the middle of a fight
+            while session.is_in_combat() {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+
+            // The client reconnected or another session took over in the meantime
+            if !world_context
+                .session_holder
+                .end_grace_period(&session.account_id, &session)
+            {
+                return;
+            }
+
+            info!(
+                "grace period over for account {}, logging out its character",
+                session.account_id
+            );
+            session.shutdown(
+                &mut world_context.database.characters.get().unwrap(),
+                world_context.clone(),
+            );
+            world_context
+                .session_holder
+                .remove_session_if_current(&session.account_id, &session);
+            world_context.update_login_queue();
+        });
+        *self.connection_state.lock() = ConnectionState::Detached(handle);
+    }
+
+    // Returns false if the session is not detached, e.g. the grace period already ended and the
+    // character is being logged out. Call through SessionHolder::reattach_session.
+    pub fn cancel_grace_period(&self) -> bool {
+        let mut connection_state = self.connection_state.lock();
+        match std::mem::replace(&mut *connection_state, ConnectionState::Connected) {
+            ConnectionState::Detached(handle) => {
+                handle.abort();
+                true
+            }
+            other => {
+                *connection_state = other;
+                false
+            }
+        }
+    }
+
+    // Returns false if the client reconnected or the session is already being logged out. Call
+    // through SessionHolder::end_grace_period.
+    pub fn end_grace_period(&self) -> bool {
+        let mut connection_state = self.connection_state.lock();
+        if !matches!(*connection_state, ConnectionState::Detached(_)) {
+            return false;
+        }
+
+        *connection_state = ConnectionState::LoggingOut;
+        true
+    }
+
+    // Returns false if the session is already being logged out by someone else, the caller is
+    // responsible for the logout otherwise
+    pub fn take_over_logout(&self) -> bool {
+        let mut connection_state = self.connection_state.lock();
+        match std::mem::replace(&mut *connection_state, ConnectionState::LoggingOut) {
+            ConnectionState::Connected => true,
+            ConnectionState::Detached(handle) => {
+                handle.abort();
+                true
+            }
+            ConnectionState::LoggingOut => false,
+        }
+    }
+
+    // Gives a detached session the connection of the reconnecting client, which doesn't know about
+    // any entity yet
+    pub fn attach_socket(
+        &self,
+        socket: TcpStream,
+        encryption: HeaderCrypto,
+        world_context: &WorldContext,
+    ) {
+        let (socket, session_to_socket_tx) = Self::open_socket(
+            socket,
+            encryption,
+            self.account_id,
+            self.socket_to_session_tx.clone(),
+            self.capture.clone(),
+            world_context.metrics.clone(),
+        );
+
+        *self.socket.write() = socket;
+        self.session_to_socket_tx
+            .write()
+            .replace(session_to_socket_tx);
+        *self.flood_protection.lock() = FloodProtection::new();
+        *self.pending_updates.lock() = PendingUpdates::default();
+        self.known_guids.write().clear();
+    }
+
+    fn is_in_combat(&self) -> bool {
+        self.run(&|WSRunnableArgs {
+                       map,
+                       player_entity_id,
+                   }| {
+            map.world().run(|v_unit: View<Unit>| {
+                v_unit
+                    .get(player_entity_id)
+                    .is_ok_and(|unit| unit.combat_state())
+            })
+        })
+        .unwrap_or(false)
+    }
+
     pub fn shutdown(
         &self,
         conn: &mut PooledConnection<SqliteConnectionManager>,
         world_context: Arc<WorldContext>,
     ) {
         self.cleanup_on_world_leave(conn, world_context);
-        self.socket.shutdown();
+        self.socket().shutdown();
+    }
+
+    pub async fn close_socket(&self) {
+        self.socket().close().await;
     }
 
     fn cleanup_on_world_leave(
@@ -181,17 +387,26 @@ impl WorldSession {
                      v_wpos: View<WorldPosition>,
                      v_powers: View<Powers>,
                      v_cooldowns: View<Cooldowns>| {
+                        let save_start = Instant::now();
                         let transaction = conn.transaction().unwrap();
 
-                        CharacterRepository::save_to_db(
+                        let snapshot = CharacterRepository::save_to_db(
                             &transaction,
-                            &mut vm_player[entity_id],
+                            &vm_player[entity_id],
                             &v_powers[entity_id],
                             &v_wpos[entity_id],
                             &v_cooldowns[entity_id],
+                            world_context.next_save_generation(),
                         )
                         .unwrap();
                         transaction.commit().unwrap();
+                        vm_player[entity_id]
+                            .inventory_mut()
+                            .mark_saved(&snapshot.saved_items());
+                        world_context
+                            .metrics
+                            .db_save_duration
+                            .observe(save_start.elapsed());
                     },
                 );
             }
@@ -203,9 +418,10 @@ impl WorldSession {
             self.player_entity_id.write().take();
             self.player_guid.write().take();
 
+            // A new session may already have replaced this one
             world_context
                 .session_holder
-                .remove_session(&self.account_id);
+                .remove_session_if_current(&self.account_id, self);
         }
     }
 
@@ -246,34 +462,95 @@ impl WorldSession {
         &self,
         packet: &ServerMessage<OPCODE, Payload>,
     ) -> Result<(), SendError<(ServerMessageHeader, Vec<u8>)>> {
-        let tx = self.session_to_socket_tx.clone();
         let payload = packet.encode_payload().expect("failed to encode payload");
-        let channel_payload = if OPCODE == Opcode::SmsgUpdateObject as u16 && payload.len() > 50 {
-            // Change to SMSG_COMPRESSED_UPDATE_OBJECT and compress the payload
-            let uncompressed_size = payload.len();
-            let compressed_payload: Vec<u8> = miniz_oxide::deflate::compress_to_vec_zlib(
+        if OPCODE == Opcode::SmsgUpdateObject as u16 {
+            return self.queue_update_blocks(&payload);
+        }
+
+        // The other packets may refer to the objects created by the pending updates
+        self.flush_updates()?;
+
+        if let Some(capture) = &self.capture {
+            capture.record(PacketDirection::ServerToClient, OPCODE as u32, &payload);
+        }
+        let header = ServerMessageHeader {
+            size: payload.len() as u16 + 2, // + 2 for the opcode size
+            opcode: OPCODE,
+        };
+        self.send_to_socket((header, payload))
+    }
+
+    // Update blocks are grouped until the end of the map tick or the next packet of another kind
+    fn queue_update_blocks(
+        &self,
+        payload: &[u8],
+    ) -> Result<(), SendError<(ServerMessageHeader, Vec<u8>)>> {
+        // Updates count (u32) and has_transport (u8), followed by the blocks
+        let updates_count = u32::from_le_bytes(payload[..4].try_into().unwrap());
+        let blocks = &payload[5..];
+
+        let mut pending = self.pending_updates.lock();
+        if !pending.blocks.is_empty() && pending.blocks.len() + blocks.len() > MAX_UPDATE_BATCH_SIZE
+        {
+            let batch = std::mem::take(&mut *pending);
+            self.send_update_batch(batch)?;
+        }
+
+        pending.updates_count += updates_count;
+        pending.blocks.extend_from_slice(blocks);
+        Ok(())
+    }
+
+    pub fn flush_updates(&self) -> Result<(), SendError<(ServerMessageHeader, Vec<u8>)>> {
+        let batch = std::mem::take(&mut *self.pending_updates.lock());
+        if batch.updates_count == 0 {
+            return Ok(());
+        }
+
+        self.send_update_batch(batch)
+    }
+
+    fn send_update_batch(
+        &self,
+        batch: PendingUpdates,
+    ) -> Result<(), SendError<(ServerMessageHeader, Vec<u8>)>> {
+        let mut payload = Vec::with_capacity(batch.blocks.len() + 5);
+        payload.extend_from_slice(&batch.updates_count.to_le_bytes());
+        payload.push(0); // has_transport
+        payload.extend(batch.blocks);
+
+        if let Some(capture) = &self.capture {
+            capture.record(
+                PacketDirection::ServerToClient,
+                Opcode::SmsgUpdateObject as u32,
                 &payload,
-                CompressionLevel::DefaultLevel as u8,
             );
+        }
 
-            let header = ServerMessageHeader {
-                size: compressed_payload.len() as u16 + 2 + 4, /* + 2 for opcode + 4 for uncompressed_size */
-                opcode: Opcode::SmsgCompressedUpdateObject as u16,
-            };
-
-            let payload: Vec<u32> = vec![uncompressed_size as u32];
-            let mut payload: Vec<u8> = cast_slice(&payload).to_vec();
-            payload.extend(compressed_payload);
-            (header, payload)
-        } else {
+        if payload.len() <= self.update_compression_threshold {
             let header = ServerMessageHeader {
                 size: payload.len() as u16 + 2, // + 2 for the opcode size
-                opcode: OPCODE,
+                opcode: Opcode::SmsgUpdateObject as u16,
             };
+            return self.send_to_socket((header, payload));
+        }
+
+        // Change to SMSG_COMPRESSED_UPDATE_OBJECT and compress the payload
+        let uncompressed_size = payload.len();
+        let compressed_payload: Vec<u8> = miniz_oxide::deflate::compress_to_vec_zlib(
+            &payload,
+            CompressionLevel::DefaultLevel as u8,
+        );
 
-            (header, payload)
+        let header = ServerMessageHeader {
+            size: compressed_payload.len() as u16 + 2 + 4, /* + 2 for opcode + 4 for uncompressed_size */
+            opcode: Opcode::SmsgCompressedUpdateObject as u16,
         };
-        tx.send(channel_payload)
+
+        let payload: Vec<u32> = vec![uncompressed_size as u32];
+        let mut payload: Vec<u8> = cast_slice(&payload).to_vec();
+        payload.extend(compressed_payload);
+        self.send_to_socket((header, payload))
     }
 
     pub fn send_movement(
@@ -282,31 +559,108 @@ impl WorldSession {
         origin_guid: &ObjectGuid,
         movement_info: &MovementInfo,
     ) -> Result<(), SendError<(ServerMessageHeader, Vec<u8>)>> {
-        let tx = self.session_to_socket_tx.clone();
+        self.flush_updates()?;
+
         let mut writer = Cursor::new(Vec::new());
         writer.write_le(&origin_guid.as_packed()).unwrap();
         writer.write_le(movement_info).unwrap();
         let payload = writer.get_ref().clone();
+        if let Some(capture) = &self.capture {
+            capture.record(PacketDirection::ServerToClient, opcode as u32, &payload);
+        }
 
         let header = ServerMessageHeader {
             size: payload.len() as u16 + 2, // + 2 for the opcode size
             opcode: opcode as u16,
         };
 
-        tx.send((header, payload))
+        self.send_to_socket((header, payload))
+    }
+
+    fn send_to_socket(
+        &self,
+        message: (ServerMessageHeader, Vec<u8>),
+    ) -> Result<(), SendError<(ServerMessageHeader, Vec<u8>)>> {
+        match self.session_to_socket_tx.read().as_ref() {
+            Some(tx) => tx.send(message),
+            // Nobody to send it to while detached, the client gets a fresh state when it comes back
+            None => Ok(()),
+        }
     }
 
     pub async fn process_incoming_packet(
         session: Arc<WorldSession>,
+        world_context: &WorldContext,
     ) -> Result<(), WorldSocketError> {
-        let client_message = session.socket.read_packet().await?;
-        if let Err(e) = session.socket.queue_client_message(client_message) {
+        let socket = session.socket();
+        let client_message = tokio::select! {
+            client_message = socket.read_packet() => client_message?,
+            _ = session.kick_notify.notified() => return Err(WorldSocketError::Kicked),
+        };
+
+        let opcode = client_message.header.opcode;
+        world_context.metrics.record_packet_received(opcode);
+        let verdict = session.flood_protection.lock().check(
+            opcode,
+            world_context.opcode_handler.rate_limit(opcode),
+            &world_context.config.get().world.flood_protection,
+        );
+        match verdict {
+            PacketVerdict::Accept => (),
+            PacketVerdict::Drop => {
+                trace!(
+                    "dropped {:?} from account {} (rate limit exceeded)",
+                    Opcode::n(opcode).unwrap(),
+                    session.account_id
+                );
+                world_context.flood_stats.dropped_packets.inc();
+                return Ok(());
+            }
+            PacketVerdict::Kick => {
+                warn!(
+                    "kicking account {} for flooding {:?}",
+                    session.account_id,
+                    Opcode::n(opcode).unwrap()
+                );
+                world_context.flood_stats.dropped_packets.inc();
+                world_context.flood_stats.kicked_sessions.inc();
+                return Err(WorldSocketError::Flooding(opcode));
+            }
+        }
+
+        if let Err(e) = session.socket_to_session_tx.send(client_message) {
             return Err(e.into());
         }
 
         Ok(())
     }
 
+    // Closes the session from the server side, the socket is shut down by the network task
+    pub fn kick(&self) {
+        self.kick_notify.notify_one();
+    }
+
+    // Dropped packets per opcode since the session started
+    pub fn dropped_packets(&self) -> Vec<(u32, u64)> {
+        let mut dropped: Vec<(u32, u64)> = self
+            .flood_protection
+            .lock()
+            .dropped_packets()
+            .iter()
+            .map(|(&opcode, &count)| (opcode, count))
+            .collect();
+        dropped.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        dropped
+    }
+
+    // Dispatches a packet as if it had been received from the socket
+    pub(crate) fn queue_client_message(
+        &self,
+        client_message: ClientMessage,
+    ) -> Result<(), SendError<ClientMessage>> {
+        self.socket_to_session_tx.send(client_message)
+    }
+
     fn schedule_time_sync(
         session: Arc<WorldSession>,
         world_context: Arc<WorldContext>,
@@ -668,6 +1022,12 @@ impl std::hash::Hash for WorldSession {
     }
 }
 
+#[derive(Default)]
+struct PendingUpdates {
+    updates_count: u32,
+    blocks: Vec<u8>,
+}
+
 struct TimeSync {
     pub server_counter: u32,
     pub server_last_sync_ticks: u32,
@@ -1,9 +1,9 @@
 use std::sync::Arc;
 
 use binrw::BinWriterExt;
-use log::{error, trace};
+use log::{trace, warn};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf},
+    io::{AsyncWriteExt, ReadHalf, WriteHalf},
     net::TcpStream,
     sync::{
         mpsc::{error::SendError, UnboundedReceiver, UnboundedSender},
@@ -13,18 +13,20 @@ use tokio::{
 use wow_srp::tbc_header::HeaderCrypto;
 
 use crate::{
-    protocol::{
-        client::{ClientMessage, ClientMessageHeader},
-        server::ServerMessageHeader,
-    },
+    game::metrics::Metrics,
+    protocol::{client::ClientMessage, server::ServerMessageHeader},
     WorldSocketError,
 };
 
+use super::packet_capture::{PacketCapture, PacketDirection};
+
 pub struct WorldSocket {
+    write_half: Arc<Mutex<WriteHalf<TcpStream>>>,
     pub read_half: Arc<Mutex<ReadHalf<TcpStream>>>,
     pub encryption: Arc<Mutex<HeaderCrypto>>,
     pub account_id: u32,
     socket_to_session_tx: UnboundedSender<ClientMessage>,
+    capture: Option<Arc<PacketCapture>>,
 }
 
 impl WorldSocket {
@@ -35,11 +37,15 @@ impl WorldSocket {
         account_id: u32,
         mut rx: UnboundedReceiver<(ServerMessageHeader, Vec<u8>)>,
         socket_to_session_tx: UnboundedSender<ClientMessage>,
+        capture: Option<Arc<PacketCapture>>,
+        metrics: Arc<Metrics>,
     ) -> WorldSocket {
         let encryption_clone = encryption.clone();
+        let write_half_clone = write_half.clone();
         tokio::spawn(async move {
             while let Some((header, payload)) = rx.recv().await {
-                let mut socket = write_half.lock().await;
+                metrics.record_packet_sent(header.opcode);
+                let mut socket = write_half_clone.lock().await;
                 let mut encryption = encryption_clone.lock().await;
 
                 // info!(
@@ -66,10 +72,12 @@ impl WorldSocket {
         });
 
         WorldSocket {
+            write_half,
             read_half,
             encryption,
             account_id,
             socket_to_session_tx,
+            capture,
         }
     }
 
@@ -81,49 +89,32 @@ impl WorldSocket {
     }
 
     pub async fn read_packet(&self) -> Result<ClientMessage, WorldSocketError> {
-        let mut buf = [0_u8; 6];
         let mut socket = self.read_half.lock().await;
 
-        match socket.read(&mut buf[..6]).await {
-            Ok(0) => {
-                trace!("Client disconnected");
-                Err(WorldSocketError::ClientDisconnected)
-            }
-            Ok(n) if n < 6 => {
-                error!("Received less than 6 bytes, need to handle partial header");
-                Err(WorldSocketError::SocketError(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "Received an incomplete client header",
-                )))
-            }
-            Ok(_) => {
-                let mut encryption = self.encryption.lock().await;
-                let client_header: ClientMessageHeader =
-                    encryption.decrypt_client_header(buf).into();
+        let raw_header = ClientMessage::read_raw_header(&mut *socket).await?;
+        // Only lock the encryption while decrypting, it is also needed to send packets
+        let client_header = self
+            .encryption
+            .lock()
+            .await
+            .decrypt_client_header(raw_header)
+            .into();
 
-                let bytes_to_read: usize = client_header.size as usize - 4; // Client opcode is u32
-                let mut buf_payload = [0_u8; 2048];
-                if bytes_to_read > 0 {
-                    socket
-                        .read_exact(&mut buf_payload[..bytes_to_read])
-                        .await
-                        .unwrap();
+        let client_message = ClientMessage::read_payload(&mut *socket, client_header).await?;
+        if let Some(capture) = &self.capture {
+            capture.record(
+                PacketDirection::ClientToServer,
+                client_message.header.opcode,
+                &client_message.payload,
+            );
+        }
 
-                    Ok(ClientMessage {
-                        header: client_header,
-                        payload: buf_payload[..bytes_to_read].to_vec(),
-                    })
-                } else {
-                    Ok(ClientMessage {
-                        header: client_header,
-                        payload: vec![],
-                    })
-                }
-            }
-            Err(e) => {
-                error!("Socket error, closing");
-                Err(WorldSocketError::SocketError(e))
-            }
+        Ok(client_message)
+    }
+
+    pub async fn close(&self) {
+        if let Err(e) = self.write_half.lock().await.shutdown().await {
+            warn!("error while closing world socket: {e}");
         }
     }
 
@@ -27,6 +27,25 @@ pub const ADDON_PUBLIC_KEY: [u8; 256] = [
     0x0D, 0x36, 0xEA, 0x01, 0xE0, 0xAA, 0x91, 0x20, 0x54, 0xF0, 0x72, 0xD8, 0x1E, 0xC7, 0x89, 0xD2,
 ];
 
+// Stored per account in the auth database, gates the chat commands
+#[derive(Clone, Copy, N, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum SecurityLevel {
+    Player = 0,
+    Moderator = 1,
+    GameMaster = 2,
+    Administrator = 3,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum ServerMessageType {
+    ShutdownTime = 1,
+    RestartTime = 2,
+    Custom = 3,
+    ShutdownCancelled = 4,
+    RestartCancelled = 5,
+}
+
 #[allow(dead_code)]
 #[derive(Clone, Copy, N)]
 pub enum Gender {
@@ -2178,7 +2197,7 @@ impl CreatureRank {
 pub const FRIENDLY_FACTION_TEMPLATE_ID: u32 = 35;
 
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(N, Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum LootType {
     None = 0, // In case of error
@@ -2195,7 +2214,7 @@ pub enum LootType {
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Copy, Clone)]
+#[derive(N, Debug, Copy, Clone)]
 #[repr(u8)]
 pub enum LootSlotType {
     Normal = 0,              // can be looted
@@ -2428,3 +2447,42 @@ pub enum AuraFlag {
     Cancelable = 0x10,      // confirmed on sunstrider: Client does not allow canceling those
     NotCancelable = 0x20,
 }
+
+// Blocks of client settings (UI, key bindings, macros...) the client uploads as zlib compressed
+// text, global to the account or specific to a character
+#[repr(u32)]
+#[derive(Clone, Copy, N, PartialEq, Eq, Debug)]
+pub enum AccountDataType {
+    GlobalConfigCache = 0,
+    PerCharacterConfigCache = 1,
+    GlobalBindingsCache = 2,
+    PerCharacterBindingsCache = 3,
+    GlobalMacrosCache = 4,
+    PerCharacterMacrosCache = 5,
+    PerCharacterLayoutCache = 6,
+    PerCharacterChatCache = 7,
+}
+
+impl AccountDataType {
+    pub fn is_per_character(&self) -> bool {
+        !matches!(
+            self,
+            AccountDataType::GlobalConfigCache
+                | AccountDataType::GlobalBindingsCache
+                | AccountDataType::GlobalMacrosCache
+        )
+    }
+}
+
+pub const MAX_ACCOUNT_DATA_SIZE: usize = 0xFFFF;
+
+// Actions a character must go through on the characters list before entering the world again
+#[bitflags]
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtLoginFlag {
+    Rename = 0x01,
+}
+
+// Makes the client ask for a new name before allowing to enter the world
+pub const CHARACTER_FLAG_RENAME: u32 = 0x00004000;